@@ -1,4 +1,336 @@
+#![deny(clippy::implicit_return)]
+#![allow(clippy::needless_return)]
+
+use clap::Parser;
+
+/// Renders a webray scene json to an image
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Args {
+    /// Path to the scene json
+    #[arg(required_unless_present = "list_adapters")]
+    scene: Option<String>,
+
+    /// Path of the rendered image
+    #[arg(short, long, default_value = "render.png")]
+    output: String,
+
+    /// Output image format, inferred from the output extension when omitted
+    #[arg(short, long, value_enum)]
+    format: Option<Format>,
+
+    /// Override the scene image width
+    #[arg(long)]
+    width: Option<u32>,
+
+    /// Override the scene image height
+    #[arg(long)]
+    height: Option<u32>,
+
+    /// Override the scene samples per pixel
+    #[arg(long)]
+    samples: Option<u32>,
+
+    /// Override the scene max bounces
+    #[arg(long)]
+    bounces: Option<u32>,
+
+    /// Override the milliseconds a single gpu submission should take at most
+    #[arg(long)]
+    dispatch_budget: Option<f32>,
+
+    /// Override the scene time limit in seconds, the samples taken by then are written
+    #[arg(long)]
+    time_limit: Option<f32>,
+
+    /// Override the scene tile size, either a size in pixels, `full` or `auto`
+    #[arg(long, value_parser = parse_tile_size)]
+    tile_size: Option<webray::WTileSize>,
+
+    /// Backend the scene is path traced on
+    #[arg(short, long, value_enum, default_value_t = Backend::Gpu)]
+    backend: Backend,
+
+    /// Use the gpu adapter whose name contains this text
+    #[arg(long)]
+    adapter: Option<String>,
+
+    /// Graphics api the gpu adapter is requested from
+    #[arg(long, value_enum)]
+    api: Option<Api>,
+
+    /// Prefer the low power gpu adapter over the high performance one
+    #[arg(long)]
+    low_power: bool,
+
+    /// Only accept the fallback (software) gpu adapter
+    #[arg(long)]
+    software: bool,
+
+    /// List the gpu adapters available to --api and exit
+    #[arg(long)]
+    list_adapters: bool,
+
+    /// Log level
+    #[arg(long, value_enum, default_value_t = LogLevel::Info)]
+    log_level: LogLevel,
+
+    /// Log the metrics report once the render is finished, requires the info log level
+    #[arg(long)]
+    metrics: bool,
+
+    /// Show a progress bar with the remaining time, best paired with --log-level warn
+    #[arg(long)]
+    progress: bool,
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum Backend {
+    Gpu,
+    Cpu,
+}
+
+impl From<Backend> for webray::Backend {
+    fn from(backend: Backend) -> Self {
+        return match backend {
+            Backend::Gpu => webray::Backend::Gpu,
+            Backend::Cpu => webray::Backend::Cpu,
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum Api {
+    Vulkan,
+    Metal,
+    Dx12,
+    Gl,
+}
+
+impl From<Api> for webray::GraphicsApi {
+    fn from(api: Api) -> Self {
+        return match api {
+            Api::Vulkan => webray::GraphicsApi::Vulkan,
+            Api::Metal => webray::GraphicsApi::Metal,
+            Api::Dx12 => webray::GraphicsApi::Dx12,
+            Api::Gl => webray::GraphicsApi::Gl,
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum Format {
+    Png,
+    Jpeg,
+    Bmp,
+    Exr,
+    Hdr,
+}
+
+impl From<Format> for webray::OutputFormat {
+    fn from(format: Format) -> Self {
+        return match format {
+            Format::Png => webray::OutputFormat::Png,
+            Format::Jpeg => webray::OutputFormat::Jpeg,
+            Format::Bmp => webray::OutputFormat::Bmp,
+            Format::Exr => webray::OutputFormat::Exr,
+            Format::Hdr => webray::OutputFormat::Hdr,
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for log::LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        return match level {
+            LogLevel::Off => log::LevelFilter::Off,
+            LogLevel::Error => log::LevelFilter::Error,
+            LogLevel::Warn => log::LevelFilter::Warn,
+            LogLevel::Info => log::LevelFilter::Info,
+            LogLevel::Debug => log::LevelFilter::Debug,
+            LogLevel::Trace => log::LevelFilter::Trace,
+        };
+    }
+}
+
+fn parse_tile_size(value: &str) -> Result<webray::WTileSize, String> {
+    if value == "full" {
+        return Ok(webray::WTileSize::Full);
+    }
+
+    if value == "auto" {
+        return Ok(webray::WTileSize::Auto);
+    }
+
+    return match value.parse::<u32>() {
+        Ok(size) if size > 0 => Ok(webray::WTileSize::Tile { size }),
+        _ => Err(format!(
+            "expected a tile size in pixels, `full` or `auto`, got {}",
+            value
+        )),
+    };
+}
+
 fn main() {
-    webray::initialize_kernel();
-    webray::render(include_str!("../../src/data/demo_02.scene.json").to_string());
-}
\ No newline at end of file
+    let args = Args::parse();
+
+    webray::initialize_kernel_with_log_level(args.log_level.into());
+
+    if args.list_adapters {
+        for adapter in webray::enumerate_adapters(args.api.map(Into::into)) {
+            println!("{}", adapter);
+        }
+
+        return;
+    }
+
+    if let Err(e) = run(&args) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run(args: &Args) -> Result<(), webray::WebRayError> {
+    // clap requires the scene unless only listing adapters
+    let path = args.scene.as_deref().unwrap_or_default();
+
+    let json = std::fs::read_to_string(path)
+        .map_err(|e| return webray::WebRayError::AssetLoad(format!("{}: {}", path, e)))?;
+
+    let mut scene = webray::WScene::from_json(&json)?;
+
+    let settings = &mut scene.render_settings;
+
+    if let Some(width) = args.width {
+        settings.width = width;
+    }
+
+    if let Some(height) = args.height {
+        settings.height = height;
+    }
+
+    if let Some(samples) = args.samples {
+        settings.samples = samples;
+    }
+
+    if let Some(bounces) = args.bounces {
+        settings.bounces = bounces;
+    }
+
+    if let Some(dispatch_budget) = args.dispatch_budget {
+        settings.dispatch_budget = dispatch_budget;
+    }
+
+    if let Some(time_limit) = args.time_limit {
+        settings.time_limit = Some(time_limit);
+    }
+
+    if let Some(tile_size) = &args.tile_size {
+        settings.tile_size = tile_size.clone();
+    }
+
+    let format = match args.format {
+        Some(format) => format.into(),
+        None => webray::OutputFormat::from_path(&args.output).unwrap_or(webray::OutputFormat::Png),
+    };
+
+    let adapter = webray::AdapterOptions {
+        name: args.adapter.clone(),
+        api: args.api.map(Into::into),
+        power_preference: if args.low_power {
+            webray::PowerPreference::LowPower
+        } else {
+            webray::PowerPreference::HighPerformance
+        },
+        force_fallback: args.software,
+    };
+
+    let (sender, receiver) = flume::unbounded();
+
+    // ends once render_scene drops the sender
+    let progress = args
+        .progress
+        .then(|| return std::thread::spawn(move || return show_progress(receiver)));
+
+    let result = webray::render_scene(
+        &scene,
+        args.backend.into(),
+        &adapter,
+        &args.output,
+        format,
+        args.metrics,
+        progress.is_some().then_some(sender),
+    );
+
+    if let Some(progress) = progress {
+        let _ = progress.join();
+    }
+
+    // a stopped render is still written, render_scene warns about it
+    result?;
+
+    return Ok(());
+}
+
+/// Width of the progress bar in characters
+const PROGRESS_BAR_WIDTH: usize = 30;
+
+/// Draws a progress bar on stderr from the finished tiles, the remaining time
+/// is extrapolated from the time the finished tiles took
+fn show_progress(receiver: flume::Receiver<webray::RenderEvent>) {
+    let start = std::time::Instant::now();
+    // cpu workers can finish tiles out of order
+    let mut done = 0;
+
+    for event in receiver.iter() {
+        match event {
+            webray::RenderEvent::TileFinished { tile, .. } => {
+                done += 1;
+                let fraction = done as f64 / tile.count as f64;
+
+                let elapsed = start.elapsed().as_secs_f64();
+                let remaining = (elapsed / fraction - elapsed).max(0.0) as u64;
+
+                let filled = (fraction * PROGRESS_BAR_WIDTH as f64) as usize;
+
+                eprint!(
+                    "\r[{}{}] {:>3}% tile {}/{} ETA {:02}:{:02}",
+                    "=".repeat(filled),
+                    " ".repeat(PROGRESS_BAR_WIDTH - filled),
+                    (fraction * 100.0) as u32,
+                    done,
+                    tile.count,
+                    remaining / 60,
+                    remaining % 60,
+                );
+            }
+            webray::RenderEvent::Finished {
+                status: webray::RenderStatus::Complete,
+            } => {
+                eprintln!(
+                    "\r[{}] 100% done in {:.1}s{}",
+                    "=".repeat(PROGRESS_BAR_WIDTH),
+                    start.elapsed().as_secs_f64(),
+                    " ".repeat(10),
+                );
+            }
+            webray::RenderEvent::Finished { status } => {
+                eprintln!(
+                    "\nStopped ({:?}) after {:.1}s",
+                    status,
+                    start.elapsed().as_secs_f64()
+                );
+            }
+            _ => {}
+        }
+    }
+}
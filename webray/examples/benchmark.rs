@@ -0,0 +1,71 @@
+#![deny(clippy::implicit_return)]
+#![allow(clippy::needless_return)]
+
+//! Measures the gpu kernel throughput in camera rays (pixel samples) per second for a few tile sizes
+//!
+//! ```sh
+//! cargo run --release -p webray --example benchmark -- src/data/demo_01.scene.json 16
+//! ```
+
+/// Timed renders per tile size, the fastest one is reported
+const RUNS: u32 = 3;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    let path = args
+        .get(1)
+        .map_or("src/data/demo_01.scene.json", |path| return path.as_str());
+
+    let json = std::fs::read_to_string(path).expect("scene json");
+    let mut scene = webray::WScene::from_json(&json).expect("valid scene");
+
+    if let Some(samples) = args.get(2) {
+        scene.render_settings.samples = samples.parse().expect("samples");
+    }
+
+    // progressive passes read back the image, which isn't part of the kernel's throughput
+    scene.render_settings.samples_per_pass = None;
+
+    let settings = &scene.render_settings;
+    let rays = settings.width as f64 * settings.height as f64 * settings.samples as f64;
+
+    println!(
+        "{}: {}x{} @ {} samples",
+        path, settings.width, settings.height, settings.samples
+    );
+
+    pollster::block_on(async {
+        let mut renderer = webray::Renderer::new(webray::Backend::Gpu)
+            .await
+            .expect("gpu renderer");
+
+        for tile_size in [
+            webray::WTileSize::Full,
+            webray::WTileSize::Tile { size: 256 },
+            webray::WTileSize::Tile { size: 64 },
+        ] {
+            scene.render_settings.tile_size = tile_size.clone();
+
+            // uploads the scene so the timed renders only path trace
+            renderer.render(&scene, None).await.expect("render");
+
+            let mut fastest = f64::MAX;
+
+            for _ in 0..RUNS {
+                let start = std::time::Instant::now();
+
+                renderer.render(&scene, None).await.expect("render");
+
+                fastest = fastest.min(start.elapsed().as_secs_f64());
+            }
+
+            println!(
+                "{:<15} {:8.3} s {:8.3} Mrays/s",
+                tile_size.to_string(),
+                fastest,
+                rays / fastest / 1e6
+            );
+        }
+    });
+}
@@ -0,0 +1,57 @@
+use core::fmt;
+
+use crate::scene::validation::Diagnostic;
+
+#[derive(Debug)]
+pub enum WebRayError {
+    /// Scene json could not be deserialized
+    SceneParse(String),
+    /// Scene is well formed but references invalid data
+    SceneValidation(String),
+    /// Scene failed validation, holds every diagnostic including warnings
+    InvalidScene(Vec<Diagnostic>),
+    /// Mesh or image referenced by the scene could not be loaded
+    AssetLoad(String),
+    /// No adapter satisfies the request
+    AdapterNotFound,
+    DeviceRequest(String),
+    /// Validation error or lost device reported while rendering
+    Gpu(String),
+    /// Data didn't fit the layout of its gpu buffer
+    BufferWrite(String),
+    BufferMap(String),
+    OutputWrite(String),
+}
+
+impl fmt::Display for WebRayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            WebRayError::SceneParse(e) => write!(f, "Failed to parse scene: {}", e),
+            WebRayError::SceneValidation(e) => write!(f, "Invalid scene: {}", e),
+            WebRayError::InvalidScene(diagnostics) => {
+                write!(f, "Invalid scene:")?;
+
+                for diagnostic in diagnostics {
+                    write!(f, "\n\t{}", diagnostic)?;
+                }
+
+                Ok(())
+            }
+            WebRayError::AssetLoad(e) => write!(f, "Failed to load asset: {}", e),
+            WebRayError::AdapterNotFound => write!(f, "No suitable GPU adapter found"),
+            WebRayError::DeviceRequest(e) => write!(f, "Failed to acquire GPU device: {}", e),
+            WebRayError::Gpu(e) => write!(f, "GPU error: {}", e),
+            WebRayError::BufferWrite(e) => write!(f, "Failed to encode buffer data: {}", e),
+            WebRayError::BufferMap(e) => write!(f, "Failed to read result buffer: {}", e),
+            WebRayError::OutputWrite(e) => write!(f, "Failed to write output: {}", e),
+        };
+    }
+}
+
+impl std::error::Error for WebRayError {}
+
+impl From<encase::internal::Error> for WebRayError {
+    fn from(error: encase::internal::Error) -> Self {
+        return WebRayError::BufferWrite(error.to_string());
+    }
+}
@@ -1,19 +1,115 @@
+use core::fmt;
+use std::sync::{Arc, Mutex};
+
+use super::error::WebRayError;
+
+/// Graphics api an adapter is driven through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphicsApi {
+    Vulkan,
+    Metal,
+    Dx12,
+    Gl,
+    WebGpu,
+}
+
+impl GraphicsApi {
+    fn backends(self) -> wgpu::Backends {
+        return match self {
+            GraphicsApi::Vulkan => wgpu::Backends::VULKAN,
+            GraphicsApi::Metal => wgpu::Backends::METAL,
+            GraphicsApi::Dx12 => wgpu::Backends::DX12,
+            GraphicsApi::Gl => wgpu::Backends::GL,
+            GraphicsApi::WebGpu => wgpu::Backends::BROWSER_WEBGPU,
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PowerPreference {
+    #[default]
+    HighPerformance,
+    LowPower,
+}
+
+/// How the gpu backend picks its adapter, the default asks
+/// wgpu for the high performance adapter on any api
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AdapterOptions {
+    /// Case insensitive part of the adapter name, native only
+    pub name: Option<String>,
+    pub api: Option<GraphicsApi>,
+    pub power_preference: PowerPreference,
+    /// Only accept the fallback (software) adapter
+    pub force_fallback: bool,
+}
+
+/// Adapter as reported by the driver
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdapterInfo {
+    pub name: String,
+    pub api: String,
+    pub device_type: String,
+}
+
+impl From<wgpu::AdapterInfo> for AdapterInfo {
+    fn from(info: wgpu::AdapterInfo) -> Self {
+        let device_type = match info.device_type {
+            wgpu::DeviceType::Other => "other",
+            wgpu::DeviceType::IntegratedGpu => "integrated gpu",
+            wgpu::DeviceType::DiscreteGpu => "discrete gpu",
+            wgpu::DeviceType::VirtualGpu => "virtual gpu",
+            wgpu::DeviceType::Cpu => "cpu",
+        };
+
+        return AdapterInfo {
+            name: info.name,
+            api: info.backend.to_str().to_string(),
+            device_type: device_type.to_string(),
+        };
+    }
+}
+
+impl fmt::Display for AdapterInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{} ({}, {})", self.name, self.api, self.device_type);
+    }
+}
+
 pub struct Gpu {
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
+    pub adapter: AdapterInfo,
+    /// first error the device reported since the last wait
+    error: Arc<Mutex<Option<String>>>,
 }
 
 impl Gpu {
-    pub async fn new() -> Self {
-        let instance = wgpu::Instance::default();
-
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptionsBase {
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                ..Default::default()
-            })
-            .await
-            .unwrap();
+    pub async fn new(options: &AdapterOptions) -> Result<Self, WebRayError> {
+        let instance = create_instance(options.api);
+
+        let adapter = match &options.name {
+            Some(name) => find_adapter(&instance, name, options.force_fallback),
+            None => {
+                let power_preference = match options.power_preference {
+                    PowerPreference::HighPerformance => wgpu::PowerPreference::HighPerformance,
+                    PowerPreference::LowPower => wgpu::PowerPreference::LowPower,
+                };
+
+                instance
+                    .request_adapter(&wgpu::RequestAdapterOptionsBase {
+                        power_preference,
+                        force_fallback_adapter: options.force_fallback,
+                        ..Default::default()
+                    })
+                    .await
+            }
+        }
+        .ok_or(WebRayError::AdapterNotFound)?;
+
+        let info = AdapterInfo::from(adapter.get_info());
+
+        log::info!("Adapter: {}", info);
 
         let (device, queue) = adapter
             .request_device(
@@ -25,12 +121,98 @@ impl Gpu {
                 None,
             )
             .await
-            .unwrap();
+            .map_err(|e| return WebRayError::DeviceRequest(e.to_string()))?;
+
+        let error = Arc::new(Mutex::new(None));
+
+        // reported by the next wait, later errors are usually caused by the first one
+        let slot = error.clone();
 
-        device.on_uncaptured_error(Box::new(|error| {
-            panic!("Aborting due to an error: {}", error);
+        device.on_uncaptured_error(Box::new(move |e| {
+            log::error!("{}", e);
+
+            let mut slot = slot.lock().unwrap();
+
+            if slot.is_none() {
+                *slot = Some(e.to_string());
+            }
         }));
 
-        return Gpu { device, queue };
+        return Ok(Gpu {
+            device,
+            queue,
+            adapter: info,
+            error,
+        });
+    }
+
+    /// Blocks until the submission, or everything submitted when none, is done and
+    /// returns the first error the device reported since the last wait
+    pub fn wait(&self, submission: Option<wgpu::SubmissionIndex>) -> Result<(), WebRayError> {
+        let maintain = match submission {
+            Some(index) => wgpu::Maintain::WaitForSubmissionIndex(index),
+            None => wgpu::Maintain::Wait,
+        };
+
+        let result = self.device.poll(maintain);
+
+        if let Some(error) = self.error.lock().unwrap().take() {
+            return Err(WebRayError::Gpu(error));
+        }
+
+        // nothing is submitted past what's waited on, work left in flight means the wait gave up
+        if !result.is_queue_empty() {
+            return Err(WebRayError::Gpu(
+                "Timed out waiting for the device".to_string(),
+            ));
+        }
+
+        return Ok(());
     }
 }
+
+/// Lists the adapters available to the given api, or all of them
+#[cfg(not(target_arch = "wasm32"))]
+pub fn enumerate_adapters(api: Option<GraphicsApi>) -> Vec<AdapterInfo> {
+    let backends = api.map_or(wgpu::Backends::all(), GraphicsApi::backends);
+
+    return create_instance(api)
+        .enumerate_adapters(backends)
+        .into_iter()
+        .map(|adapter| return AdapterInfo::from(adapter.get_info()))
+        .collect();
+}
+
+fn create_instance(api: Option<GraphicsApi>) -> wgpu::Instance {
+    return match api {
+        // default lets wgpu pick between webgpu and webgl in the browser
+        None => wgpu::Instance::default(),
+        Some(api) => wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: api.backends(),
+            ..Default::default()
+        }),
+    };
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn find_adapter(instance: &wgpu::Instance, name: &str, software: bool) -> Option<wgpu::Adapter> {
+    let name = name.to_lowercase();
+
+    return instance
+        .enumerate_adapters(wgpu::Backends::all())
+        .into_iter()
+        .find(|adapter| {
+            let info = adapter.get_info();
+
+            return info.name.to_lowercase().contains(&name)
+                && (!software || info.device_type == wgpu::DeviceType::Cpu);
+        });
+}
+
+#[cfg(target_arch = "wasm32")]
+fn find_adapter(_instance: &wgpu::Instance, name: &str, _software: bool) -> Option<wgpu::Adapter> {
+    // browsers don't enumerate adapters
+    log::error!("Adapter {} can't be selected by name in the browser", name);
+
+    return None;
+}
@@ -4,8 +4,23 @@
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
-use scene::types::WScene;
-use utils::metrics::Metrics;
+pub use crate::{
+    core::{
+        error::WebRayError,
+        gpu::{AdapterInfo, AdapterOptions, GraphicsApi, PowerPreference},
+    },
+    renderer::{
+        cancel::{CancelToken, RenderStatus},
+        Backend, RenderEvent, RenderOutput, Renderer, Tile,
+    },
+    scene::{
+        types::{WScene, WTileSize},
+        validation::{Diagnostic, Severity},
+    },
+};
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::{core::gpu::enumerate_adapters, output::native::OutputFormat};
 
 mod core;
 mod output;
@@ -19,13 +34,20 @@ pub fn initialize_kernel() {
         if #[cfg(target_arch = "wasm32")] {
             std::panic::set_hook(Box::new(console_error_panic_hook::hook));
             console_log::init_with_level(log::Level::Info).unwrap();
+
+            log::info!("WebRay Loaded");
         } else {
-            env_logger::builder()
-                .filter_level(log::LevelFilter::Info)
-                .format_timestamp_millis()
-                .init();
+            initialize_kernel_with_log_level(log::LevelFilter::Info);
         }
     }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn initialize_kernel_with_log_level(level: log::LevelFilter) {
+    env_logger::builder()
+        .filter_level(level)
+        .format_timestamp_millis()
+        .init();
 
     log::info!("WebRay Loaded");
 }
@@ -33,66 +55,290 @@ pub fn initialize_kernel() {
 #[cfg(target_arch = "wasm32")]
 #[wasm_bindgen]
 pub fn render(value: JsValue) -> js_sys::Promise {
-    let scene = serde_wasm_bindgen::from_value::<WScene>(value).unwrap();
-
     // not sure if the move is required here
     return wasm_bindgen_futures::future_to_promise(async move {
-        run_internal(scene).await;
-        return Ok(JsValue::TRUE);
+        let scene = serde_wasm_bindgen::from_value::<WScene>(value)
+            .map_err(|e| return WebRayError::SceneParse(e.to_string()))
+            .map_err(to_js_error)?;
+
+        let mut renderer = Renderer::new(Backend::Gpu).await.map_err(to_js_error)?;
+
+        let status = run_internal(&mut renderer, scene, None)
+            .await
+            .map_err(to_js_error)?;
+
+        return Ok(status_to_js(status));
     });
 }
 
+/// Renderer exposed to js as `Renderer`, keeps the device and kernel alive
+/// between renders so the editor only pays for changed buffers
 #[cfg(target_arch = "wasm32")]
-#[wasm_bindgen]
-pub fn parse_scene(value: JsValue) {
-    use scene::types::WScene;
+#[wasm_bindgen(js_name = Renderer)]
+pub struct WasmRenderer {
+    /// taken out for the duration of a render as the future has to be 'static
+    renderer: std::rc::Rc<std::cell::RefCell<Option<Renderer>>>,
+    /// kept outside the renderer so a running render can be cancelled
+    cancel: CancelToken,
+}
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen(js_class = Renderer)]
+impl WasmRenderer {
+    /// Resolves to a `Renderer` once the device is acquired
+    pub fn create() -> js_sys::Promise {
+        return wasm_bindgen_futures::future_to_promise(async move {
+            let renderer = Renderer::new(Backend::Gpu).await.map_err(to_js_error)?;
+
+            return Ok(WasmRenderer {
+                cancel: renderer.cancel_token(),
+                renderer: std::rc::Rc::new(std::cell::RefCell::new(Some(renderer))),
+            }
+            .into());
+        });
+    }
+
+    /// on_event is called with every render event, see `event_to_js` for their shape.
+    /// Resolves to the status of the render, see `status_to_js`
+    pub fn render(&self, value: JsValue, on_event: Option<js_sys::Function>) -> js_sys::Promise {
+        let cell = self.renderer.clone();
+
+        return wasm_bindgen_futures::future_to_promise(async move {
+            let scene = serde_wasm_bindgen::from_value::<WScene>(value)
+                .map_err(|e| return WebRayError::SceneParse(e.to_string()))
+                .map_err(to_js_error)?;
+
+            let mut renderer = cell
+                .borrow_mut()
+                .take()
+                .ok_or_else(|| return JsValue::from(js_sys::Error::new("Render in progress")))?;
+
+            let result = run_internal(&mut renderer, scene, on_event).await;
+
+            cell.borrow_mut().replace(renderer);
+
+            let status = result.map_err(to_js_error)?;
 
-    let scene = serde_wasm_bindgen::from_value::<WScene>(value).unwrap();
+            return Ok(status_to_js(status));
+        });
+    }
+
+    /// Stops the running render before its next tile, the render then resolves
+    /// to `cancelled` with the image of the samples taken so far
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn parse_scene(value: JsValue) -> Result<(), JsValue> {
+    let scene = serde_wasm_bindgen::from_value::<WScene>(value)
+        .map_err(|e| return to_js_error(WebRayError::SceneParse(e.to_string())))?;
 
     log::info!("{}", scene);
+
+    return Ok(());
 }
 
-#[cfg(not(target_arch = "wasm32"))]
-pub fn render(json: String) {
-    let scene = serde_json::from_str::<WScene>(&json).unwrap();
+/// Validates the scene without rendering, resolves to the list of diagnostics
+/// so the editor can highlight the offending fields
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn validate_scene(value: JsValue) -> Result<JsValue, JsValue> {
+    let scene = serde_wasm_bindgen::from_value::<WScene>(value)
+        .map_err(|e| return to_js_error(WebRayError::SceneParse(e.to_string())))?;
 
-    pollster::block_on(run_internal(scene));
+    return serde_wasm_bindgen::to_value(&scene.validate())
+        .map_err(|e| return JsValue::from(js_sys::Error::new(&e.to_string())));
 }
 
-async fn run_internal(scene: WScene) {
-    let mut metrics: Option<Metrics>;
+#[cfg(target_arch = "wasm32")]
+fn to_js_error(error: WebRayError) -> JsValue {
+    log::error!("{}", error);
 
-    cfg_if::cfg_if! {
-        if #[cfg(target_arch = "wasm32")] {
-            metrics = None;
-        } else {
-            metrics = Some(Metrics::new());
+    let js_error = js_sys::Error::new(&error.to_string());
+
+    // lets the editor highlight fields of a render rejected by validation
+    if let WebRayError::InvalidScene(diagnostics) = &error {
+        if let Ok(value) = serde_wasm_bindgen::to_value(diagnostics) {
+            let _ = js_sys::Reflect::set(&js_error, &JsValue::from_str("diagnostics"), &value);
         }
-    };
+    }
+
+    return js_error.into();
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn render(json: String) -> Result<(), WebRayError> {
+    let scene = WScene::from_json(&json)?;
+
+    render_scene(
+        &scene,
+        Backend::Gpu,
+        &AdapterOptions::default(),
+        "render.png",
+        OutputFormat::Png,
+        true,
+        None,
+    )?;
 
-    if let Ok(buffer) = renderer::render(
-        &scene.get_kernel_config(),
-        &scene.get_kernel_scene(),
-        &mut metrics,
-    )
-    .await
-    {
-        cfg_if::cfg_if! {
-            if #[cfg(target_arch = "wasm32")] {
-                output::wasm::output_image(buffer, glam::uvec2(scene.render_settings.width, scene.render_settings.height));
-            } else {
-                output::native::output_image(buffer, glam::uvec2(scene.render_settings.width, scene.render_settings.height), "render.png");
+    return Ok(());
+}
+
+/// Renders the scene and writes the image to the given path,
+/// the render events are sent to events while rendering.
+/// A render stopped by its time limit is still written
+#[cfg(not(target_arch = "wasm32"))]
+pub fn render_scene(
+    scene: &WScene,
+    backend: Backend,
+    adapter: &AdapterOptions,
+    path: &str,
+    format: OutputFormat,
+    log_metrics: bool,
+    events: Option<flume::Sender<RenderEvent>>,
+) -> Result<RenderStatus, WebRayError> {
+    let dimensions = glam::uvec2(scene.render_settings.width, scene.render_settings.height);
+
+    return pollster::block_on(async {
+        let mut renderer = Renderer::with_adapter(backend, adapter).await?;
+
+        // a dropped receiver only means no one is listening anymore
+        let mut send_event = |event: RenderEvent| {
+            if let Some(sender) = &events {
+                let _ = sender.send(event);
             }
+        };
+
+        let on_event = events
+            .is_some()
+            .then_some(&mut send_event as &mut dyn FnMut(RenderEvent));
+
+        let output = renderer.render(scene, on_event).await?;
+        let status = output.status;
+
+        if status != RenderStatus::Complete {
+            log::warn!(
+                "Render stopped early ({:?}), writing the partial image",
+                status
+            );
         }
 
-        if let Some(m) = metrics.as_mut() {
+        output::native::output_image(output, dimensions, path, format)?;
+
+        if let Some(m) = renderer.metrics.as_mut() {
             m.capture_output_write();
+
+            m.capture_total();
+
+            if log_metrics {
+                m.log();
+            }
+        }
+
+        return Ok(status);
+    });
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn run_internal(
+    renderer: &mut Renderer,
+    scene: WScene,
+    on_event: Option<js_sys::Function>,
+) -> Result<RenderStatus, WebRayError> {
+    let dimensions = glam::uvec2(scene.render_settings.width, scene.render_settings.height);
+
+    let mut handle_event = |event: RenderEvent| {
+        // editor shows the image refining with every progressive pass
+        if let RenderEvent::PassFinished { image, .. } = &event {
+            // a failed preview shouldn't abort the render
+            if let Err(e) = output::wasm::output_image(image.clone(), dimensions) {
+                log::warn!("{}", e);
+            }
+        }
+
+        if let Some(callback) = &on_event {
+            if let Err(e) = callback.call1(&JsValue::NULL, &event_to_js(event)) {
+                log::warn!("Render event callback failed: {:?}", e);
+            }
         }
     };
 
-    if let Some(m) = metrics.as_mut() {
-        m.capture_total();
+    let output = renderer.render(&scene, Some(&mut handle_event)).await?;
 
-        m.log();
+    output::wasm::output_image(output.image, dimensions)?;
+
+    return Ok(output.status);
+}
+
+/// `complete`, `cancelled` or `timed_out`
+#[cfg(target_arch = "wasm32")]
+fn status_to_js(status: RenderStatus) -> JsValue {
+    return JsValue::from_str(match status {
+        RenderStatus::Complete => "complete",
+        RenderStatus::Cancelled => "cancelled",
+        RenderStatus::TimedOut => "timed_out",
+    });
+}
+
+/// Converts the event to `{ type, tile?, image?, samples?, total_samples?, status? }`, type is one of
+/// `tile_started`, `tile_finished`, `pass_finished` and `finished`. Images are rgba8
+/// Uint8ClampedArrays, the size of the tile or the whole image
+#[cfg(target_arch = "wasm32")]
+fn event_to_js(event: RenderEvent) -> JsValue {
+    let object = js_sys::Object::new();
+
+    let set = |key: &str, value: JsValue| {
+        let _ = js_sys::Reflect::set(&object, &JsValue::from_str(key), &value);
+    };
+
+    let tile_to_js = |tile: Tile| {
+        let tile_object = js_sys::Object::new();
+
+        for (key, value) in [
+            ("index", tile.index),
+            ("count", tile.count),
+            ("x", tile.x),
+            ("y", tile.y),
+            ("width", tile.width),
+            ("height", tile.height),
+        ] {
+            let _ = js_sys::Reflect::set(&tile_object, &JsValue::from_str(key), &value.into());
+        }
+
+        return JsValue::from(tile_object);
+    };
+
+    let image_to_js = |image: &[u8]| {
+        return JsValue::from(js_sys::Uint8ClampedArray::from(image));
+    };
+
+    match event {
+        RenderEvent::TileStarted { tile } => {
+            set("type", "tile_started".into());
+            set("tile", tile_to_js(tile));
+        }
+        RenderEvent::TileFinished { tile, image } => {
+            set("type", "tile_finished".into());
+            set("tile", tile_to_js(tile));
+            set("image", image_to_js(&image));
+        }
+        RenderEvent::PassFinished {
+            samples,
+            total_samples,
+            image,
+        } => {
+            set("type", "pass_finished".into());
+            set("samples", samples.into());
+            set("total_samples", total_samples.into());
+            set("image", image_to_js(&image));
+        }
+        RenderEvent::Finished { status } => {
+            set("type", "finished".into());
+            set("status", status_to_js(status));
+        }
     }
+
+    return object.into();
 }
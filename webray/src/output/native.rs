@@ -1,12 +1,110 @@
-pub fn output_image(image_data: Vec<u8>, dimensions: glam::UVec2, path: &str) {
-    match image::save_buffer(
-        path,
-        &image_data,
-        dimensions.x,
-        dimensions.y,
-        image::ColorType::Rgba8,
-    ) {
-        Ok(_) => log::info!("Output saved at path: {}", path),
-        Err(e) => log::error!("{:?}", e),
+use std::path::Path;
+
+use crate::{
+    core::error::WebRayError,
+    renderer::{aov::Aov, RenderOutput},
+};
+
+/// Image formats the native output can be encoded as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Png,
+    Jpeg,
+    Bmp,
+    /// OpenEXR, linear radiance before exposure and tone mapping
+    Exr,
+    /// Radiance rgbe, linear radiance before exposure and tone mapping
+    Hdr,
+}
+
+impl OutputFormat {
+    /// Format implied by the extension of the output path
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+
+        return match extension.as_str() {
+            "png" => Some(OutputFormat::Png),
+            "jpg" | "jpeg" => Some(OutputFormat::Jpeg),
+            "bmp" => Some(OutputFormat::Bmp),
+            "exr" => Some(OutputFormat::Exr),
+            "hdr" => Some(OutputFormat::Hdr),
+            _ => None,
+        };
+    }
+}
+
+/// Saves the render at path and every aov as an OpenEXR next to it, named after the aov
+pub fn output_image(
+    output: RenderOutput,
+    dimensions: glam::UVec2,
+    path: &str,
+    format: OutputFormat,
+) -> Result<(), WebRayError> {
+    let RenderOutput {
+        hdr, image, aovs, ..
+    } = output;
+
+    let image = match format {
+        OutputFormat::Png | OutputFormat::Jpeg | OutputFormat::Bmp => {
+            image::RgbaImage::from_raw(dimensions.x, dimensions.y, image)
+                .map(image::DynamicImage::ImageRgba8)
+        }
+        OutputFormat::Exr | OutputFormat::Hdr => {
+            image::Rgba32FImage::from_raw(dimensions.x, dimensions.y, hdr)
+                .map(image::DynamicImage::ImageRgba32F)
+        }
+    }
+    .ok_or_else(dimensions_mismatch)?;
+
+    let result = match format {
+        OutputFormat::Png => image.save_with_format(path, image::ImageFormat::Png),
+        // jpeg has no alpha channel
+        OutputFormat::Jpeg => image
+            .into_rgb8()
+            .save_with_format(path, image::ImageFormat::Jpeg),
+        OutputFormat::Bmp => image.save_with_format(path, image::ImageFormat::Bmp),
+        OutputFormat::Exr => image.save_with_format(path, image::ImageFormat::OpenExr),
+        OutputFormat::Hdr => save_hdr(image.into_rgb32f(), path),
+    };
+
+    result.map_err(|e| return WebRayError::OutputWrite(format!("{}: {}", path, e)))?;
+
+    log::info!("Output saved at path: {}", path);
+
+    for aov in aovs {
+        let aov_path = aov_path(path, aov.aov);
+
+        image::Rgba32FImage::from_raw(dimensions.x, dimensions.y, aov.data)
+            .ok_or_else(dimensions_mismatch)?
+            .save_with_format(&aov_path, image::ImageFormat::OpenExr)
+            .map_err(|e| return WebRayError::OutputWrite(format!("{}: {}", aov_path, e)))?;
+
+        log::info!("Aov saved at path: {}", aov_path);
     }
+
+    return Ok(());
+}
+
+/// render.png has its albedo saved at render.albedo.exr
+fn aov_path(path: &str, aov: Aov) -> String {
+    return Path::new(path)
+        .with_extension(format!("{}.exr", aov.name()))
+        .to_string_lossy()
+        .into_owned();
+}
+
+fn dimensions_mismatch() -> WebRayError {
+    return WebRayError::OutputWrite("image data doesn't match the dimensions".to_string());
+}
+
+/// Radiance encoding isn't reachable through `save_with_format` and has no alpha channel
+fn save_hdr(image: image::Rgb32FImage, path: &str) -> image::ImageResult<()> {
+    let writer = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let pixels: Vec<image::Rgb<f32>> = image.pixels().copied().collect();
+
+    return image::codecs::hdr::HdrEncoder::new(writer).encode(
+        &pixels,
+        image.width() as usize,
+        image.height() as usize,
+    );
 }
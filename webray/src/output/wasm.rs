@@ -1,43 +1,49 @@
 use wasm_bindgen::JsCast;
 
-pub fn output_image(image_data: Vec<u8>, dimensions: glam::UVec2) {
-    let document = web_sys::window().unwrap().document().unwrap();
+use crate::core::error::WebRayError;
+
+pub fn output_image(image_data: Vec<u8>, dimensions: glam::UVec2) -> Result<(), WebRayError> {
+    let document = web_sys::window()
+        .and_then(|window| return window.document())
+        .ok_or_else(|| return WebRayError::OutputWrite("document not available".to_string()))?;
 
     let canvas = if let Some(found_canvas) = document.get_element_by_id("staging-canvas") {
         match found_canvas.dyn_into::<web_sys::HtmlCanvasElement>() {
             Ok(canvas_as_canvas) => canvas_as_canvas,
             Err(e) => {
                 e.remove();
-                create_staging_canvas(&document)
+                create_staging_canvas(&document)?
             }
         }
     } else {
-        create_staging_canvas(&document)
+        create_staging_canvas(&document)?
     };
 
     let image_dimension_strings = (dimensions.x.to_string(), dimensions.y.to_string());
 
     canvas
         .set_attribute("width", image_dimension_strings.0.as_str())
-        .unwrap();
+        .map_err(js_error)?;
     canvas
         .set_attribute("height", image_dimension_strings.1.as_str())
-        .unwrap();
+        .map_err(js_error)?;
 
     let context = canvas
         .get_context("2d")
-        .unwrap()
-        .unwrap()
+        .map_err(js_error)?
+        .ok_or_else(|| return WebRayError::OutputWrite("2d context not available".to_string()))?
         .dyn_into::<web_sys::CanvasRenderingContext2d>()
-        .unwrap();
+        .map_err(|e| return js_error(e.into()))?;
 
     let image_data = web_sys::ImageData::new_with_u8_clamped_array(
         wasm_bindgen::Clamped(&image_data),
         dimensions.x,
     )
-    .unwrap();
+    .map_err(js_error)?;
 
-    context.put_image_data(&image_data, 0.0, 0.0).unwrap();
+    context
+        .put_image_data(&image_data, 0.0, 0.0)
+        .map_err(js_error)?;
 
     let image_element =
         if let Some(found_image_element) = document.get_element_by_id("output-image-target") {
@@ -45,47 +51,64 @@ pub fn output_image(image_data: Vec<u8>, dimensions: glam::UVec2) {
                 Ok(e) => e,
                 Err(e) => {
                     e.remove();
-                    create_output_image_element(&document)
+                    create_output_image_element(&document)?
                 }
             }
         } else {
-            create_output_image_element(&document)
+            create_output_image_element(&document)?
         };
 
-    let data_url = canvas.to_data_url().unwrap();
+    let data_url = canvas.to_data_url().map_err(js_error)?;
     image_element.set_src(&data_url);
 
     log::info!("Image displayed");
+
+    return Ok(());
 }
 
-fn create_staging_canvas(document: &web_sys::Document) -> web_sys::HtmlCanvasElement {
-    let body = document.body().expect("Failed to get document body.");
+fn js_error(e: wasm_bindgen::JsValue) -> WebRayError {
+    return WebRayError::OutputWrite(format!("{:?}", e));
+}
+
+fn create_staging_canvas(
+    document: &web_sys::Document,
+) -> Result<web_sys::HtmlCanvasElement, WebRayError> {
+    let body = document.body().ok_or_else(|| {
+        return WebRayError::OutputWrite("document body not available".to_string());
+    })?;
     let new_canvas = document
         .create_element("canvas")
-        .expect("Failed to create staging canvas.")
+        .map_err(js_error)?
         .dyn_into::<web_sys::HtmlCanvasElement>()
-        .unwrap();
+        .map_err(|e| return js_error(e.into()))?;
 
     // We don't want to show the canvas, we just want it to exist in the background.
-    new_canvas.set_attribute("hidden", "true").unwrap();
-    new_canvas.set_attribute("background-color", "red").unwrap();
-    body.append_child(&new_canvas).unwrap();
-
-    return new_canvas;
+    new_canvas
+        .set_attribute("hidden", "true")
+        .map_err(js_error)?;
+    new_canvas
+        .set_attribute("background-color", "red")
+        .map_err(js_error)?;
+    body.append_child(&new_canvas).map_err(js_error)?;
+
+    return Ok(new_canvas);
 }
 
-fn create_output_image_element(document: &web_sys::Document) -> web_sys::HtmlImageElement {
-    let body = document.body().expect("Failed to get document body.");
+fn create_output_image_element(
+    document: &web_sys::Document,
+) -> Result<web_sys::HtmlImageElement, WebRayError> {
+    let body = document.body().ok_or_else(|| {
+        return WebRayError::OutputWrite("document body not available".to_string());
+    })?;
     let new_image = document
         .create_element("img")
-        .expect("Failed to create output image element.")
+        .map_err(js_error)?
         .dyn_into::<web_sys::HtmlImageElement>()
-        .unwrap();
+        .map_err(|e| return js_error(e.into()))?;
 
     new_image.set_id("output-image-target");
 
-    body.append_child(&new_image)
-        .expect("Failed to append output image target to document body.");
+    body.append_child(&new_image).map_err(js_error)?;
 
-    return new_image;
+    return Ok(new_image);
 }
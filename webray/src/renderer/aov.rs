@@ -0,0 +1,106 @@
+/// Layers of the aov texture, the kernel packs every aov into these
+/// - 0: albedo rgb
+/// - 1: world normal xyz
+/// - 2: x depth, y object id, z material id
+pub const AOV_LAYERS: u32 = 3;
+
+/// Auxiliary image taken from the first hit of every pixel's center ray
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aov {
+    /// surface color at the first hit, the background clamped to 0..1 where nothing was hit
+    Albedo,
+    /// world space normal facing the camera, zero where nothing was hit
+    Normal,
+    /// distance from the camera, zero where nothing was hit
+    Depth,
+    /// id of the scene object, -1 where nothing was hit
+    ObjectId,
+    /// id of the scene material, -1 where nothing was hit
+    MaterialId,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 5] = [
+        Aov::Albedo,
+        Aov::Normal,
+        Aov::Depth,
+        Aov::ObjectId,
+        Aov::MaterialId,
+    ];
+
+    /// Name used for the output files
+    pub fn name(self) -> &'static str {
+        return match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+        };
+    }
+
+    /// Layer of the aov texture this aov is packed in
+    pub fn layer(self) -> u32 {
+        return match self {
+            Aov::Albedo => 0,
+            Aov::Normal => 1,
+            Aov::Depth | Aov::ObjectId | Aov::MaterialId => 2,
+        };
+    }
+
+    /// Config mask with a bit per aov, the kernel skips the aovs when no bit is set
+    pub fn mask(aovs: &[Aov]) -> u32 {
+        return aovs
+            .iter()
+            .fold(0, |mask, aov| return mask | (1 << *aov as u32));
+    }
+
+    /// Unpacks this aov from its rgba32 layer, single channel aovs are repeated across rgb
+    pub fn extract(self, layer: &[f32]) -> Vec<f32> {
+        let channel = match self {
+            Aov::Albedo | Aov::Normal => return layer.to_vec(),
+            Aov::Depth => 0,
+            Aov::ObjectId => 1,
+            Aov::MaterialId => 2,
+        };
+
+        let mut image = Vec::with_capacity(layer.len());
+
+        for pixel in layer.chunks_exact(4) {
+            image.extend_from_slice(&[pixel[channel], pixel[channel], pixel[channel], 1.0]);
+        }
+
+        return image;
+    }
+}
+
+/// Aov read back from a render, rgba32 per pixel
+pub struct AovImage {
+    pub aov: Aov,
+    pub data: Vec<f32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packed_aovs_are_unpacked_per_channel() {
+        let layer = [2.0, 5.0, 7.0, 1.0, 0.0, -1.0, -1.0, 1.0];
+
+        assert_eq!(
+            Aov::Depth.extract(&layer),
+            vec![2.0, 2.0, 2.0, 1.0, 0.0, 0.0, 0.0, 1.0]
+        );
+        assert_eq!(
+            Aov::ObjectId.extract(&layer),
+            vec![5.0, 5.0, 5.0, 1.0, -1.0, -1.0, -1.0, 1.0]
+        );
+        assert_eq!(
+            Aov::MaterialId.extract(&layer),
+            vec![7.0, 7.0, 7.0, 1.0, -1.0, -1.0, -1.0, 1.0]
+        );
+        assert_eq!(Aov::Normal.extract(&layer), layer.to_vec());
+        assert_eq!(Aov::mask(&[Aov::Albedo, Aov::MaterialId]), 0b10001);
+    }
+}
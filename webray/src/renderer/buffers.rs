@@ -2,11 +2,13 @@ use std::mem::size_of;
 
 use wgpu::util::DeviceExt;
 
-use crate::core::gpu::Gpu;
+use crate::core::{error::WebRayError, gpu::Gpu};
 
 use super::{
+    aov::AOV_LAYERS,
     config::{ExecutionContext, SystemConfig},
     scene::KernelScene,
+    texture::KTexture,
 };
 
 /// Container for kernel buffers
@@ -15,32 +17,211 @@ pub struct KernelBuffers {
     /// output from render texture is copied to this
     /// so that it can be mapped and read
     pub result: wgpu::Buffer,
-    /// storage texture where the rendered image is
+    /// storage texture where the linear rendered image is
     /// written in the compute shader
     pub render: wgpu::Texture,
+    /// running sum of all samples from previous passes
+    pub accumulation_in: wgpu::Texture,
+    /// running sum including the current pass, copied to
+    /// accumulation_in once the pass is complete
+    pub accumulation_out: wgpu::Texture,
+    /// one layer per packed aov, see AOV_LAYERS.
+    /// 1x1 when the render has no aovs
+    pub aovs: wgpu::Texture,
+    /// written by a denoise iteration and copied back to render,
+    /// sized with the aov texture as denoising needs the aovs
+    pub denoise: wgpu::Texture,
 
     // User buffers
     pub config: wgpu::Buffer,
-    pub spheres: wgpu::Buffer,
-    pub diffuse_mats: wgpu::Buffer,
-    pub metal_mats: wgpu::Buffer,
-    pub dielectric_mats: wgpu::Buffer,
+    pub primitives: wgpu::Buffer,
+    pub vertices: wgpu::Buffer,
+    pub triangles: wgpu::Buffer,
+    pub bvh_nodes: wgpu::Buffer,
+    pub instances: wgpu::Buffer,
+    pub materials: wgpu::Buffer,
+    pub lights: wgpu::Buffer,
+    /// equirectangular background, 1x1 placeholder when the scene has none
+    pub environment_map: wgpu::Texture,
+    /// image textures of the materials, 1x1 placeholder when the scene has none
+    pub texture_atlas: wgpu::Texture,
 
     // Execution Context buffers
     pub execution_context: wgpu::Buffer,
 }
 
 impl KernelBuffers {
-    pub fn new(gpu: &Gpu, system_config: &SystemConfig, scene: &KernelScene) -> Self {
-        // &arr and &arr[..] are different, second one is a slice and what we need
-        let result_buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+    pub fn new(
+        gpu: &Gpu,
+        system_config: &SystemConfig,
+        scene: &KernelScene,
+    ) -> Result<Self, WebRayError> {
+        let placeholder = KTexture::placeholder();
+
+        return Ok(KernelBuffers {
+            result: Self::result_buffer(gpu, system_config),
+            render: Self::render_texture(gpu, system_config),
+            accumulation_in: Self::accumulation_texture(
+                gpu,
+                system_config,
+                "Accumulation in texture",
+                wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            ),
+            accumulation_out: Self::accumulation_texture(
+                gpu,
+                system_config,
+                "Accumulation out texture",
+                wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+            ),
+            aovs: Self::aov_texture(gpu, system_config),
+            denoise: Self::denoise_texture(gpu, system_config),
+            config: Self::config_buffer(gpu, system_config)?,
+            primitives: Self::storage_buffer(
+                gpu,
+                "Scene primitives buffer",
+                &scene.primitives_as_wgsl_bytes()?[..],
+            ),
+            vertices: Self::storage_buffer(
+                gpu,
+                "Scene vertices buffer",
+                &scene.vertices_as_wgsl_bytes()?[..],
+            ),
+            triangles: Self::storage_buffer(
+                gpu,
+                "Scene triangles buffer",
+                &scene.triangles_as_wgsl_bytes()?[..],
+            ),
+            bvh_nodes: Self::storage_buffer(
+                gpu,
+                "Scene BVH nodes buffer",
+                &scene.bvh_nodes_as_wgsl_bytes()?[..],
+            ),
+            instances: Self::storage_buffer(
+                gpu,
+                "Scene instances buffer",
+                &scene.instances_as_wgsl_bytes()?[..],
+            ),
+            materials: Self::storage_buffer(
+                gpu,
+                "Scene materials buffer",
+                &scene.materials_as_wgsl_bytes()?[..],
+            ),
+            lights: Self::storage_buffer(
+                gpu,
+                "Scene lights buffer",
+                &scene.lights_as_wgsl_bytes()?[..],
+            ),
+            environment_map: Self::sampled_texture(
+                gpu,
+                "Scene environment map texture",
+                scene.environment_map().unwrap_or(&placeholder),
+            ),
+            texture_atlas: Self::sampled_texture(
+                gpu,
+                "Scene texture atlas",
+                scene.texture_atlas().unwrap_or(&placeholder),
+            ),
+            execution_context: Self::execution_context_buffer(gpu),
+        });
+    }
+
+    /// Recreates the image sized buffers and the aov texture, scene buffers are left untouched
+    pub fn resize(&mut self, gpu: &Gpu, system_config: &SystemConfig) {
+        self.result = Self::result_buffer(gpu, system_config);
+        self.render = Self::render_texture(gpu, system_config);
+        self.accumulation_in = Self::accumulation_texture(
+            gpu,
+            system_config,
+            "Accumulation in texture",
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        );
+        self.accumulation_out = Self::accumulation_texture(
+            gpu,
+            system_config,
+            "Accumulation out texture",
+            wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+        );
+        self.aovs = Self::aov_texture(gpu, system_config);
+        self.denoise = Self::denoise_texture(gpu, system_config);
+    }
+
+    /// Replaces the geometry, instance, material, light and texture buffers with the given scene
+    pub fn upload_scene(&mut self, gpu: &Gpu, scene: &KernelScene) -> Result<(), WebRayError> {
+        let placeholder = KTexture::placeholder();
+
+        self.primitives = Self::storage_buffer(
+            gpu,
+            "Scene primitives buffer",
+            &scene.primitives_as_wgsl_bytes()?[..],
+        );
+        self.vertices = Self::storage_buffer(
+            gpu,
+            "Scene vertices buffer",
+            &scene.vertices_as_wgsl_bytes()?[..],
+        );
+        self.triangles = Self::storage_buffer(
+            gpu,
+            "Scene triangles buffer",
+            &scene.triangles_as_wgsl_bytes()?[..],
+        );
+        self.bvh_nodes = Self::storage_buffer(
+            gpu,
+            "Scene BVH nodes buffer",
+            &scene.bvh_nodes_as_wgsl_bytes()?[..],
+        );
+        self.instances = Self::storage_buffer(
+            gpu,
+            "Scene instances buffer",
+            &scene.instances_as_wgsl_bytes()?[..],
+        );
+        self.materials = Self::storage_buffer(
+            gpu,
+            "Scene materials buffer",
+            &scene.materials_as_wgsl_bytes()?[..],
+        );
+        self.lights = Self::storage_buffer(
+            gpu,
+            "Scene lights buffer",
+            &scene.lights_as_wgsl_bytes()?[..],
+        );
+        self.environment_map = Self::sampled_texture(
+            gpu,
+            "Scene environment map texture",
+            scene.environment_map().unwrap_or(&placeholder),
+        );
+        self.texture_atlas = Self::sampled_texture(
+            gpu,
+            "Scene texture atlas",
+            scene.texture_atlas().unwrap_or(&placeholder),
+        );
+
+        return Ok(());
+    }
+
+    /// Camera, background and sample settings change every render so the
+    /// uniform is rewritten in place
+    pub fn upload_config(
+        &self,
+        gpu: &Gpu,
+        system_config: &SystemConfig,
+    ) -> Result<(), WebRayError> {
+        gpu.queue
+            .write_buffer(&self.config, 0, &system_config.as_wgsl_bytes()?[..]);
+
+        return Ok(());
+    }
+
+    fn result_buffer(gpu: &Gpu, system_config: &SystemConfig) -> wgpu::Buffer {
+        return gpu.device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Result buffer"),
             size: system_config.result_size(),
             usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
+    }
 
-        let render_texture = gpu.device.create_texture(&wgpu::TextureDescriptor {
+    fn render_texture(gpu: &Gpu, system_config: &SystemConfig) -> wgpu::Texture {
+        return gpu.device.create_texture(&wgpu::TextureDescriptor {
             label: Some("Render texture"),
             size: wgpu::Extent3d {
                 width: system_config.image.width,
@@ -50,70 +231,138 @@ impl KernelBuffers {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8Unorm, // format is specified in the shader
+            format: wgpu::TextureFormat::Rgba32Float, // format is specified in the shader
+            // sampled and overwritten by the denoiser
+            usage: wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[wgpu::TextureFormat::Rgba32Float],
+        });
+    }
+
+    fn accumulation_texture(
+        gpu: &Gpu,
+        system_config: &SystemConfig,
+        label: &str,
+        usage: wgpu::TextureUsages,
+    ) -> wgpu::Texture {
+        return gpu.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: system_config.image.width,
+                height: system_config.image.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba32Float,
+            usage,
+            view_formats: &[wgpu::TextureFormat::Rgba32Float],
+        });
+    }
+
+    /// Image size while the render has aovs, 1x1 otherwise
+    fn aov_size(system_config: &SystemConfig) -> (u32, u32) {
+        if system_config.image.aovs == 0 {
+            return (1, 1);
+        }
+
+        return (system_config.image.width, system_config.image.height);
+    }
+
+    fn aov_texture(gpu: &Gpu, system_config: &SystemConfig) -> wgpu::Texture {
+        let (width, height) = Self::aov_size(system_config);
+
+        return gpu.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Aov texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: AOV_LAYERS,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba32Float,
+            usage: wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[wgpu::TextureFormat::Rgba32Float],
+        });
+    }
+
+    fn denoise_texture(gpu: &Gpu, system_config: &SystemConfig) -> wgpu::Texture {
+        let (width, height) = Self::aov_size(system_config);
+
+        return gpu.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Denoise texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba32Float,
             usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
-            view_formats: &[wgpu::TextureFormat::Rgba8Unorm],
+            view_formats: &[wgpu::TextureFormat::Rgba32Float],
         });
+    }
 
+    fn config_buffer(gpu: &Gpu, system_config: &SystemConfig) -> Result<wgpu::Buffer, WebRayError> {
         // using buffer init and write the buffer here it self, don't need to do queue.write_buffer
-        // maybe we can delay this, lazy upload
-
-        let config_buffer = gpu
+        // copy dst so a persistent renderer can rewrite it between renders
+        return Ok(gpu
             .device
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some("Config uniform buffer"),
-                contents: &system_config.as_wgsl_bytes().unwrap()[..],
-                usage: wgpu::BufferUsages::UNIFORM,
-            });
-
-        let spheres_buffer = gpu
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Scene spheres buffer"),
-                contents: &scene.spheres_as_wgsl_bytes().unwrap()[..],
-                usage: wgpu::BufferUsages::STORAGE,
-            });
-
-        let diffuse_mats_buffer =
-            gpu.device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("Scene diffuse materials buffer"),
-                    contents: &scene.diffuse_mats_as_wgsl_bytes().unwrap()[..],
-                    usage: wgpu::BufferUsages::STORAGE,
-                });
+                contents: &system_config.as_wgsl_bytes()?[..],
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }));
+    }
 
-        let metal_mats_buffer = gpu
+    fn storage_buffer(gpu: &Gpu, label: &str, contents: &[u8]) -> wgpu::Buffer {
+        // &arr and &arr[..] are different, second one is a slice and what we need
+        return gpu
             .device
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Scene metal materials buffer"),
-                contents: &scene.metal_mats_as_wgsl_bytes().unwrap()[..],
+                label: Some(label),
+                contents,
                 usage: wgpu::BufferUsages::STORAGE,
             });
+    }
 
-        let dielectric_mats_buffer =
-            gpu.device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("Scene dielectric materials buffer"),
-                    contents: &scene.dielectric_mats_as_wgsl_bytes().unwrap()[..],
-                    usage: wgpu::BufferUsages::STORAGE,
-                });
+    fn sampled_texture(gpu: &Gpu, label: &str, texture: &KTexture) -> wgpu::Texture {
+        return gpu.device.create_texture_with_data(
+            &gpu.queue,
+            &wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: texture.width,
+                    height: texture.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba32Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[wgpu::TextureFormat::Rgba32Float],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            texture.as_bytes(),
+        );
+    }
 
-        let execution_context_buffer = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+    fn execution_context_buffer(gpu: &Gpu) -> wgpu::Buffer {
+        return gpu.device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Execution Context buffer"),
             size: size_of::<ExecutionContext>() as wgpu::BufferAddress,
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
-
-        return KernelBuffers {
-            result: result_buffer,
-            render: render_texture,
-            config: config_buffer,
-            spheres: spheres_buffer,
-            diffuse_mats: diffuse_mats_buffer,
-            metal_mats: metal_mats_buffer,
-            dielectric_mats: dielectric_mats_buffer,
-            execution_context: execution_context_buffer,
-        };
     }
 }
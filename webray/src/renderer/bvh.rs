@@ -0,0 +1,865 @@
+use super::shapes::{KPrimitive, KTriangle, KVertex};
+
+/// Number of buckets the centroid range is split into when evaluating SAH
+const BIN_COUNT: usize = 12;
+/// Nodes with at most these many primitives become leaves when splitting doesn't pay off
+const MAX_LEAF_SIZE: usize = 4;
+/// Relative cost of a ray box test compared to a ray primitive test
+const TRAVERSAL_COST: f32 = 1.0;
+/// Infinite planes are bounded to a disk of this radius around their point
+const PLANE_EXTENT: f32 = 1e5;
+/// Nodes the kernel's traversal stack holds, same as BVH_STACK_SIZE in webray.wgsl
+const BVH_STACK_SIZE: usize = 64;
+/// Nodes this deep are always leaves, the traversal drops the children of deeper interior nodes
+const MAX_DEPTH: usize = BVH_STACK_SIZE - 1;
+
+shader_types! {
+    mod layout;
+
+    /// Flattened BVH node in depth first order, the first child of an interior node
+    /// is always the next node so only the second child index is stored.
+    /// Leaves of a shape BVH hold primitives and triangles, leaves of the
+    /// top level BVH only hold instances
+    #[derive(Debug, Clone, encase::ShaderType)]
+    pub struct KBvhNode {
+        pub min: glam::Vec3,
+        /// index of the second child, 0 for leaves as the root can never be a child
+        pub next: u32,
+        pub max: glam::Vec3,
+        /// offset, count into the primitive buffer
+        pub primitives: glam::UVec2,
+        /// offset, count into the triangle buffer
+        pub triangles: glam::UVec2,
+        /// offset, count into the instance buffer
+        pub instances: glam::UVec2,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: glam::Vec3,
+    pub max: glam::Vec3,
+}
+
+impl Aabb {
+    pub fn empty() -> Self {
+        return Aabb {
+            min: glam::Vec3::splat(f32::INFINITY),
+            max: glam::Vec3::splat(f32::NEG_INFINITY),
+        };
+    }
+
+    pub fn grow(&mut self, other: &Aabb) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    pub fn grow_point(&mut self, point: glam::Vec3) {
+        self.min = self.min.min(point);
+        self.max = self.max.max(point);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.min.cmpgt(self.max).any();
+    }
+
+    pub fn centroid(&self) -> glam::Vec3 {
+        return (self.min + self.max) * 0.5;
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+
+        let extent = self.max - self.min;
+
+        return 2.0 * (extent.x * extent.y + extent.y * extent.z + extent.z * extent.x);
+    }
+
+    /// Bounds of the transformed corners, which contain the transformed box
+    pub fn transformed(&self, matrix: &glam::Mat4) -> Aabb {
+        let mut bounds = Aabb::empty();
+
+        if self.is_empty() {
+            return bounds;
+        }
+
+        for corner in 0..8 {
+            let point = glam::vec3(
+                if corner & 1 == 0 {
+                    self.min.x
+                } else {
+                    self.max.x
+                },
+                if corner & 2 == 0 {
+                    self.min.y
+                } else {
+                    self.max.y
+                },
+                if corner & 4 == 0 {
+                    self.min.z
+                } else {
+                    self.max.z
+                },
+            );
+
+            bounds.grow_point(matrix.transform_point3(point));
+        }
+
+        return bounds;
+    }
+}
+
+impl Aabb {
+    fn of_primitive(primitive: &KPrimitive) -> Aabb {
+        let a = primitive.a.truncate();
+        let b = primitive.b.truncate();
+        let c = primitive.c.truncate();
+
+        return match primitive.kind {
+            KPrimitive::PLANE => Self::of_disk(a, b, PLANE_EXTENT),
+            KPrimitive::QUAD => {
+                let mut bounds = Aabb::empty();
+
+                bounds.grow_point(a);
+                bounds.grow_point(a + b);
+                bounds.grow_point(a + c);
+                bounds.grow_point(a + b + c);
+
+                bounds
+            }
+            KPrimitive::BOX => {
+                // extent of the rotated box along every axis
+                let rotation = glam::Mat3::from_quat(glam::Quat::from_vec4(primitive.c));
+                let extent = rotation.x_axis.abs() * b.x
+                    + rotation.y_axis.abs() * b.y
+                    + rotation.z_axis.abs() * b.z;
+
+                Aabb {
+                    min: a - extent,
+                    max: a + extent,
+                }
+            }
+            KPrimitive::DISK => Self::of_disk(a, b, primitive.a.w),
+            KPrimitive::CYLINDER => {
+                let top = Self::of_disk(b, b - a, primitive.a.w);
+                let mut bounds = Self::of_disk(a, b - a, primitive.a.w);
+
+                bounds.grow(&top);
+
+                bounds
+            }
+            _ => {
+                // hollow spheres use a negative radius
+                let radius = glam::Vec3::splat(primitive.a.w.abs());
+
+                Aabb {
+                    min: a - radius,
+                    max: a + radius,
+                }
+            }
+        };
+    }
+
+    /// A disk reaches radius * sin of the angle between its normal and each axis
+    fn of_disk(center: glam::Vec3, normal: glam::Vec3, radius: f32) -> Aabb {
+        let normal = normal.normalize();
+        let sin_squared = (glam::Vec3::ONE - normal * normal).max(glam::Vec3::ZERO);
+        let extent = glam::vec3(
+            sin_squared.x.sqrt(),
+            sin_squared.y.sqrt(),
+            sin_squared.z.sqrt(),
+        ) * radius.abs();
+
+        return Aabb {
+            min: center - extent,
+            max: center + extent,
+        };
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Primitive {
+    Analytic(u32),
+    Triangle(u32),
+    Instance(u32),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BuildItem {
+    primitive: Primitive,
+    bounds: Aabb,
+    centroid: glam::Vec3,
+}
+
+/// SAH bounding volume hierarchy over the primitives and triangles of a shape or the
+/// instances of a scene, leaves reference contiguous ranges of the primitive
+/// buffers once they are reordered with the order vectors
+#[derive(Debug)]
+pub struct Bvh {
+    pub nodes: Vec<KBvhNode>,
+    /// new position -> old primitive index
+    pub primitive_order: Vec<u32>,
+    /// new position -> old triangle index
+    pub triangle_order: Vec<u32>,
+    /// new position -> old instance index
+    pub instance_order: Vec<u32>,
+}
+
+impl Bvh {
+    pub fn build(primitives: &[KPrimitive], vertices: &[KVertex], triangles: &[KTriangle]) -> Self {
+        let mut items = Vec::with_capacity(primitives.len() + triangles.len());
+
+        for (i, primitive) in primitives.iter().enumerate() {
+            let bounds = Aabb::of_primitive(primitive);
+
+            items.push(BuildItem {
+                primitive: Primitive::Analytic(i as u32),
+                bounds,
+                centroid: bounds.centroid(),
+            });
+        }
+
+        for (i, triangle) in triangles.iter().enumerate() {
+            let mut bounds = Aabb::empty();
+
+            bounds.grow_point(vertices[triangle.vertices.x as usize].position);
+            bounds.grow_point(vertices[triangle.vertices.y as usize].position);
+            bounds.grow_point(vertices[triangle.vertices.z as usize].position);
+
+            items.push(BuildItem {
+                primitive: Primitive::Triangle(i as u32),
+                bounds,
+                centroid: bounds.centroid(),
+            });
+        }
+
+        return Self::build_items(&mut items[..]);
+    }
+
+    /// Top level BVH, bounds are the world space bounds of every instance
+    pub fn build_instances(bounds: &[Aabb]) -> Self {
+        let mut items = Vec::with_capacity(bounds.len());
+
+        for (i, bounds) in bounds.iter().enumerate() {
+            items.push(BuildItem {
+                primitive: Primitive::Instance(i as u32),
+                bounds: *bounds,
+                centroid: bounds.centroid(),
+            });
+        }
+
+        return Self::build_items(&mut items[..]);
+    }
+
+    fn build_items(items: &mut [BuildItem]) -> Self {
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(items.len() * 2),
+            primitive_order: Vec::new(),
+            triangle_order: Vec::new(),
+            instance_order: Vec::new(),
+        };
+
+        bvh.build_node(items, 0);
+
+        return bvh;
+    }
+
+    fn build_node(&mut self, items: &mut [BuildItem], depth: usize) -> u32 {
+        let node_index = self.nodes.len();
+
+        let mut bounds = Aabb::empty();
+        let mut centroid_bounds = Aabb::empty();
+
+        for item in items.iter() {
+            bounds.grow(&item.bounds);
+            centroid_bounds.grow_point(item.centroid);
+        }
+
+        if bounds.is_empty() {
+            bounds = Aabb {
+                min: glam::Vec3::ZERO,
+                max: glam::Vec3::ZERO,
+            };
+        }
+
+        self.nodes.push(KBvhNode {
+            min: bounds.min,
+            next: 0,
+            max: bounds.max,
+            primitives: glam::UVec2::ZERO,
+            triangles: glam::UVec2::ZERO,
+            instances: glam::UVec2::ZERO,
+        });
+
+        // degenerate inputs, such as exponentially spaced centroids, split off a single item per level
+        if items.len() <= 1 || depth >= MAX_DEPTH {
+            self.make_leaf(node_index, items);
+            return node_index as u32;
+        }
+
+        let split = Self::find_split(items, &bounds, &centroid_bounds);
+
+        let mid = match split {
+            Some((axis, bin, cost)) => {
+                if cost >= items.len() as f32 && items.len() <= MAX_LEAF_SIZE {
+                    self.make_leaf(node_index, items);
+                    return node_index as u32;
+                }
+
+                Self::partition(items, axis, bin, &centroid_bounds)
+            }
+            None => {
+                // all centroids coincide, SAH can't separate them
+                if items.len() <= MAX_LEAF_SIZE {
+                    self.make_leaf(node_index, items);
+                    return node_index as u32;
+                }
+
+                items.len() / 2
+            }
+        };
+
+        let (left, right) = items.split_at_mut(mid);
+
+        self.build_node(left, depth + 1);
+        let next = self.build_node(right, depth + 1);
+
+        self.nodes[node_index].next = next;
+
+        return node_index as u32;
+    }
+
+    /// Returns the best (axis, bin, cost) where primitives in bins <= bin go to the left child,
+    /// cost is relative to intersecting a single primitive
+    fn find_split(
+        items: &[BuildItem],
+        bounds: &Aabb,
+        centroid_bounds: &Aabb,
+    ) -> Option<(usize, usize, f32)> {
+        let mut best: Option<(usize, usize, f32)> = None;
+        let parent_area = bounds.surface_area();
+
+        for axis in 0..3 {
+            let extent = centroid_bounds.max[axis] - centroid_bounds.min[axis];
+
+            if extent <= 0.0 {
+                continue;
+            }
+
+            let mut bin_bounds = [Aabb::empty(); BIN_COUNT];
+            let mut bin_counts = [0usize; BIN_COUNT];
+
+            for item in items.iter() {
+                let bin = Self::bin_index(item.centroid[axis], centroid_bounds.min[axis], extent);
+
+                bin_bounds[bin].grow(&item.bounds);
+                bin_counts[bin] += 1;
+            }
+
+            // sweep from the right to get the area and count of every right side
+            let mut right_areas = [0.0f32; BIN_COUNT];
+            let mut right_counts = [0usize; BIN_COUNT];
+            let mut right_bounds = Aabb::empty();
+            let mut right_count = 0;
+
+            for bin in (1..BIN_COUNT).rev() {
+                right_bounds.grow(&bin_bounds[bin]);
+                right_count += bin_counts[bin];
+
+                right_areas[bin] = right_bounds.surface_area();
+                right_counts[bin] = right_count;
+            }
+
+            let mut left_bounds = Aabb::empty();
+            let mut left_count = 0;
+
+            for bin in 0..BIN_COUNT - 1 {
+                left_bounds.grow(&bin_bounds[bin]);
+                left_count += bin_counts[bin];
+
+                let right_count = right_counts[bin + 1];
+
+                if left_count == 0 || right_count == 0 {
+                    continue;
+                }
+
+                let cost = TRAVERSAL_COST
+                    + (left_bounds.surface_area() * left_count as f32
+                        + right_areas[bin + 1] * right_count as f32)
+                        / parent_area;
+
+                if best.is_none_or(|(_, _, best_cost)| return cost < best_cost) {
+                    best = Some((axis, bin, cost));
+                }
+            }
+        }
+
+        return best;
+    }
+
+    /// Moves items in bins <= bin to the front, returns the number of items moved
+    fn partition(
+        items: &mut [BuildItem],
+        axis: usize,
+        bin: usize,
+        centroid_bounds: &Aabb,
+    ) -> usize {
+        let extent = centroid_bounds.max[axis] - centroid_bounds.min[axis];
+        let mut mid = 0;
+
+        for i in 0..items.len() {
+            if Self::bin_index(items[i].centroid[axis], centroid_bounds.min[axis], extent) <= bin {
+                items.swap(i, mid);
+                mid += 1;
+            }
+        }
+
+        return mid;
+    }
+
+    fn bin_index(value: f32, min: f32, extent: f32) -> usize {
+        let bin = ((value - min) / extent * BIN_COUNT as f32) as usize;
+
+        return bin.min(BIN_COUNT - 1);
+    }
+
+    fn make_leaf(&mut self, node_index: usize, items: &[BuildItem]) {
+        let primitive_offset = self.primitive_order.len() as u32;
+        let triangle_offset = self.triangle_order.len() as u32;
+        let instance_offset = self.instance_order.len() as u32;
+
+        for item in items.iter() {
+            match item.primitive {
+                Primitive::Analytic(i) => self.primitive_order.push(i),
+                Primitive::Triangle(i) => self.triangle_order.push(i),
+                Primitive::Instance(i) => self.instance_order.push(i),
+            }
+        }
+
+        let node = &mut self.nodes[node_index];
+
+        node.primitives = glam::uvec2(
+            primitive_offset,
+            self.primitive_order.len() as u32 - primitive_offset,
+        );
+        node.triangles = glam::uvec2(
+            triangle_offset,
+            self.triangle_order.len() as u32 - triangle_offset,
+        );
+        node.instances = glam::uvec2(
+            instance_offset,
+            self.instance_order.len() as u32 - instance_offset,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Hit {
+        t: f32,
+        primitive: Primitive,
+    }
+
+    fn hit_sphere(
+        sphere: &KPrimitive,
+        origin: glam::Vec3,
+        direction: glam::Vec3,
+        t_max: f32,
+    ) -> Option<f32> {
+        let radius = sphere.a.w;
+        let oc = origin - sphere.a.truncate();
+        let a = direction.length_squared();
+        let half_b = oc.dot(direction);
+        let c = oc.length_squared() - radius * radius;
+        let discriminant = half_b * half_b - a * c;
+
+        if discriminant < 0.0 {
+            return None;
+        }
+
+        let sqrtd = discriminant.sqrt();
+
+        let near = (-half_b - sqrtd) / a;
+        let far = (-half_b + sqrtd) / a;
+
+        if 0.001 < near && near < t_max {
+            return Some(near);
+        }
+
+        if 0.001 < far && far < t_max {
+            return Some(far);
+        }
+
+        return None;
+    }
+
+    fn hit_triangle(
+        vertices: &[KVertex],
+        triangle: &KTriangle,
+        origin: glam::Vec3,
+        direction: glam::Vec3,
+        t_max: f32,
+    ) -> Option<f32> {
+        let v0 = vertices[triangle.vertices.x as usize].position;
+        let v1 = vertices[triangle.vertices.y as usize].position;
+        let v2 = vertices[triangle.vertices.z as usize].position;
+
+        let edge1 = v1 - v0;
+        let edge2 = v2 - v0;
+        let p = direction.cross(edge2);
+        let det = edge1.dot(p);
+
+        if det.abs() < 1e-8 {
+            return None;
+        }
+
+        let s = origin - v0;
+        let u = s.dot(p) / det;
+        let q = s.cross(edge1);
+        let v = direction.dot(q) / det;
+        let t = edge2.dot(q) / det;
+
+        if u < 0.0 || v < 0.0 || u + v > 1.0 || t <= 0.001 || t >= t_max {
+            return None;
+        }
+
+        return Some(t);
+    }
+
+    fn hit_aabb(node: &KBvhNode, origin: glam::Vec3, inv_dir: glam::Vec3, t_max: f32) -> bool {
+        let t0 = (node.min - origin) * inv_dir;
+        let t1 = (node.max - origin) * inv_dir;
+
+        let t_enter = t0.min(t1).max_element().max(0.001);
+        let t_exit = t0.max(t1).min_element().min(t_max);
+
+        return t_enter <= t_exit;
+    }
+
+    /// Mirrors the stack traversal in webray.wgsl
+    fn traverse(
+        bvh: &Bvh,
+        spheres: &[KPrimitive],
+        vertices: &[KVertex],
+        triangles: &[KTriangle],
+        origin: glam::Vec3,
+        direction: glam::Vec3,
+    ) -> Option<Hit> {
+        let inv_dir = direction.recip();
+        let mut closest: Option<Hit> = None;
+        let mut stack = vec![0u32];
+
+        while let Some(node_index) = stack.pop() {
+            let node = &bvh.nodes[node_index as usize];
+            let t_max = closest.map_or(f32::INFINITY, |hit| return hit.t);
+
+            if !hit_aabb(node, origin, inv_dir, t_max) {
+                continue;
+            }
+
+            if node.next != 0 {
+                stack.push(node.next);
+                stack.push(node_index + 1);
+                continue;
+            }
+
+            for i in node.primitives.x..node.primitives.x + node.primitives.y {
+                let index = bvh.primitive_order[i as usize];
+                let t_max = closest.map_or(f32::INFINITY, |hit| return hit.t);
+
+                if let Some(t) = hit_sphere(&spheres[index as usize], origin, direction, t_max) {
+                    closest = Some(Hit {
+                        t,
+                        primitive: Primitive::Analytic(index),
+                    });
+                }
+            }
+
+            for i in node.triangles.x..node.triangles.x + node.triangles.y {
+                let index = bvh.triangle_order[i as usize];
+                let t_max = closest.map_or(f32::INFINITY, |hit| return hit.t);
+
+                if let Some(t) = hit_triangle(
+                    vertices,
+                    &triangles[index as usize],
+                    origin,
+                    direction,
+                    t_max,
+                ) {
+                    closest = Some(Hit {
+                        t,
+                        primitive: Primitive::Triangle(index),
+                    });
+                }
+            }
+        }
+
+        return closest;
+    }
+
+    fn brute_force(
+        spheres: &[KPrimitive],
+        vertices: &[KVertex],
+        triangles: &[KTriangle],
+        origin: glam::Vec3,
+        direction: glam::Vec3,
+    ) -> Option<Hit> {
+        let mut closest: Option<Hit> = None;
+
+        for (i, sphere) in spheres.iter().enumerate() {
+            let t_max = closest.map_or(f32::INFINITY, |hit| return hit.t);
+
+            if let Some(t) = hit_sphere(sphere, origin, direction, t_max) {
+                closest = Some(Hit {
+                    t,
+                    primitive: Primitive::Analytic(i as u32),
+                });
+            }
+        }
+
+        for (i, triangle) in triangles.iter().enumerate() {
+            let t_max = closest.map_or(f32::INFINITY, |hit| return hit.t);
+
+            if let Some(t) = hit_triangle(vertices, triangle, origin, direction, t_max) {
+                closest = Some(Hit {
+                    t,
+                    primitive: Primitive::Triangle(i as u32),
+                });
+            }
+        }
+
+        return closest;
+    }
+
+    fn random_vec3(rng: &mut StdRng, min: f32, max: f32) -> glam::Vec3 {
+        return glam::vec3(
+            rng.gen_range(min..max),
+            rng.gen_range(min..max),
+            rng.gen_range(min..max),
+        );
+    }
+
+    #[test]
+    fn bvh_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(1337);
+
+        let mut spheres = Vec::new();
+        let mut vertices = Vec::new();
+        let mut triangles = Vec::new();
+
+        for _ in 0..300 {
+            spheres.push(KPrimitive::sphere(
+                random_vec3(&mut rng, -20.0, 20.0),
+                rng.gen_range(0.1..1.5),
+                glam::UVec4::ZERO,
+            ));
+        }
+
+        for i in 0..300 {
+            let center = random_vec3(&mut rng, -20.0, 20.0);
+
+            for _ in 0..3 {
+                vertices.push(KVertex {
+                    position: center + random_vec3(&mut rng, -1.0, 1.0),
+                    normal: glam::Vec3::Y,
+                    uv: glam::Vec2::ZERO,
+                });
+            }
+
+            triangles.push(KTriangle {
+                vertices: glam::uvec4(i * 3, i * 3 + 1, i * 3 + 2, 0),
+                mid: glam::UVec4::ZERO,
+            });
+        }
+
+        let bvh = Bvh::build(&spheres, &vertices, &triangles);
+
+        assert_eq!(bvh.primitive_order.len(), spheres.len());
+        assert_eq!(bvh.triangle_order.len(), triangles.len());
+
+        let mut hits = 0;
+
+        for _ in 0..5000 {
+            let origin = random_vec3(&mut rng, -25.0, 25.0);
+            let target = random_vec3(&mut rng, -20.0, 20.0);
+            let direction = target - origin;
+
+            let expected = brute_force(&spheres, &vertices, &triangles, origin, direction);
+            let actual = traverse(&bvh, &spheres, &vertices, &triangles, origin, direction);
+
+            assert_eq!(
+                expected, actual,
+                "origin: {}, direction: {}",
+                origin, direction
+            );
+
+            if expected.is_some() {
+                hits += 1;
+            }
+        }
+
+        // make sure the test isn't passing by missing everything
+        assert!(hits > 1000);
+    }
+
+    #[test]
+    fn instance_leaves_enclose_their_instances() {
+        let mut rng = StdRng::seed_from_u64(7);
+
+        let shape = Aabb {
+            min: glam::vec3(-1.0, -0.5, -2.0),
+            max: glam::vec3(1.0, 0.5, 2.0),
+        };
+
+        let bounds = (0..200)
+            .map(|_| {
+                let matrix = glam::Mat4::from_scale_rotation_translation(
+                    random_vec3(&mut rng, 0.1, 3.0),
+                    glam::Quat::from_euler(
+                        glam::EulerRot::XYZ,
+                        rng.gen_range(0.0..std::f32::consts::TAU),
+                        rng.gen_range(0.0..std::f32::consts::TAU),
+                        rng.gen_range(0.0..std::f32::consts::TAU),
+                    ),
+                    random_vec3(&mut rng, -50.0, 50.0),
+                );
+
+                return shape.transformed(&matrix);
+            })
+            .collect::<Vec<_>>();
+
+        let bvh = Bvh::build_instances(&bounds);
+
+        let mut seen = vec![false; bounds.len()];
+
+        for node in bvh.nodes.iter().filter(|node| return node.next == 0) {
+            for i in node.instances.x..node.instances.x + node.instances.y {
+                let index = bvh.instance_order[i as usize] as usize;
+                let instance = &bounds[index];
+
+                assert!(!seen[index], "instance {} is in more than one leaf", index);
+                seen[index] = true;
+
+                assert!(node.min.cmple(instance.min).all() && node.max.cmpge(instance.max).all());
+            }
+        }
+
+        assert!(seen.into_iter().all(|seen| return seen));
+    }
+
+    #[test]
+    fn primitive_bounds_enclose_their_surface() {
+        let mut rng = StdRng::seed_from_u64(42);
+
+        for _ in 0..100 {
+            let center = random_vec3(&mut rng, -10.0, 10.0);
+            let normal = random_vec3(&mut rng, -1.0, 1.0).normalize();
+            let (e1, e2) = normal.any_orthonormal_pair();
+            let u = random_vec3(&mut rng, -3.0, 3.0);
+            let v = random_vec3(&mut rng, -3.0, 3.0);
+            let radius = rng.gen_range(0.1..3.0);
+            let rotation = glam::Quat::from_scaled_axis(random_vec3(&mut rng, -3.0, 3.0));
+            let half_size = random_vec3(&mut rng, 0.1, 3.0);
+
+            let primitives = [
+                KPrimitive::plane(center, normal, glam::UVec4::ZERO),
+                KPrimitive::quad(center, u, v, glam::UVec4::ZERO),
+                KPrimitive::oriented_box(center, half_size, rotation, glam::UVec4::ZERO),
+                KPrimitive::disk(center, normal, radius, glam::UVec4::ZERO),
+                KPrimitive::cylinder(center, center + u, radius, glam::UVec4::ZERO),
+            ];
+
+            for primitive in primitives.iter() {
+                let bounds = Aabb::of_primitive(primitive);
+
+                for _ in 0..50 {
+                    let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+                    let along = rng.gen_range(0.0..1.0);
+                    let across = rng.gen_range(0.0..1.0);
+
+                    let point = match primitive.kind {
+                        KPrimitive::PLANE => {
+                            center + (e1 * angle.cos() + e2 * angle.sin()) * PLANE_EXTENT * along
+                        }
+                        KPrimitive::QUAD => center + u * along + v * across,
+                        KPrimitive::BOX => {
+                            let corner = glam::vec3(
+                                if angle < 3.0 { 1.0 } else { -1.0 },
+                                along * 2.0 - 1.0,
+                                across * 2.0 - 1.0,
+                            );
+
+                            center + rotation * (corner * half_size)
+                        }
+                        KPrimitive::DISK => {
+                            center + (e1 * angle.cos() + e2 * angle.sin()) * radius * along
+                        }
+                        _ => {
+                            let (a1, a2) = u.normalize().any_orthonormal_pair();
+
+                            center + u * along + (a1 * angle.cos() + a2 * angle.sin()) * radius
+                        }
+                    };
+
+                    let slack = glam::Vec3::splat(1e-3 * (1.0 + point.abs().max_element()));
+
+                    assert!(
+                        (bounds.min - slack).cmple(point).all()
+                            && (bounds.max + slack).cmpge(point).all(),
+                        "kind {}, point {} outside {:?}",
+                        primitive.kind,
+                        point,
+                        bounds
+                    );
+                }
+            }
+        }
+    }
+
+    /// Depth of the deepest leaf below the node
+    fn depth(nodes: &[KBvhNode], index: usize) -> usize {
+        let node = &nodes[index];
+
+        if node.next == 0 {
+            return 0;
+        }
+
+        return 1 + depth(nodes, index + 1).max(depth(nodes, node.next as usize));
+    }
+
+    #[test]
+    fn degenerate_bvh_stays_within_the_traversal_stack() {
+        // exponentially spaced along both directions of every axis,
+        // most splits only separate the farthest sphere from the rest
+        let spheres: Vec<KPrimitive> = (0..210)
+            .map(|i| {
+                let sign = if i % 2 == 0 { 1.0 } else { -1.0 };
+                let mut center = glam::Vec3::ZERO;
+                center[i / 2 % 3] = sign * 12f32.powi(i as i32 / 6);
+
+                return KPrimitive::sphere(center, 0.1, glam::UVec4::ZERO);
+            })
+            .collect();
+
+        let bvh = Bvh::build(&spheres, &[], &[]);
+
+        assert_eq!(bvh.primitive_order.len(), spheres.len());
+        assert_eq!(depth(&bvh.nodes, 0), MAX_DEPTH);
+    }
+
+    #[test]
+    fn bvh_of_empty_scene_is_a_single_empty_leaf() {
+        let bvh = Bvh::build(&[], &[], &[]);
+
+        assert_eq!(bvh.nodes.len(), 1);
+        assert_eq!(bvh.nodes[0].next, 0);
+        assert_eq!(bvh.nodes[0].primitives.y + bvh.nodes[0].triangles.y, 0);
+    }
+}
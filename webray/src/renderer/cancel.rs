@@ -0,0 +1,159 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use crate::utils::time;
+
+/// Stops a running render, checked between tiles and passes. Clones share the same flag
+/// so one can be handed to another thread (or js) while the render runs
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        return self.cancelled.load(Ordering::Relaxed);
+    }
+
+    /// Called when a render starts so a cancel meant for the last one doesn't stop it
+    pub(crate) fn reset(&self) {
+        self.cancelled.store(false, Ordering::Relaxed);
+    }
+}
+
+/// How a render ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderStatus {
+    /// every sample was taken
+    Complete,
+    /// stopped through the cancel token, the output holds the samples taken so far
+    Cancelled,
+    /// ran out of the time limit, the output holds the samples taken so far
+    TimedOut,
+}
+
+/// Cancel token and time limit of a single render
+pub(crate) struct StopCondition {
+    token: CancelToken,
+    deadline: Option<Deadline>,
+}
+
+impl StopCondition {
+    pub fn new(token: CancelToken, time_limit: Option<std::time::Duration>) -> Self {
+        return StopCondition {
+            token,
+            deadline: time_limit.map(Deadline::after),
+        };
+    }
+
+    /// Status the render ends with when it has to stop now
+    pub fn check(&self) -> Option<RenderStatus> {
+        if self.token.is_cancelled() {
+            return Some(RenderStatus::Cancelled);
+        }
+
+        if self.deadline.as_ref().is_some_and(Deadline::passed) {
+            return Some(RenderStatus::TimedOut);
+        }
+
+        return None;
+    }
+}
+
+struct Deadline {
+    /// see time::now_ms
+    at: f64,
+}
+
+impl Deadline {
+    fn after(duration: std::time::Duration) -> Self {
+        return Deadline {
+            at: time::now_ms() + duration.as_secs_f64() * 1000.0,
+        };
+    }
+
+    fn passed(&self) -> bool {
+        return time::now_ms() >= self.at;
+    }
+}
+
+/// Lets the browser run pending tasks, such as a click on cancel, before continuing
+#[cfg(target_arch = "wasm32")]
+pub(crate) async fn yield_to_event_loop() {
+    let promise = js_sys::Promise::new(&mut |resolve, _| {
+        let scheduled = web_sys::window()
+            .is_some_and(|window| return window.set_timeout_with_callback(&resolve).is_ok());
+
+        // without a window there's no timer to wait for
+        if !scheduled {
+            let _ = resolve.call0(&wasm_bindgen::JsValue::NULL);
+        }
+    });
+
+    let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+}
+
+/// Average of the accumulated samples, the alpha of every pixel counts its samples so
+/// pixels a stopped pass didn't reach keep the average of the previous passes
+pub(crate) fn resolve_partial(accumulation: &[f32]) -> Vec<f32> {
+    let mut hdr = Vec::with_capacity(accumulation.len());
+
+    for pixel in accumulation.chunks_exact(4) {
+        let samples = pixel[3];
+
+        if samples > 0.0 {
+            hdr.extend_from_slice(&[
+                pixel[0] / samples,
+                pixel[1] / samples,
+                pixel[2] / samples,
+                1.0,
+            ]);
+        } else {
+            hdr.extend_from_slice(&[0.0, 0.0, 0.0, 1.0]);
+        }
+    }
+
+    return hdr;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clones_share_the_cancel_and_reset_clears_it() {
+        let token = CancelToken::new();
+        let stop = StopCondition::new(token.clone(), None);
+
+        assert_eq!(stop.check(), None);
+
+        token.clone().cancel();
+        assert_eq!(stop.check(), Some(RenderStatus::Cancelled));
+
+        token.reset();
+        assert_eq!(stop.check(), None);
+
+        let timed = StopCondition::new(CancelToken::new(), Some(std::time::Duration::ZERO));
+        assert_eq!(timed.check(), Some(RenderStatus::TimedOut));
+    }
+
+    #[test]
+    fn partial_resolve_averages_every_pixel_by_its_own_samples() {
+        let accumulation = [4.0, 2.0, 0.0, 4.0, 1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0];
+
+        assert_eq!(
+            resolve_partial(&accumulation),
+            vec![1.0, 0.5, 0.0, 1.0, 1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 1.0]
+        );
+    }
+}
@@ -1,7 +1,15 @@
+use super::{
+    aov::Aov,
+    denoise::DenoiseConfig,
+    post::{PostConfig, Tonemap},
+};
+
 #[derive(Debug, Clone, Copy)]
 pub enum TileSize {
     Full,
     Tile(u32),
+    /// picked by the kernel's scheduler from the dispatch budget
+    Auto,
 }
 
 #[derive(Debug)]
@@ -9,8 +17,20 @@ pub struct RenderConfig {
     pub width: u32,
     pub height: u32,
     pub samples: u32,
+    pub samples_per_pass: u32,
     pub bounces: u32,
+    pub diffuse_bounces: u32,
+    pub glossy_bounces: u32,
+    pub transmission_bounces: u32,
+    pub roulette_depth: u32,
+    pub aovs: Vec<Aov>,
+    pub denoise: Option<DenoiseConfig>,
+    pub time_limit: Option<std::time::Duration>,
     pub tile_size: TileSize,
+    pub dispatch_budget: std::time::Duration,
+    pub exposure: f32,
+    pub tonemap: Tonemap,
+    pub seed: u32,
 }
 
 #[derive(Debug)]
@@ -21,49 +41,115 @@ pub struct CameraConfig {
     pub v_fov: f32,
     pub dof_angle: f32,
     pub dof_distance: f32,
+    pub projection: ProjectionMode,
+    /// orthographic height of the view in world units
+    pub view_height: f32,
 }
 
-#[derive(Debug, encase::ShaderType)]
-pub struct Image {
-    pub width: u32,
-    pub height: u32,
-    samples: u32,
-    bounces: u32,
+#[derive(Debug, Clone, Copy)]
+pub enum ProjectionMode {
+    Perspective = 0,
+    Orthographic = 1,
+    Fisheye = 2,
+    Equirectangular = 3,
 }
 
-#[derive(Debug, encase::ShaderType)]
-pub struct Camera {
-    center: glam::Vec3,
-    dof_angle: f32,
-    dof_disk_u: glam::Vec3,
-    dof_disk_v: glam::Vec3,
+#[derive(Debug, Clone, Copy)]
+pub enum BackgroundMode {
+    Solid = 0,
+    Gradient = 1,
+    EnvironmentMap = 2,
 }
 
-#[derive(Debug, encase::ShaderType)]
-pub struct Viewport {
-    width: f32,
-    height: f32,
-    u: glam::Vec3, // local horizontal vector
-    v: glam::Vec3, // local inverted vertical vector
-    delta_u: glam::Vec3,
-    delta_v: glam::Vec3,
-    upper_left: glam::Vec3,
+#[derive(Debug)]
+pub struct BackgroundConfig {
+    pub mode: BackgroundMode,
+    /// solid color or top of the gradient
+    pub color_a: glam::Vec3,
+    /// bottom of the gradient
+    pub color_b: glam::Vec3,
+    /// degrees around the up axis
+    pub rotation: f32,
+    pub intensity: f32,
 }
 
-#[derive(Debug, encase::ShaderType)]
-pub struct SystemConfig {
-    pub image: Image,
-    pub camera: Camera,
-    pub viewport: Viewport,
-    pixel_zero_loc: glam::Vec3,
+shader_types! {
+    mod layout;
+
+    #[derive(Debug, encase::ShaderType)]
+    pub struct Image {
+        pub width: u32,
+        pub height: u32,
+        pub samples: u32,
+        pub bounces: u32,
+        pub diffuse_bounces: u32,
+        pub glossy_bounces: u32,
+        pub transmission_bounces: u32,
+        pub roulette_depth: u32,
+        /// see Aov::mask
+        pub aovs: u32,
+        pub seed: u32,
+    }
+
+    #[derive(Debug, encase::ShaderType)]
+    pub struct Camera {
+        pub center: glam::Vec3,
+        pub dof_angle: f32,
+        pub dof_distance: f32,
+        pub dof_disk_u: glam::Vec3,
+        pub dof_disk_v: glam::Vec3,
+        pub projection: u32,
+        /// fisheye angle across the image height in radians
+        pub fov: f32,
+        // camera frame, w points backwards
+        pub u: glam::Vec3,
+        pub v: glam::Vec3,
+        pub w: glam::Vec3,
+    }
+
+    #[derive(Debug, encase::ShaderType)]
+    pub struct Viewport {
+        pub width: f32,
+        pub height: f32,
+        pub u: glam::Vec3, // local horizontal vector
+        pub v: glam::Vec3, // local inverted vertical vector
+        pub delta_u: glam::Vec3,
+        pub delta_v: glam::Vec3,
+        pub upper_left: glam::Vec3,
+    }
+
+    #[derive(Debug, encase::ShaderType)]
+    pub struct Background {
+        pub mode: u32,
+        pub rotation: f32,
+        pub intensity: f32,
+        pub color_a: glam::Vec3,
+        pub color_b: glam::Vec3,
+    }
+
+    #[derive(Debug, encase::ShaderType)]
+    pub struct SystemConfig {
+        pub image: Image,
+        pub camera: Camera,
+        pub viewport: Viewport,
+        pub pixel_zero_loc: glam::Vec3,
+        pub background: Background,
+    }
 }
 
 impl SystemConfig {
     /// A lot of camera calculations
-    pub fn new(render_config: &RenderConfig, camera_config: &CameraConfig) -> Self {
+    pub fn new(
+        render_config: &RenderConfig,
+        camera_config: &CameraConfig,
+        background_config: &BackgroundConfig,
+    ) -> Self {
         // Determine viewport dimensions.
         let h = (camera_config.v_fov.to_radians() / 2.0).tan(); // 90 deg this equation = 1.0
-        let viewport_height = 2.0 * h * camera_config.dof_distance;
+        let viewport_height = match camera_config.projection {
+            ProjectionMode::Orthographic => camera_config.view_height,
+            _ => 2.0 * h * camera_config.dof_distance,
+        };
         let viewport_width =
             viewport_height * (render_config.width as f32 / render_config.height as f32);
 
@@ -80,9 +166,13 @@ impl SystemConfig {
         let delta_u = viewport_u / render_config.width as f32;
         let delta_v = viewport_v / render_config.height as f32;
 
-        // Calculate the location of the upper left pixel.
+        // Calculate the location of the upper left pixel, orthographic rays start on the viewport.
+        let viewport_distance = match camera_config.projection {
+            ProjectionMode::Orthographic => 0.0,
+            _ => camera_config.dof_distance,
+        };
         let upper_left = camera_config.look_from
-            - (camera_config.dof_distance * w)
+            - (viewport_distance * w)
             - (viewport_u / 2.0)
             - (viewport_v / 2.0);
         let pixel_zero_loc = upper_left + 0.5 * (delta_u + delta_v);
@@ -98,13 +188,25 @@ impl SystemConfig {
             height: render_config.height,
             samples: render_config.samples,
             bounces: render_config.bounces,
+            diffuse_bounces: render_config.diffuse_bounces,
+            glossy_bounces: render_config.glossy_bounces,
+            transmission_bounces: render_config.transmission_bounces,
+            roulette_depth: render_config.roulette_depth,
+            aovs: Aov::mask(&render_config.aovs) | Self::denoise_aovs(render_config),
+            seed: render_config.seed,
         };
 
         let camera = Camera {
             center: camera_config.look_from,
             dof_angle: camera_config.dof_angle,
+            dof_distance: camera_config.dof_distance,
             dof_disk_u: defocus_disk_u,
             dof_disk_v: defocus_disk_v,
+            projection: camera_config.projection as u32,
+            fov: camera_config.v_fov.to_radians(),
+            u,
+            v,
+            w,
         };
 
         let viewport = Viewport {
@@ -117,60 +219,122 @@ impl SystemConfig {
             upper_left,
         };
 
+        let background = Background {
+            mode: background_config.mode as u32,
+            rotation: background_config.rotation,
+            intensity: background_config.intensity,
+            color_a: background_config.color_a,
+            color_b: background_config.color_b,
+        };
+
         return SystemConfig {
             image,
             camera,
             viewport,
             pixel_zero_loc,
+            background,
         };
     }
 
+    /// Guides the denoiser needs written even when they aren't output
+    fn denoise_aovs(render_config: &RenderConfig) -> u32 {
+        return match render_config.denoise {
+            Some(_) => Aov::mask(&[Aov::Albedo, Aov::Normal]),
+            None => 0,
+        };
+    }
+
+    /// Bytes in the rgba32float result image, with its rows padded as texture copies need
     pub fn result_size(&self) -> u64 {
-        return (self.image.width * self.image.height * 4) as u64;
+        return padded_row_size(self.image.width) as u64 * self.image.height as u64;
     }
 
     pub fn as_wgsl_bytes(&self) -> encase::internal::Result<Vec<u8>> {
         let mut buffer = encase::UniformBuffer::new(Vec::new());
-        buffer.write(self).unwrap();
+        buffer.write(self)?;
         return Ok(buffer.into_inner());
     }
 }
 
-#[derive(Debug, encase::ShaderType)]
-pub struct ExecutionContext {
-    pub tile_position: glam::UVec2,
+/// Bytes in a row of an rgba32float image copied to a buffer, rows
+/// of texture copies start at a multiple of COPY_BYTES_PER_ROW_ALIGNMENT
+pub fn padded_row_size(width: u32) -> u32 {
+    return (width * 16).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+}
+
+shader_types! {
+    mod execution_layout;
+
+    #[derive(Debug, encase::ShaderType)]
+    pub struct ExecutionContext {
+        pub tile_position: glam::UVec2,
+        /// invocations past the size return without writing
+        pub tile_size: glam::UVec2,
+        /// samples accumulated by previous passes
+        pub sample_offset: u32,
+        /// samples to take in this dispatch
+        pub sample_count: u32,
+    }
 }
 
 impl ExecutionContext {
     pub fn as_wgsl_bytes(&self) -> encase::internal::Result<Vec<u8>> {
         let mut buffer = encase::UniformBuffer::new(Vec::new());
-        buffer.write(self).unwrap();
+        buffer.write(self)?;
         return Ok(buffer.into_inner());
     }
 }
 
 pub struct ExecutionConfig {
     pub tile_size: TileSize,
+    /// time a single submission should take at most
+    pub dispatch_budget: std::time::Duration,
+    pub samples: u32,
+    pub samples_per_pass: u32,
+    /// aovs read back once the render is done
+    pub aovs: Vec<Aov>,
+    /// render is stopped between tiles once this much time passed
+    pub time_limit: Option<std::time::Duration>,
 }
 
 impl ExecutionConfig {
     pub fn new(render_config: &RenderConfig) -> Self {
         return ExecutionConfig {
             tile_size: render_config.tile_size,
+            dispatch_budget: render_config.dispatch_budget,
+            samples: render_config.samples,
+            samples_per_pass: render_config
+                .samples_per_pass
+                .clamp(1, render_config.samples.max(1)),
+            aovs: render_config.aovs.clone(),
+            time_limit: render_config.time_limit,
         };
     }
+
+    pub fn pass_count(&self) -> u32 {
+        return self.samples.div_ceil(self.samples_per_pass);
+    }
 }
 
 pub struct KernelConfig {
     pub system: SystemConfig,
     pub execution: ExecutionConfig,
+    pub post: PostConfig,
+    /// applied to the linear image before the post processing
+    pub denoise: Option<DenoiseConfig>,
 }
 
 impl KernelConfig {
-    pub fn new(render_config: &RenderConfig, camera_config: &CameraConfig) -> Self {
+    pub fn new(
+        render_config: &RenderConfig,
+        camera_config: &CameraConfig,
+        background_config: &BackgroundConfig,
+    ) -> Self {
         return KernelConfig {
-            system: SystemConfig::new(render_config, camera_config),
+            system: SystemConfig::new(render_config, camera_config, background_config),
             execution: ExecutionConfig::new(render_config),
+            post: PostConfig::new(render_config),
+            denoise: render_config.denoise,
         };
     }
 }
@@ -0,0 +1,1856 @@
+use std::sync::{Mutex, OnceLock};
+
+use super::{
+    aov::{AovImage, AOV_LAYERS},
+    bvh::KBvhNode,
+    cancel::{resolve_partial, RenderStatus, StopCondition},
+    config::{BackgroundMode, KernelConfig, ProjectionMode, SystemConfig},
+    denoise::denoise,
+    light::KLight,
+    material::KMaterial,
+    post::PostConfig,
+    random,
+    scene::KernelScene,
+    shapes::{KInstance, KPrimitive, KTriangle},
+    RenderEvent, Tile,
+};
+
+/// Image rows a worker renders before picking up the next tile
+const TILE_ROWS: usize = 8;
+const BVH_STACK_SIZE: usize = 64;
+
+/// Same sentinel the kernel uses for an unbounded ray
+const INF_F32: f32 = f32::MAX;
+const EPSILON: f32 = f32::from_bits(1);
+const ERR_COLOR: glam::Vec3 = glam::vec3(1.0, 0.0, 1.0);
+
+/// Metal smoother than this is treated as a mirror, light sampling would only add fireflies
+const NEE_MIN_ROUGHNESS: f32 = 0.1;
+/// Shadow rays stop short of the sampled point so they don't hit the light itself
+const SHADOW_RAY_SCALE: f32 = 0.999;
+
+/// Reference path tracer that mirrors webray.wgsl on the CPU, used where no adapter is available.
+/// Rows are split into tiles that worker threads pull from a shared queue.
+pub fn execute(
+    config: &KernelConfig,
+    scene: &KernelScene,
+    stop: &StopCondition,
+    mut on_event: Option<&mut dyn FnMut(RenderEvent)>,
+) -> (Vec<f32>, Vec<AovImage>, RenderStatus) {
+    let system_config = &config.system;
+    let execution_config = &config.execution;
+
+    // wasm has no threads, everything runs on the calling thread
+    let threads = std::thread::available_parallelism().map_or(1, |n| return n.get());
+
+    log::info!(
+        "CPU render, threads: {}, width: {}, height: {}",
+        threads,
+        system_config.image.width,
+        system_config.image.height
+    );
+
+    let tracer = Tracer {
+        config: system_config,
+        scene,
+    };
+
+    // the center rays don't depend on the samples so the aovs are traced once up front
+    let layers = if system_config.image.aovs != 0 {
+        tracer.render_aovs()
+    } else {
+        Vec::new()
+    };
+
+    // the accumulation is left noisy, only the resolved image is filtered
+    let resolve_image = |samples: u32, accumulation: &[f32]| {
+        let hdr = resolve(accumulation, samples);
+
+        return match &config.denoise {
+            Some(denoise_config) => denoise(
+                denoise_config,
+                system_config.image.width,
+                system_config.image.height,
+                &hdr,
+                &layers,
+            ),
+            None => hdr,
+        };
+    };
+
+    // running sum of all samples, same as the accumulation texture
+    let mut accumulation =
+        vec![0f32; (system_config.image.width * system_config.image.height * 4) as usize];
+
+    let pass_count = execution_config.pass_count();
+
+    // stopped renders are averaged per pixel and not denoised, same as the kernel
+    let mut status_and_image = None;
+
+    for pass in 0..pass_count {
+        let sample_offset = pass * execution_config.samples_per_pass;
+        let sample_count = execution_config
+            .samples_per_pass
+            .min(execution_config.samples - sample_offset);
+
+        log::info!(
+            "Rendering pass {}/{}, samples: {}",
+            pass + 1,
+            pass_count,
+            sample_offset + sample_count
+        );
+
+        let stopped = tracer.render_pass(
+            &mut accumulation,
+            glam::uvec2(pass, pass_count),
+            glam::uvec2(sample_offset, sample_count),
+            threads,
+            stop,
+            &config.post,
+            &mut on_event,
+        );
+
+        if let Some(status) = stopped {
+            log::warn!("Render stopped ({:?}) in pass {}", status, pass + 1);
+
+            // rows the pass didn't reach keep the samples of the previous passes
+            status_and_image = Some((status, resolve_partial(&accumulation)));
+            break;
+        }
+
+        // final pass is resolved below
+        if pass + 1 == pass_count {
+            break;
+        }
+
+        if let Some(callback) = on_event.as_mut() {
+            let hdr = resolve_image(sample_offset + sample_count, &accumulation);
+
+            callback(RenderEvent::PassFinished {
+                samples: sample_offset + sample_count,
+                total_samples: execution_config.samples,
+                image: config.post.apply(&hdr),
+            });
+        }
+    }
+
+    let (status, hdr) = match status_and_image {
+        Some(stopped) => stopped,
+        None => (
+            RenderStatus::Complete,
+            resolve_image(execution_config.samples, &accumulation),
+        ),
+    };
+
+    let aovs = execution_config
+        .aovs
+        .iter()
+        .map(|aov| {
+            return AovImage {
+                aov: *aov,
+                data: aov.extract(&layers[aov.layer() as usize]),
+            };
+        })
+        .collect();
+
+    return (hdr, aovs, status);
+}
+
+/// Average of the accumulated samples
+fn resolve(accumulation: &[f32], samples: u32) -> Vec<f32> {
+    let mut hdr = Vec::with_capacity(accumulation.len());
+
+    for value in accumulation {
+        hdr.push(value / samples as f32);
+    }
+
+    return hdr;
+}
+
+/// Pcg32 modified, mirrors the kernel rng so both backends draw the same sequences
+struct Rng {
+    state: u32,
+}
+
+impl Rng {
+    fn new(pixel_position: glam::UVec2, width: u32, sample: u32, seed: u32) -> Self {
+        return Rng {
+            state: random::sample_seed(pixel_position, width, sample, seed),
+        };
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        let new_state = self.state.wrapping_mul(747796405).wrapping_add(1);
+
+        self.state = new_state;
+
+        let w1 = ((new_state >> ((new_state >> 28) + 4)) ^ new_state).wrapping_mul(277803737);
+
+        return (w1 >> 22) ^ w1;
+    }
+
+    fn float(&mut self) -> f32 {
+        return self.next_u32() as f32 / 4294967295.0;
+    }
+
+    fn float_range(&mut self, min: f32, max: f32) -> f32 {
+        return min + (max - min) * self.float();
+    }
+
+    fn in_unit_disk(&mut self) -> glam::Vec3 {
+        let x = self.float_range(-1.0, 1.0);
+        let y = self.float_range(-1.0, 1.0);
+
+        let p = glam::vec3(x, y, 0.0);
+
+        if p.length_squared() >= 1.0 {
+            return p.normalize();
+        }
+
+        return p;
+    }
+
+    /// uniform on the unit sphere, exact so the pdf of scattered directions is known
+    fn unit_vector(&mut self) -> glam::Vec3 {
+        let z = self.float_range(-1.0, 1.0);
+        let phi = 2.0 * std::f32::consts::PI * self.float();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+
+        return glam::vec3(r * phi.cos(), r * phi.sin(), z);
+    }
+
+    /// uniform in the cone around the unit axis with the given cosine of its half angle
+    fn in_cone(&mut self, axis: glam::Vec3, cos_max: f32) -> glam::Vec3 {
+        let cos_theta = 1.0 - self.float() * (1.0 - cos_max);
+        let phi = 2.0 * std::f32::consts::PI * self.float();
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+
+        return orthonormal_basis(axis)
+            * glam::vec3(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Ray {
+    origin: glam::Vec3,
+    direction: glam::Vec3,
+}
+
+impl Ray {
+    fn at(&self, t: f32) -> glam::Vec3 {
+        return self.origin + t * self.direction;
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Interval {
+    min: f32,
+    max: f32,
+}
+
+impl Interval {
+    fn surrounds(&self, x: f32) -> bool {
+        return self.min < x && x < self.max;
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct HitRecord {
+    t: f32,
+    point: glam::Vec3,
+    normal: glam::Vec3,
+    mat_type: u32,
+    mat_index: u32,
+    front_face: bool,
+    uv: glam::Vec2,
+    /// light index plus one for emissive surfaces that are sampled, 0 otherwise
+    light: u32,
+    /// object id plus one
+    object: u32,
+}
+
+impl HitRecord {
+    /// out_normal needs to be a unit vector
+    fn set_face_normal(&mut self, ray: &Ray, out_normal: glam::Vec3) {
+        self.front_face = ray.direction.dot(out_normal) < 0.0;
+        self.normal = if self.front_face {
+            out_normal
+        } else {
+            -out_normal
+        };
+    }
+}
+
+struct Tracer<'a> {
+    config: &'a SystemConfig,
+    scene: &'a KernelScene,
+}
+
+impl Tracer<'_> {
+    /// pass is (index, count) of the passes and samples (offset, count) of the samples taken by
+    /// this pass. Workers report their tiles through a channel the calling thread forwards to on_event.
+    /// Returns the status when stopped before every tile was rendered
+    #[allow(clippy::too_many_arguments)]
+    fn render_pass(
+        &self,
+        accumulation: &mut [f32],
+        pass: glam::UVec2,
+        samples: glam::UVec2,
+        threads: usize,
+        stop: &StopCondition,
+        post: &PostConfig,
+        on_event: &mut Option<&mut dyn FnMut(RenderEvent)>,
+    ) -> Option<RenderStatus> {
+        let width = self.config.image.width as usize;
+        let height = self.config.image.height;
+        let tiles_per_pass = height.div_ceil(TILE_ROWS as u32);
+
+        let tiles = Mutex::new(accumulation.chunks_mut(width * 4 * TILE_ROWS).enumerate());
+
+        let (sender, receiver) = flume::unbounded();
+        // nothing is sent when no one is listening
+        let sender = on_event.is_some().then_some(sender);
+
+        // set by the first worker that sees the stop
+        let stopped = OnceLock::new();
+
+        let worker = |sender: Option<flume::Sender<RenderEvent>>| loop {
+            let next = {
+                let mut tiles = tiles.lock().unwrap();
+
+                // a pass that has no tiles left isn't stopped anymore
+                if tiles.len() > 0 && stopped.get().is_none() {
+                    if let Some(status) = stop.check() {
+                        let _ = stopped.set(status);
+                    }
+                }
+
+                if stopped.get().is_some() {
+                    None
+                } else {
+                    tiles.next()
+                }
+            };
+
+            let Some((index, pixels)) = next else {
+                return;
+            };
+
+            let y = (index * TILE_ROWS) as u32;
+
+            let tile = Tile {
+                index: pass.x * tiles_per_pass + index as u32,
+                count: pass.y * tiles_per_pass,
+                x: 0,
+                y,
+                width: width as u32,
+                height: (TILE_ROWS as u32).min(height - y),
+            };
+
+            if let Some(sender) = &sender {
+                let _ = sender.send(RenderEvent::TileStarted { tile });
+            }
+
+            for (i, pixel) in pixels.chunks_exact_mut(4).enumerate() {
+                let pixel_position = glam::uvec2((i % width) as u32, y + (i / width) as u32);
+
+                for sample in samples.x..samples.x + samples.y {
+                    let mut rng = Rng::new(
+                        pixel_position,
+                        self.config.image.width,
+                        sample,
+                        self.config.image.seed,
+                    );
+
+                    let color = self.render(pixel_position, &mut rng);
+
+                    pixel[0] += color.x;
+                    pixel[1] += color.y;
+                    pixel[2] += color.z;
+                    pixel[3] += 1.0;
+                }
+            }
+
+            if let Some(sender) = &sender {
+                let hdr = resolve(pixels, samples.x + samples.y);
+
+                let _ = sender.send(RenderEvent::TileFinished {
+                    tile,
+                    image: post.apply(&hdr),
+                });
+            }
+        };
+
+        let forward = |on_event: &mut Option<&mut dyn FnMut(RenderEvent)>| {
+            if let Some(callback) = on_event.as_mut() {
+                // ends once every worker dropped its sender
+                for event in receiver.iter() {
+                    callback(event);
+                }
+            }
+        };
+
+        if threads <= 1 {
+            worker(sender);
+            forward(on_event);
+            return stopped.into_inner();
+        }
+
+        std::thread::scope(|scope| {
+            for _ in 0..threads {
+                let sender = sender.clone();
+                let worker = &worker;
+
+                scope.spawn(move || return worker(sender));
+            }
+
+            drop(sender);
+            forward(on_event);
+        });
+
+        return stopped.into_inner();
+    }
+
+    fn render(&self, pixel_position: glam::UVec2, rng: &mut Rng) -> glam::Vec3 {
+        let jitter = glam::vec2(-0.5 + rng.float(), -0.5 + rng.float());
+        // drawn even without depth of field so every projection takes the same random numbers
+        let lens = rng.in_unit_disk();
+
+        let lens = if self.config.camera.dof_angle <= 0.0 {
+            glam::Vec2::ZERO
+        } else {
+            lens.truncate()
+        };
+
+        let Some(ray) = self.camera_ray(pixel_position, jitter, lens) else {
+            return glam::Vec3::ZERO;
+        };
+
+        return self.render_ray(ray, rng);
+    }
+
+    /// Ray through the pixel offset by jitter pixels, from the point lens of the unit disk on the lens,
+    /// none where the projection doesn't cover the pixel
+    fn camera_ray(
+        &self,
+        pixel_position: glam::UVec2,
+        jitter: glam::Vec2,
+        lens: glam::Vec2,
+    ) -> Option<Ray> {
+        let viewport = &self.config.viewport;
+        let camera = &self.config.camera;
+
+        // pixels from the top left corner of the image
+        let pixel = pixel_position.as_vec2() + 0.5 + jitter;
+        let size = glam::vec2(
+            self.config.image.width as f32,
+            self.config.image.height as f32,
+        );
+
+        let pixel_sample = || {
+            return self.config.pixel_zero_loc
+                + (pixel_position.x as f32 * viewport.delta_u)
+                + (pixel_position.y as f32 * viewport.delta_v)
+                + ((jitter.x * viewport.delta_u) + (jitter.y * viewport.delta_v));
+        };
+
+        if camera.projection == ProjectionMode::Orthographic as u32 {
+            let pixel_sample = pixel_sample();
+
+            // parallel rays focused on the plane at the focus distance
+            let origin = pixel_sample + (lens.x * camera.dof_disk_u) + (lens.y * camera.dof_disk_v);
+
+            return Some(Ray {
+                origin,
+                direction: pixel_sample - (camera.dof_distance * camera.w) - origin,
+            });
+        } else if camera.projection == ProjectionMode::Fisheye as u32 {
+            // equidistant, the angle from the view direction grows linearly with the distance from the center
+            let offset = (pixel - (0.5 * size)) / (0.5 * size.y);
+            let theta = offset.length() * camera.fov / 2.0;
+
+            if theta > std::f32::consts::PI {
+                return None;
+            }
+
+            let phi = offset.y.atan2(offset.x);
+
+            return Some(Ray {
+                origin: camera.center,
+                direction: (theta.cos() * -camera.w)
+                    + (theta.sin() * ((phi.cos() * camera.u) - (phi.sin() * camera.v))),
+            });
+        } else if camera.projection == ProjectionMode::Equirectangular as u32 {
+            let uv = pixel / size;
+            let longitude = (uv.x - 0.5) * 2.0 * std::f32::consts::PI;
+            let latitude = (0.5 - uv.y) * std::f32::consts::PI;
+
+            return Some(Ray {
+                origin: camera.center,
+                direction: (latitude.cos()
+                    * ((longitude.sin() * camera.u) - (longitude.cos() * camera.w)))
+                    + (latitude.sin() * camera.v),
+            });
+        }
+
+        let origin = camera.center + (lens.x * camera.dof_disk_u) + (lens.y * camera.dof_disk_v);
+
+        return Some(Ray {
+            origin,
+            direction: pixel_sample() - origin,
+        });
+    }
+
+    /// Every aov layer, rgba32 per pixel, same as the kernel's aov texture
+    fn render_aovs(&self) -> Vec<Vec<f32>> {
+        let width = self.config.image.width;
+        let height = self.config.image.height;
+
+        let mut layers =
+            vec![Vec::with_capacity((width * height * 4) as usize); AOV_LAYERS as usize];
+
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = self.render_aov_pixel(glam::uvec2(x, y));
+
+                for (layer, value) in layers.iter_mut().zip(pixel) {
+                    layer.extend_from_slice(&value.to_array());
+                }
+            }
+        }
+
+        return layers;
+    }
+
+    /// first hit of the pixel's center ray, traced without depth of field and without drawing random numbers
+    fn render_aov_pixel(&self, pixel_position: glam::UVec2) -> [glam::Vec4; AOV_LAYERS as usize] {
+        // outside the fisheye's circle
+        let Some(ray) = self.camera_ray(pixel_position, glam::Vec2::ZERO, glam::Vec2::ZERO) else {
+            return [
+                glam::vec4(0.0, 0.0, 0.0, 1.0),
+                glam::vec4(0.0, 0.0, 0.0, 1.0),
+                glam::vec4(0.0, -1.0, -1.0, 1.0),
+            ];
+        };
+
+        let mut hit = HitRecord::default();
+
+        if !self.hit_world(
+            &ray,
+            Interval {
+                min: 0.001,
+                max: INF_F32,
+            },
+            &mut hit,
+        ) {
+            return [
+                self.background(&ray)
+                    .clamp(glam::Vec3::ZERO, glam::Vec3::ONE)
+                    .extend(1.0),
+                glam::vec4(0.0, 0.0, 0.0, 1.0),
+                glam::vec4(0.0, -1.0, -1.0, 1.0),
+            ];
+        }
+
+        // dielectrics are white and emissive surfaces their emitted color
+        let material = &self.scene.materials()[hit.mat_index as usize];
+        let albedo = self.material_color(material, &hit);
+        let depth = hit.t * ray.direction.length();
+
+        return [
+            albedo.extend(1.0),
+            hit.normal.extend(1.0),
+            glam::vec4(depth, hit.object as f32 - 1.0, material.id as f32, 1.0),
+        ];
+    }
+
+    fn render_ray(&self, ray: Ray, rng: &mut Rng) -> glam::Vec3 {
+        let mut current_ray = ray;
+
+        // light gathered along the path and how much of it survives till the camera
+        let mut radiance = glam::Vec3::ZERO;
+        let mut throughput = glam::Vec3::ONE;
+
+        // pdf of the bounce that picked current_ray, 0 when that bounce didn't sample lights
+        let mut bsdf_pdf = 0.0;
+
+        // diffuse, glossy and transmission scatters so far and how many each is allowed
+        let mut bounce_counts = [0u32; 3];
+        let bounce_limits = [
+            self.config.image.diffuse_bounces,
+            self.config.image.glossy_bounces,
+            self.config.image.transmission_bounces,
+        ];
+
+        for bounce in 0..self.config.image.bounces {
+            let mut hit = HitRecord::default();
+
+            if !self.hit_world(
+                &current_ray,
+                Interval {
+                    min: 0.001,
+                    max: INF_F32,
+                },
+                &mut hit,
+            ) {
+                radiance += throughput * self.background(&current_ray);
+                break;
+            }
+
+            radiance += throughput
+                * self.emitted(&hit)
+                * self.emission_weight(&current_ray, &hit, bsdf_pdf);
+
+            let lit = self.samples_lights(&hit);
+
+            if lit {
+                radiance += throughput * self.sample_lights(&current_ray, &hit, rng);
+            }
+
+            let Some((attenuation, scattered)) = self.scatter(&current_ray, &hit, rng) else {
+                break;
+            };
+
+            // diffuse, metal and dielectric are types 1 to 3, emissive surfaces never scatter
+            let kind = (hit.mat_type - 1) as usize;
+            bounce_counts[kind] += 1;
+
+            if bounce_counts[kind] > bounce_limits[kind] {
+                break;
+            }
+
+            bsdf_pdf = if lit {
+                self.scatter_pdf(&current_ray, &hit, scattered.direction.normalize())
+            } else {
+                0.0
+            };
+
+            throughput *= attenuation;
+
+            // dim paths are ended at random, the survivors are brightened to keep the estimate unbiased
+            if bounce + 1 >= self.config.image.roulette_depth {
+                let survival = throughput.max_element().min(1.0);
+
+                if rng.float() >= survival {
+                    break;
+                }
+
+                throughput /= survival;
+            }
+            current_ray = scattered;
+        }
+
+        return radiance;
+    }
+
+    /// Mirrors the top level traversal in the kernel
+    fn hit_world(&self, ray: &Ray, ray_limits: Interval, hit: &mut HitRecord) -> bool {
+        let mut hit_anything = false;
+        let mut closest_so_far = ray_limits.max;
+
+        self.traverse(ray, ray_limits, 0, |node| {
+            for instance in self.range(self.scene.instances(), node.instances) {
+                let limits = Interval {
+                    min: ray_limits.min,
+                    max: closest_so_far,
+                };
+
+                if self.hit_instance(instance, ray, limits, hit) {
+                    hit_anything = true;
+                    closest_so_far = hit.t;
+                }
+            }
+
+            return closest_so_far;
+        });
+
+        return hit_anything;
+    }
+
+    /// intersects the instance's shape in object space, the hit is moved back to world space
+    fn hit_instance(
+        &self,
+        instance: &KInstance,
+        ray: &Ray,
+        ray_limits: Interval,
+        hit: &mut HitRecord,
+    ) -> bool {
+        // direction is transformed without normalizing so t is the same in both spaces
+        let object_ray = Ray {
+            origin: instance.world_to_object.transform_point3(ray.origin),
+            direction: instance.world_to_object.transform_vector3(ray.direction),
+        };
+
+        if !self.hit_shape(&object_ray, ray_limits, instance.root, hit) {
+            return false;
+        }
+
+        hit.point = ray.at(hit.t);
+        // transpose of the inverse keeps normals perpendicular under non uniform scale
+        hit.normal = instance
+            .world_to_object
+            .transpose()
+            .transform_vector3(hit.normal)
+            .normalize();
+
+        if instance.mid.x != 0 {
+            hit.mat_type = instance.mid.x;
+            hit.mat_index = instance.mid.y;
+            hit.object = instance.mid.w;
+        }
+
+        return true;
+    }
+
+    /// closest hit in a shape, walks its BVH from the root
+    fn hit_shape(&self, ray: &Ray, ray_limits: Interval, root: u32, hit: &mut HitRecord) -> bool {
+        let mut hit_anything = false;
+        let mut closest_so_far = ray_limits.max;
+
+        self.traverse(ray, ray_limits, root, |node| {
+            for primitive in self.range(self.scene.primitives(), node.primitives) {
+                let limits = Interval {
+                    min: ray_limits.min,
+                    max: closest_so_far,
+                };
+
+                if hit_primitive(primitive, ray, limits, hit) {
+                    hit_anything = true;
+                    closest_so_far = hit.t;
+                }
+            }
+
+            for triangle in self.range(self.scene.triangles(), node.triangles) {
+                let limits = Interval {
+                    min: ray_limits.min,
+                    max: closest_so_far,
+                };
+
+                if self.hit_triangle(triangle, ray, limits, hit) {
+                    hit_anything = true;
+                    closest_so_far = hit.t;
+                }
+            }
+
+            return closest_so_far;
+        });
+
+        return hit_anything;
+    }
+
+    /// Mirrors the fixed size stack traversal in the kernel, on_leaf
+    /// intersects the leaf and returns the closest hit so far
+    fn traverse(
+        &self,
+        ray: &Ray,
+        ray_limits: Interval,
+        root: u32,
+        mut on_leaf: impl FnMut(&KBvhNode) -> f32,
+    ) {
+        let nodes = self.scene.bvh_nodes();
+
+        let mut closest_so_far = ray_limits.max;
+
+        let inv_direction = ray.direction.recip();
+
+        let mut stack = [0u32; BVH_STACK_SIZE];
+        let mut stack_size = 1;
+        stack[0] = root;
+
+        while stack_size > 0 {
+            stack_size -= 1;
+
+            let node_index = stack[stack_size];
+            let node = &nodes[node_index as usize];
+
+            let t0 = (node.min - ray.origin) * inv_direction;
+            let t1 = (node.max - ray.origin) * inv_direction;
+
+            let t_enter = t0.min(t1).max_element().max(ray_limits.min);
+            let t_exit = t0.max(t1).min_element().min(closest_so_far);
+
+            if t_enter > t_exit {
+                continue;
+            }
+
+            // interior node, first child is always the next node
+            if node.next != 0 {
+                if stack_size + 2 <= BVH_STACK_SIZE {
+                    stack[stack_size] = node.next;
+                    stack[stack_size + 1] = node_index + 1;
+                    stack_size += 2;
+                }
+
+                continue;
+            }
+
+            closest_so_far = on_leaf(node);
+        }
+    }
+
+    /// range is (offset, count) into the buffer
+    fn range<'b, T>(&self, buffer: &'b [T], range: glam::UVec2) -> &'b [T] {
+        return &buffer[range.x as usize..(range.x + range.y) as usize];
+    }
+
+    /// Moller-Trumbore intersection, normal is interpolated from the vertex normals
+    fn hit_triangle(
+        &self,
+        triangle: &KTriangle,
+        ray: &Ray,
+        ray_limits: Interval,
+        hit: &mut HitRecord,
+    ) -> bool {
+        let vertices = self.scene.vertices();
+
+        let v0 = &vertices[triangle.vertices.x as usize];
+        let v1 = &vertices[triangle.vertices.y as usize];
+        let v2 = &vertices[triangle.vertices.z as usize];
+
+        let edge1 = v1.position - v0.position;
+        let edge2 = v2.position - v0.position;
+
+        let p = ray.direction.cross(edge2);
+        let det = edge1.dot(p);
+
+        // ray is parallel to the triangle plane or the triangle is degenerate
+        if det.abs() < 1e-8 {
+            return false;
+        }
+
+        let inv_det = 1.0 / det;
+        let s = ray.origin - v0.position;
+
+        let u = s.dot(p) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return false;
+        }
+
+        let q = s.cross(edge1);
+
+        let v = ray.direction.dot(q) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return false;
+        }
+
+        let t = edge2.dot(q) * inv_det;
+        if !ray_limits.surrounds(t) {
+            return false;
+        }
+
+        hit.t = t;
+        hit.point = ray.at(t);
+        hit.uv = (1.0 - u - v) * v0.uv + u * v1.uv + v * v2.uv;
+
+        hit.mat_type = triangle.mid.x;
+        hit.mat_index = triangle.mid.y;
+        hit.light = triangle.mid.z;
+        hit.object = triangle.mid.w;
+
+        // face side comes from the winding, shading uses the interpolated normal
+        let geometric_normal = edge1.cross(edge2).normalize();
+        let shading_normal =
+            ((1.0 - u - v) * v0.normal + u * v1.normal + v * v2.normal).normalize();
+
+        hit.set_face_normal(ray, geometric_normal);
+        hit.normal = if hit.front_face {
+            shading_normal
+        } else {
+            -shading_normal
+        };
+
+        return true;
+    }
+
+    /// attenuation and scattered ray, None when the ray is absorbed
+    fn scatter(&self, ray: &Ray, hit: &HitRecord, rng: &mut Rng) -> Option<(glam::Vec3, Ray)> {
+        return match hit.mat_type {
+            KMaterial::DIFFUSE => {
+                let material = &self.scene.materials()[hit.mat_index as usize];
+                let mut scatter_direction = hit.normal + rng.unit_vector();
+
+                if near_zero(scatter_direction) {
+                    scatter_direction = hit.normal;
+                }
+
+                Some((
+                    self.material_color(material, hit),
+                    Ray {
+                        origin: hit.point,
+                        direction: scatter_direction,
+                    },
+                ))
+            }
+            KMaterial::METAL => {
+                let material = &self.scene.materials()[hit.mat_index as usize];
+                let roughness = material.param;
+                let reflected = reflect(ray.direction.normalize(), hit.normal);
+
+                Some((
+                    self.material_color(material, hit),
+                    Ray {
+                        origin: hit.point,
+                        direction: reflected + roughness * rng.unit_vector(),
+                    },
+                ))
+            }
+            KMaterial::DIELECTRIC => {
+                let ior = self.scene.materials()[hit.mat_index as usize].param;
+
+                let refraction_ratio = if hit.front_face { 1.0 / ior } else { ior };
+
+                let unit_direction = ray.direction.normalize();
+
+                let cos_theta = (-unit_direction).dot(hit.normal).min(1.0);
+                let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+                let cannot_refract = refraction_ratio * sin_theta > 1.0;
+
+                let direction =
+                    if cannot_refract || reflectance(cos_theta, refraction_ratio) > rng.float() {
+                        reflect(unit_direction, hit.normal)
+                    } else {
+                        refract(unit_direction, hit.normal, refraction_ratio)
+                    };
+
+                Some((
+                    glam::Vec3::ONE,
+                    Ray {
+                        origin: hit.point,
+                        direction,
+                    },
+                ))
+            }
+            // emissive surfaces absorb everything
+            _ => None,
+        };
+    }
+
+    /// diffuse and metal rougher than NEE_MIN_ROUGHNESS are lit by sampling lights,
+    /// everything else only finds light by scattering into it
+    fn samples_lights(&self, hit: &HitRecord) -> bool {
+        return match hit.mat_type {
+            KMaterial::DIFFUSE => true,
+            KMaterial::METAL => {
+                self.scene.materials()[hit.mat_index as usize].param >= NEE_MIN_ROUGHNESS
+            }
+            _ => false,
+        };
+    }
+
+    /// solid angle pdf of scatter picking the unit direction, only defined where samples_lights is true.
+    /// for both materials the bsdf times the cosine is the albedo times this pdf
+    fn scatter_pdf(&self, ray: &Ray, hit: &HitRecord, direction: glam::Vec3) -> f32 {
+        return match hit.mat_type {
+            KMaterial::DIFFUSE => offset_sphere_pdf(direction, hit.normal, 1.0),
+            KMaterial::METAL => {
+                let reflected = reflect(ray.direction.normalize(), hit.normal);
+                let roughness = self.scene.materials()[hit.mat_index as usize].param;
+
+                offset_sphere_pdf(direction, reflected, roughness)
+            }
+            _ => 0.0,
+        };
+    }
+
+    /// next event estimation, picks a light uniformly and returns what it adds through the surface
+    /// when nothing is in the way, area lights are weighted against scattering into them
+    fn sample_lights(&self, ray: &Ray, hit: &HitRecord, rng: &mut Rng) -> glam::Vec3 {
+        let lights = self.scene.lights();
+        let count = lights.len() as u32;
+
+        if count == 0 {
+            return glam::Vec3::ZERO;
+        }
+
+        let index = ((rng.float() * count as f32) as u32).min(count - 1);
+        let sample = sample_light(&lights[index as usize], hit.point, rng);
+
+        if sample.value == glam::Vec3::ZERO || sample.direction.dot(hit.normal) <= 0.0 {
+            return glam::Vec3::ZERO;
+        }
+
+        let bsdf_pdf = self.scatter_pdf(ray, hit, sample.direction);
+
+        if bsdf_pdf <= 0.0 {
+            return glam::Vec3::ZERO;
+        }
+
+        let mut shadow_hit = HitRecord::default();
+
+        let shadow_ray = Ray {
+            origin: hit.point,
+            direction: sample.direction,
+        };
+        let shadow_limits = Interval {
+            min: 0.001,
+            max: sample.distance * SHADOW_RAY_SCALE,
+        };
+
+        if self.hit_world(&shadow_ray, shadow_limits, &mut shadow_hit) {
+            return glam::Vec3::ZERO;
+        }
+
+        let weight = if sample.pdf > 0.0 {
+            power_heuristic(sample.pdf / count as f32, bsdf_pdf)
+        } else {
+            1.0
+        };
+
+        let albedo = self.material_color(&self.scene.materials()[hit.mat_index as usize], hit);
+
+        return albedo * bsdf_pdf * sample.value * count as f32 * weight;
+    }
+
+    /// how much of the emission a scattered ray finds is kept, the rest is
+    /// found by sampling the light at the bounce the ray comes from
+    fn emission_weight(&self, ray: &Ray, hit: &HitRecord, bsdf_pdf: f32) -> f32 {
+        if bsdf_pdf <= 0.0 || hit.light == 0 {
+            return 1.0;
+        }
+
+        let lights = self.scene.lights();
+
+        let ray_length = ray.direction.length();
+        let direction = ray.direction / ray_length;
+
+        let light_pdf = light_pdf(
+            &lights[hit.light as usize - 1],
+            ray.origin,
+            direction,
+            hit,
+            hit.t * ray_length,
+        ) / lights.len() as f32;
+
+        return power_heuristic(bsdf_pdf, light_pdf);
+    }
+
+    /// albedo of the material at the hit
+    fn material_color(&self, material: &KMaterial, hit: &HitRecord) -> glam::Vec3 {
+        return match material.texture {
+            KMaterial::TEXTURE_IMAGE => self.sample_image(material.image, hit.uv * material.scale),
+            KMaterial::TEXTURE_CHECKER => {
+                let cell = (hit.uv * material.scale).floor().as_ivec2();
+
+                if (cell.x.wrapping_add(cell.y) & 1) == 0 {
+                    material.color
+                } else {
+                    material.color_b
+                }
+            }
+            KMaterial::TEXTURE_NOISE => {
+                let blend =
+                    (0.5 + 0.5 * fbm(hit.point * material.scale, material.octaves)).clamp(0.0, 1.0);
+
+                material.color.lerp(material.color_b, blend)
+            }
+            _ => material.color,
+        };
+    }
+
+    /// bilinear lookup of the image's rect in the atlas, wraps in both directions, v is 0 at the bottom
+    fn sample_image(&self, rect: glam::UVec4, uv: glam::Vec2) -> glam::Vec3 {
+        let Some(texture) = self.scene.texture_atlas() else {
+            return glam::Vec3::ZERO;
+        };
+
+        let origin = glam::ivec2(rect.x as i32, rect.y as i32);
+        let size = glam::ivec2(rect.z as i32, rect.w as i32);
+        let wrapped = uv.fract_gl();
+
+        let texel = glam::vec2(wrapped.x * size.x as f32, (1.0 - wrapped.y) * size.y as f32) - 0.5;
+        let base = texel.floor();
+        let blend = texel - base;
+
+        let x0 = (base.x as i32 + size.x) % size.x;
+        let x1 = (x0 + 1) % size.x;
+        let y0 = (base.y as i32 + size.y) % size.y;
+        let y1 = (y0 + 1) % size.y;
+
+        let load = |x: i32, y: i32| {
+            let index = (((origin.y + y) * texture.width as i32 + origin.x + x) * 4) as usize;
+            return glam::Vec3::from_slice(&texture.data[index..index + 3]);
+        };
+
+        let top = load(x0, y0).lerp(load(x1, y0), blend.x);
+        let bottom = load(x0, y1).lerp(load(x1, y1), blend.x);
+
+        return top.lerp(bottom, blend.y);
+    }
+
+    fn emitted(&self, hit: &HitRecord) -> glam::Vec3 {
+        if hit.mat_type == KMaterial::EMISSIVE {
+            let material = &self.scene.materials()[hit.mat_index as usize];
+            let strength = material.param;
+            return material.color * strength;
+        }
+
+        return glam::Vec3::ZERO;
+    }
+
+    fn background(&self, ray: &Ray) -> glam::Vec3 {
+        let background = &self.config.background;
+        let unit_dir = ray.direction.normalize();
+
+        let color = if background.mode == BackgroundMode::Solid as u32 {
+            background.color_a
+        } else if background.mode == BackgroundMode::Gradient as u32 {
+            let alpha = 0.5 * (unit_dir.y + 1.0);
+            (1.0 - alpha) * background.color_b + alpha * background.color_a
+        } else if background.mode == BackgroundMode::EnvironmentMap as u32 {
+            self.sample_environment_map(unit_dir)
+        } else {
+            ERR_COLOR
+        };
+
+        return color * background.intensity;
+    }
+
+    /// equirectangular lookup with bilinear filtering, wraps horizontally
+    fn sample_environment_map(&self, direction: glam::Vec3) -> glam::Vec3 {
+        let Some(texture) = self.scene.environment_map() else {
+            return glam::Vec3::ZERO;
+        };
+
+        let width = texture.width as i32;
+        let height = texture.height as i32;
+
+        let u = (direction.z.atan2(direction.x) / (2.0 * std::f32::consts::PI)
+            + 0.5
+            + self.config.background.rotation / 360.0)
+            .rem_euclid(1.0);
+        let v = direction.y.clamp(-1.0, 1.0).acos() / std::f32::consts::PI;
+
+        let texel = glam::vec2(u * width as f32, v * height as f32) - 0.5;
+        let base = texel.floor();
+        let blend = texel - base;
+
+        let x0 = (base.x as i32 + width) % width;
+        let x1 = (x0 + 1) % width;
+        let y0 = (base.y as i32).clamp(0, height - 1);
+        let y1 = (base.y as i32 + 1).clamp(0, height - 1);
+
+        let load = |x: i32, y: i32| {
+            let index = ((y * width + x) * 4) as usize;
+            return glam::Vec3::from_slice(&texture.data[index..index + 3]);
+        };
+
+        let top = load(x0, y0).lerp(load(x1, y0), blend.x);
+        let bottom = load(x0, y1).lerp(load(x1, y1), blend.x);
+
+        return top.lerp(bottom, blend.y);
+    }
+}
+
+/// What a primitive intersection finds, the rest of the record is filled by hit_primitive
+struct PrimitiveHit {
+    t: f32,
+    /// outward unit normal
+    normal: glam::Vec3,
+    uv: glam::Vec2,
+}
+
+fn hit_primitive(
+    primitive: &KPrimitive,
+    ray: &Ray,
+    ray_limits: Interval,
+    hit: &mut HitRecord,
+) -> bool {
+    let found = match primitive.kind {
+        KPrimitive::SPHERE => hit_sphere(primitive, ray, ray_limits),
+        KPrimitive::PLANE => hit_plane(primitive, ray, ray_limits),
+        KPrimitive::QUAD => hit_quad(primitive, ray, ray_limits),
+        KPrimitive::BOX => hit_box(primitive, ray, ray_limits),
+        KPrimitive::DISK => hit_disk(primitive, ray, ray_limits),
+        KPrimitive::CYLINDER => hit_cylinder(primitive, ray, ray_limits),
+        _ => None,
+    };
+
+    let Some(found) = found else {
+        return false;
+    };
+
+    hit.t = found.t;
+    hit.point = ray.at(found.t);
+    hit.uv = found.uv;
+
+    hit.mat_type = primitive.mid.x;
+    hit.mat_index = primitive.mid.y;
+    hit.light = primitive.mid.z;
+    hit.object = primitive.mid.w;
+
+    hit.set_face_normal(ray, found.normal);
+
+    return true;
+}
+
+/// longitude and latitude of a unit direction, v is 0 at the bottom
+fn sphere_uv(direction: glam::Vec3) -> glam::Vec2 {
+    let u =
+        ((-direction.z).atan2(direction.x) + std::f32::consts::PI) / (2.0 * std::f32::consts::PI);
+    let v = (-direction.y).clamp(-1.0, 1.0).acos() / std::f32::consts::PI;
+
+    return glam::vec2(u, v);
+}
+
+/// coordinates of the offset in a tangent frame of the unit normal
+fn planar_uv(offset: glam::Vec3, normal: glam::Vec3) -> glam::Vec2 {
+    let basis = orthonormal_basis(normal);
+
+    return glam::vec2(offset.dot(basis.x_axis), offset.dot(basis.y_axis));
+}
+
+/// tangent, bitangent and the unit normal as columns, mirrors the kernel rather than
+/// using glam's any_orthonormal_pair which treats -0.0 differently
+fn orthonormal_basis(normal: glam::Vec3) -> glam::Mat3 {
+    let side = if normal.z >= 0.0 { 1.0 } else { -1.0 };
+    let a = -1.0 / (side + normal.z);
+    let b = normal.x * normal.y * a;
+
+    let tangent = glam::vec3(
+        1.0 + side * normal.x * normal.x * a,
+        side * b,
+        -side * normal.x,
+    );
+    let bitangent = glam::vec3(b, side + normal.y * normal.y * a, -normal.y);
+
+    return glam::Mat3::from_cols(tangent, bitangent, normal);
+}
+
+/// solves the sphere ray intersection equation, which is a quadratic equation
+fn hit_sphere(sphere: &KPrimitive, ray: &Ray, ray_limits: Interval) -> Option<PrimitiveHit> {
+    let center = sphere.a.truncate();
+    let radius = sphere.a.w;
+
+    let origin_to_center = ray.origin - center;
+
+    let a = ray.direction.length_squared();
+    let half_b = origin_to_center.dot(ray.direction);
+    let c = origin_to_center.length_squared() - radius * radius;
+
+    let discriminant = (half_b * half_b) - (a * c);
+
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let sqrtd = discriminant.sqrt();
+
+    let mut root = (-half_b - sqrtd) / a;
+    if !ray_limits.surrounds(root) {
+        root = (-half_b + sqrtd) / a;
+        if !ray_limits.surrounds(root) {
+            return None;
+        }
+    }
+
+    let point = ray.at(root);
+
+    return Some(PrimitiveHit {
+        t: root,
+        normal: (point - center) / radius,
+        uv: sphere_uv((point - center) / radius.abs()),
+    });
+}
+
+fn hit_plane(plane: &KPrimitive, ray: &Ray, ray_limits: Interval) -> Option<PrimitiveHit> {
+    let point = plane.a.truncate();
+    let normal = plane.b.truncate();
+
+    let denom = normal.dot(ray.direction);
+
+    // ray is parallel to the plane
+    if denom.abs() < 1e-8 {
+        return None;
+    }
+
+    let root = (point - ray.origin).dot(normal) / denom;
+    if !ray_limits.surrounds(root) {
+        return None;
+    }
+
+    return Some(PrimitiveHit {
+        t: root,
+        normal,
+        uv: planar_uv(ray.at(root) - point, normal),
+    });
+}
+
+/// plane hit followed by the planar coordinates of the point along both edges
+fn hit_quad(quad: &KPrimitive, ray: &Ray, ray_limits: Interval) -> Option<PrimitiveHit> {
+    let corner = quad.a.truncate();
+    let u = quad.b.truncate();
+    let v = quad.c.truncate();
+
+    let n = u.cross(v);
+    let unit_normal = n.normalize();
+
+    let denom = unit_normal.dot(ray.direction);
+
+    if denom.abs() < 1e-8 {
+        return None;
+    }
+
+    let root = (corner - ray.origin).dot(unit_normal) / denom;
+    if !ray_limits.surrounds(root) {
+        return None;
+    }
+
+    let planar = ray.at(root) - corner;
+    let w = n / n.dot(n);
+
+    let alpha = w.dot(planar.cross(v));
+    let beta = w.dot(u.cross(planar));
+
+    if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+        return None;
+    }
+
+    return Some(PrimitiveHit {
+        t: root,
+        normal: unit_normal,
+        uv: glam::vec2(alpha, beta),
+    });
+}
+
+/// slab test in the box's frame, the exit is used when the ray starts inside
+fn hit_box(bx: &KPrimitive, ray: &Ray, ray_limits: Interval) -> Option<PrimitiveHit> {
+    let center = bx.a.truncate();
+    let half_size = bx.b.truncate();
+    let rotation = glam::Quat::from_vec4(bx.c);
+    let inverse_rotation = rotation.conjugate();
+
+    let origin = inverse_rotation * (ray.origin - center);
+    let direction = inverse_rotation * ray.direction;
+    let inv_direction = direction.recip();
+
+    let t0 = (-half_size - origin) * inv_direction;
+    let t1 = (half_size - origin) * inv_direction;
+
+    let t_enter = t0.min(t1).max_element();
+    let t_exit = t0.max(t1).min_element();
+
+    if t_enter > t_exit {
+        return None;
+    }
+
+    let mut root = t_enter;
+    if !ray_limits.surrounds(root) {
+        root = t_exit;
+        if !ray_limits.surrounds(root) {
+            return None;
+        }
+    }
+
+    // the face hit is the one the point is closest to
+    let point = origin + root * direction;
+    let distance = half_size - point.abs();
+
+    // face coordinates, guarded against flat boxes
+    let face = point / half_size.max(glam::Vec3::splat(1e-6)) * 0.5 + 0.5;
+
+    let (local_normal, uv) = if distance.x <= distance.y && distance.x <= distance.z {
+        (
+            glam::vec3(sign(point.x), 0.0, 0.0),
+            glam::vec2(face.z, face.y),
+        )
+    } else if distance.y <= distance.z {
+        (
+            glam::vec3(0.0, sign(point.y), 0.0),
+            glam::vec2(face.x, face.z),
+        )
+    } else {
+        (
+            glam::vec3(0.0, 0.0, sign(point.z)),
+            glam::vec2(face.x, face.y),
+        )
+    };
+
+    return Some(PrimitiveHit {
+        t: root,
+        normal: rotation * local_normal,
+        uv,
+    });
+}
+
+fn hit_disk(disk: &KPrimitive, ray: &Ray, ray_limits: Interval) -> Option<PrimitiveHit> {
+    let center = disk.a.truncate();
+    let radius = disk.a.w;
+    let normal = disk.b.truncate();
+
+    let denom = normal.dot(ray.direction);
+
+    if denom.abs() < 1e-8 {
+        return None;
+    }
+
+    let root = (center - ray.origin).dot(normal) / denom;
+    if !ray_limits.surrounds(root) {
+        return None;
+    }
+
+    let offset = ray.at(root) - center;
+
+    if offset.length_squared() > radius * radius {
+        return None;
+    }
+
+    return Some(PrimitiveHit {
+        t: root,
+        normal,
+        uv: planar_uv(offset, normal) / (2.0 * radius) + 0.5,
+    });
+}
+
+/// closest of the two side roots and the two caps, heights along the axis are scaled by its squared length
+fn hit_cylinder(cylinder: &KPrimitive, ray: &Ray, ray_limits: Interval) -> Option<PrimitiveHit> {
+    let base = cylinder.a.truncate();
+    let radius = cylinder.a.w;
+    let axis = cylinder.b.truncate() - base;
+    let axis_direction = axis.normalize();
+
+    let origin_to_base = ray.origin - base;
+
+    let axis_len_squared = axis.length_squared();
+    let axis_dot_direction = axis.dot(ray.direction);
+    let axis_dot_origin = axis.dot(origin_to_base);
+
+    let mut closest: Option<PrimitiveHit> = None;
+    let mut closest_so_far = ray_limits.max;
+
+    // side, distance to the axis equals the radius
+    let a =
+        axis_len_squared * ray.direction.length_squared() - axis_dot_direction * axis_dot_direction;
+    let half_b =
+        axis_len_squared * origin_to_base.dot(ray.direction) - axis_dot_origin * axis_dot_direction;
+    let c = axis_len_squared * origin_to_base.length_squared()
+        - axis_dot_origin * axis_dot_origin
+        - radius * radius * axis_len_squared;
+
+    let discriminant = (half_b * half_b) - (a * c);
+
+    // a is zero when the ray runs along the axis, only the caps can be hit
+    if a > 0.0 && discriminant >= 0.0 {
+        let sqrtd = discriminant.sqrt();
+
+        for root in [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a] {
+            let height = axis_dot_origin + root * axis_dot_direction;
+            let limits = Interval {
+                min: ray_limits.min,
+                max: closest_so_far,
+            };
+
+            if limits.surrounds(root) && height >= 0.0 && height <= axis_len_squared {
+                let radial =
+                    origin_to_base + root * ray.direction - axis * (height / axis_len_squared);
+                let around = planar_uv(radial, axis_direction);
+
+                closest_so_far = root;
+                closest = Some(PrimitiveHit {
+                    t: root,
+                    normal: radial / radius,
+                    uv: glam::vec2(
+                        (around.y.atan2(around.x) + std::f32::consts::PI)
+                            / (2.0 * std::f32::consts::PI),
+                        height / axis_len_squared,
+                    ),
+                });
+            }
+        }
+    }
+
+    // caps, base at a height of 0 and top at 1
+    if axis_dot_direction != 0.0 {
+        for cap in [0.0, 1.0] {
+            let root = (cap * axis_len_squared - axis_dot_origin) / axis_dot_direction;
+            let offset = origin_to_base + root * ray.direction - cap * axis;
+            let limits = Interval {
+                min: ray_limits.min,
+                max: closest_so_far,
+            };
+
+            if limits.surrounds(root) && offset.length_squared() <= radius * radius {
+                closest_so_far = root;
+                closest = Some(PrimitiveHit {
+                    t: root,
+                    normal: axis_direction * if cap == 1.0 { 1.0 } else { -1.0 },
+                    uv: planar_uv(offset, axis_direction) / (2.0 * radius) + 0.5,
+                });
+            }
+        }
+    }
+
+    return closest;
+}
+
+struct LightSample {
+    /// unit direction towards the light
+    direction: glam::Vec3,
+    /// how far a shadow ray has to go
+    distance: f32,
+    /// arriving radiance divided by the pdf of the direction
+    value: glam::Vec3,
+    /// solid angle pdf, 0 for lights scattered rays can't hit
+    pdf: f32,
+}
+
+fn sample_light(light: &KLight, point: glam::Vec3, rng: &mut Rng) -> LightSample {
+    let mut sample = LightSample {
+        direction: glam::Vec3::ZERO,
+        distance: 0.0,
+        value: glam::Vec3::ZERO,
+        pdf: 0.0,
+    };
+
+    match light.kind {
+        KLight::POINT | KLight::SPOT => {
+            let to_light = light.a.truncate() - point;
+            let distance = to_light.length();
+
+            sample.direction = to_light / distance;
+            sample.distance = distance;
+            sample.value = light.color / (distance * distance);
+
+            if light.kind == KLight::SPOT {
+                let cos_angle = (-sample.direction).dot(light.b.truncate());
+                let edge =
+                    ((cos_angle - light.c.x) / (light.c.y - light.c.x).max(1e-6)).clamp(0.0, 1.0);
+
+                sample.value *= edge * edge * (3.0 - 2.0 * edge);
+            }
+        }
+        KLight::DIRECTIONAL => {
+            sample.direction = -light.b.truncate();
+
+            if light.c.x < 1.0 {
+                sample.direction = rng.in_cone(-light.b.truncate(), light.c.x);
+            }
+
+            sample.distance = INF_F32;
+            sample.value = light.color;
+        }
+        KLight::SPHERE => {
+            let radius = light.a.w.abs();
+            let to_center = light.a.truncate() - point;
+            let distance_squared = to_center.length_squared();
+
+            // no cone to sample from inside
+            if distance_squared <= radius * radius {
+                return sample;
+            }
+
+            let sin_squared_max = radius * radius / distance_squared;
+            let cos_max = (1.0 - sin_squared_max).max(0.0).sqrt();
+
+            sample.direction = rng.in_cone(to_center / distance_squared.sqrt(), cos_max);
+
+            let half_b = sample.direction.dot(to_center);
+            sample.distance = half_b
+                - (half_b * half_b - distance_squared + radius * radius)
+                    .max(0.0)
+                    .sqrt();
+
+            sample.pdf = sphere_light_pdf(sin_squared_max, cos_max);
+            sample.value = light.color / sample.pdf;
+        }
+        KLight::QUAD | KLight::DISK => {
+            let (point_on_light, area, normal) = if light.kind == KLight::QUAD {
+                let u = rng.float();
+                let v = rng.float();
+                let n = light.b.truncate().cross(light.c.truncate());
+
+                (
+                    light.a.truncate() + u * light.b.truncate() + v * light.c.truncate(),
+                    n.length(),
+                    n / n.length(),
+                )
+            } else {
+                let r = light.a.w * rng.float().sqrt();
+                let phi = 2.0 * std::f32::consts::PI * rng.float();
+
+                (
+                    light.a.truncate()
+                        + orthonormal_basis(light.b.truncate())
+                            * glam::vec3(r * phi.cos(), r * phi.sin(), 0.0),
+                    std::f32::consts::PI * light.a.w * light.a.w,
+                    light.b.truncate(),
+                )
+            };
+
+            let to_light = point_on_light - point;
+            let distance = to_light.length();
+
+            sample.direction = to_light / distance;
+            sample.distance = distance;
+
+            let cos_light = normal.dot(sample.direction).abs();
+
+            if cos_light <= 0.0 {
+                return sample;
+            }
+
+            sample.pdf = distance * distance / (area * cos_light);
+            sample.value = light.color / sample.pdf;
+        }
+        _ => {}
+    }
+
+    return sample;
+}
+
+/// solid angle pdf of sample_light picking the unit direction from the point, the hit is where the
+/// direction meets the light. only area lights can be hit, the rest are 0
+fn light_pdf(
+    light: &KLight,
+    point: glam::Vec3,
+    direction: glam::Vec3,
+    hit: &HitRecord,
+    distance: f32,
+) -> f32 {
+    return match light.kind {
+        KLight::SPHERE => {
+            let radius = light.a.w.abs();
+            let distance_squared = (light.a.truncate() - point).length_squared();
+
+            if distance_squared <= radius * radius {
+                return 0.0;
+            }
+
+            let sin_squared_max = radius * radius / distance_squared;
+
+            sphere_light_pdf(sin_squared_max, (1.0 - sin_squared_max).max(0.0).sqrt())
+        }
+        KLight::QUAD => {
+            let area = light.b.truncate().cross(light.c.truncate()).length();
+            distance * distance / (area * hit.normal.dot(direction).abs())
+        }
+        KLight::DISK => {
+            let area = std::f32::consts::PI * light.a.w * light.a.w;
+            distance * distance / (area * hit.normal.dot(direction).abs())
+        }
+        _ => 0.0,
+    };
+}
+
+/// uniform over the cone the sphere covers, 1 - cos is rewritten to stay accurate for far away spheres
+fn sphere_light_pdf(sin_squared_max: f32, cos_max: f32) -> f32 {
+    return 1.0 / (2.0 * std::f32::consts::PI * sin_squared_max / (1.0 + cos_max));
+}
+
+/// pdf of normalize(center + radius * unit_vector()) picking the unit direction, the uniform
+/// density on the offset sphere moved to solid angle at each point the direction crosses it
+fn offset_sphere_pdf(direction: glam::Vec3, center: glam::Vec3, radius: f32) -> f32 {
+    let half_b = direction.dot(center);
+    let discriminant = half_b * half_b - center.length_squared() + radius * radius;
+
+    if discriminant <= 0.0 || radius <= 0.0 {
+        return 0.0;
+    }
+
+    let sqrtd = discriminant.sqrt();
+    let t_near = half_b - sqrtd;
+    let t_far = half_b + sqrtd;
+
+    let distance_squared = if t_near > 0.0 { t_near * t_near } else { 0.0 }
+        + if t_far > 0.0 { t_far * t_far } else { 0.0 };
+
+    return distance_squared / (4.0 * std::f32::consts::PI * radius * sqrtd);
+}
+
+fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let a = pdf * pdf;
+    let b = other_pdf * other_pdf;
+
+    return a / (a + b);
+}
+
+/// sum of octaves of gradient noise, each at twice the frequency and half the amplitude
+fn fbm(point: glam::Vec3, octaves: u32) -> f32 {
+    let mut sum = 0.0;
+    let mut amplitude = 0.5;
+    let mut frequency = 1.0;
+
+    for _ in 0..octaves {
+        sum += amplitude * gradient_noise(point * frequency);
+
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+
+    return sum;
+}
+
+/// perlin style noise with hashed gradients instead of a permutation table
+fn gradient_noise(point: glam::Vec3) -> f32 {
+    let cell = point.floor();
+    let local = point - cell;
+
+    // quintic fade keeps the second derivative continuous
+    let fade = local * local * local * (local * (local * 6.0 - 15.0) + 10.0);
+
+    let mut sum = 0.0;
+
+    for i in 0..8 {
+        let corner = glam::ivec3(i & 1, (i >> 1) & 1, (i >> 2) & 1);
+        let weight = glam::Vec3::select(corner.cmpeq(glam::IVec3::ONE), fade, 1.0 - fade);
+
+        sum += weight.x
+            * weight.y
+            * weight.z
+            * noise_gradient(cell.as_ivec3() + corner).dot(local - corner.as_vec3());
+    }
+
+    return sum;
+}
+
+/// unit gradient of a lattice point
+fn noise_gradient(cell: glam::IVec3) -> glam::Vec3 {
+    let h = random::hash(cell.x as u32 ^ random::hash(cell.y as u32 ^ random::hash(cell.z as u32)));
+    let gradient = glam::vec3(
+        (h & 1023) as f32,
+        ((h >> 10) & 1023) as f32,
+        ((h >> 20) & 1023) as f32,
+    ) / 511.5
+        - 1.0;
+
+    return (gradient + 1e-6).normalize();
+}
+
+/// wgsl sign, zero stays zero
+fn sign(x: f32) -> f32 {
+    if x == 0.0 {
+        return 0.0;
+    }
+
+    return x.signum();
+}
+
+fn near_zero(v: glam::Vec3) -> bool {
+    return v.x < EPSILON && v.y < EPSILON && v.z < EPSILON;
+}
+
+fn reflect(v: glam::Vec3, n: glam::Vec3) -> glam::Vec3 {
+    return v - 2.0 * v.dot(n) * n;
+}
+
+fn refract(uv: glam::Vec3, n: glam::Vec3, etai_over_etat: f32) -> glam::Vec3 {
+    let cos_theta = (-uv).dot(n).min(1.0);
+    let r_out_perp = etai_over_etat * (uv + cos_theta * n);
+    let r_out_para = -(1.0 - r_out_perp.length_squared()).abs().sqrt() * n;
+
+    return r_out_perp + r_out_para;
+}
+
+fn reflectance(cosine: f32, refraction_ratio: f32) -> f32 {
+    let r0 = (1.0 - refraction_ratio) / (1.0 + refraction_ratio);
+    let r0 = r0 * r0;
+
+    return r0 + (1.0 - r0) * (1.0 - cosine).powf(5.0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Midpoint rule over the angle to the lobe's center, the pdfs are symmetric around it
+    fn integrate_lobe(center: glam::Vec3, radius: f32) -> f32 {
+        let steps = 1_000_000;
+        let axis = orthonormal_basis(center);
+        let mut sum = 0.0f64;
+
+        for i in 0..steps {
+            let theta = (i as f64 + 0.5) / steps as f64 * std::f64::consts::PI;
+            let direction = axis * glam::vec3(theta.sin() as f32, 0.0, theta.cos() as f32);
+
+            sum += offset_sphere_pdf(direction, center, radius) as f64 * theta.sin();
+        }
+
+        return (sum * 2.0 * std::f64::consts::PI * std::f64::consts::PI / steps as f64) as f32;
+    }
+
+    #[test]
+    fn scatter_pdfs_integrate_to_one() {
+        let normal = glam::vec3(0.0, 1.0, 0.0);
+
+        // diffuse is the cosine lobe
+        let direction = glam::vec3(0.6, 0.8, 0.0);
+        assert!(
+            (offset_sphere_pdf(direction, normal, 1.0) - 0.8 / std::f32::consts::PI).abs() < 1e-5
+        );
+        assert_eq!(offset_sphere_pdf(-direction, normal, 1.0), 0.0);
+
+        for radius in [1.0, 0.3, 0.7, 1.5] {
+            let total = integrate_lobe(glam::vec3(0.0, 0.6, 0.8), radius);
+            assert!((total - 1.0).abs() < 0.01, "radius {}: {}", radius, total);
+        }
+    }
+
+    #[test]
+    fn light_pdf_matches_the_sampled_pdf() {
+        let mid = glam::UVec4::ZERO;
+        let radiance = glam::Vec3::ONE;
+        let point = glam::vec3(0.3, -0.2, 0.1);
+
+        let lights = [
+            KLight::area(
+                &KPrimitive::sphere(glam::vec3(1.0, 4.0, -2.0), 0.5, mid),
+                radiance,
+            ),
+            KLight::area(
+                &KPrimitive::quad(
+                    glam::vec3(-1.0, 3.0, -1.0),
+                    glam::vec3(2.0, 0.0, 0.0),
+                    glam::vec3(0.0, 0.5, 1.5),
+                    mid,
+                ),
+                radiance,
+            ),
+            KLight::area(
+                &KPrimitive::disk(
+                    glam::vec3(0.0, 3.0, 0.0),
+                    glam::vec3(0.2, -1.0, 0.1),
+                    0.8,
+                    mid,
+                ),
+                radiance,
+            ),
+        ];
+
+        let mut rng = Rng { state: 42 };
+
+        for light in lights.iter().map(|light| return light.as_ref().unwrap()) {
+            for _ in 0..64 {
+                let sample = sample_light(light, point, &mut rng);
+
+                let ray = Ray {
+                    origin: point,
+                    direction: sample.direction,
+                };
+                let limits = Interval {
+                    min: 0.0,
+                    max: INF_F32,
+                };
+
+                // the light seen as a primitive, as a scattered ray would find it
+                let primitive = KPrimitive {
+                    a: light.a,
+                    b: light.b,
+                    c: light.c,
+                    mid,
+                    kind: match light.kind {
+                        KLight::SPHERE => KPrimitive::SPHERE,
+                        KLight::QUAD => KPrimitive::QUAD,
+                        _ => KPrimitive::DISK,
+                    },
+                };
+
+                let mut hit = HitRecord::default();
+                assert!(hit_primitive(&primitive, &ray, limits, &mut hit));
+                assert!((hit.t - sample.distance).abs() < 1e-3);
+
+                let pdf = light_pdf(light, point, sample.direction, &hit, hit.t);
+                assert!((pdf - sample.pdf).abs() < sample.pdf * 1e-3);
+            }
+        }
+    }
+}
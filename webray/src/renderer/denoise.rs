@@ -0,0 +1,229 @@
+use super::aov::AOV_LAYERS;
+
+/// B3 spline, the 5 taps of the a-trous filter along each axis
+const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// Edge avoiding a-trous wavelet filter (Dammertz et al. 2010) over the linear image,
+/// guided by the albedo and normal aovs
+#[derive(Debug, Clone, Copy)]
+pub struct DenoiseConfig {
+    /// every iteration doubles the distance between the taps
+    pub iterations: u32,
+    /// tone mapped color difference, halved every iteration
+    pub color_sigma: f32,
+    pub normal_sigma: f32,
+    pub albedo_sigma: f32,
+}
+
+shader_types! {
+    mod layout;
+
+    /// Settings of a single iteration, same layout as the denoise kernel's uniform
+    #[derive(Debug, encase::ShaderType)]
+    pub struct DenoiseIteration {
+        /// pixels between the taps
+        pub step: u32,
+        pub color_sigma: f32,
+        pub normal_sigma: f32,
+        pub albedo_sigma: f32,
+    }
+}
+
+impl DenoiseConfig {
+    pub fn iteration(&self, iteration: u32) -> DenoiseIteration {
+        let step = 1 << iteration;
+
+        return DenoiseIteration {
+            step,
+            color_sigma: self.color_sigma / step as f32,
+            normal_sigma: self.normal_sigma,
+            albedo_sigma: self.albedo_sigma,
+        };
+    }
+}
+
+impl DenoiseIteration {
+    pub fn as_wgsl_bytes(&self) -> encase::internal::Result<Vec<u8>> {
+        let mut buffer = encase::UniformBuffer::new(Vec::new());
+        buffer.write(self)?;
+        return Ok(buffer.into_inner());
+    }
+}
+
+/// Filters the rgba32 image, aovs are the layers of the kernel's aov texture.
+/// Mirrors denoise.wgsl
+pub fn denoise(
+    config: &DenoiseConfig,
+    width: u32,
+    height: u32,
+    hdr: &[f32],
+    aovs: &[Vec<f32>],
+) -> Vec<f32> {
+    debug_assert_eq!(aovs.len(), AOV_LAYERS as usize);
+
+    let albedo = &aovs[0];
+    let normal = &aovs[1];
+
+    let mut input = hdr.to_vec();
+    let mut output = vec![0f32; hdr.len()];
+
+    let load = |buffer: &[f32], index: usize| {
+        return glam::Vec3::from_slice(&buffer[index * 4..index * 4 + 3]);
+    };
+
+    for i in 0..config.iterations {
+        let iteration = config.iteration(i);
+
+        for y in 0..height as i32 {
+            for x in 0..width as i32 {
+                let center = (y * width as i32 + x) as usize;
+
+                let color = load(&input, center);
+                let mapped = color / (1.0 + color);
+                let pixel_normal = load(normal, center);
+                let pixel_albedo = load(albedo, center);
+
+                let mut sum = glam::Vec3::ZERO;
+                let mut weight_sum = 0.0;
+
+                for (ky, hy) in KERNEL.iter().enumerate() {
+                    for (kx, hx) in KERNEL.iter().enumerate() {
+                        let qx = x + (kx as i32 - 2) * iteration.step as i32;
+                        let qy = y + (ky as i32 - 2) * iteration.step as i32;
+
+                        if qx < 0 || qy < 0 || qx >= width as i32 || qy >= height as i32 {
+                            continue;
+                        }
+
+                        let tap = (qy * width as i32 + qx) as usize;
+                        let tap_color = load(&input, tap);
+
+                        let weight = hx
+                            * hy
+                            * edge_weight(
+                                mapped,
+                                tap_color / (1.0 + tap_color),
+                                iteration.color_sigma,
+                            )
+                            * edge_weight(pixel_normal, load(normal, tap), iteration.normal_sigma)
+                            * edge_weight(pixel_albedo, load(albedo, tap), iteration.albedo_sigma);
+
+                        sum += tap_color * weight;
+                        weight_sum += weight;
+                    }
+                }
+
+                // the center tap always has a weight so the sum is never zero
+                let filtered = sum / weight_sum;
+
+                output[center * 4..center * 4 + 3].copy_from_slice(&filtered.to_array());
+                output[center * 4 + 3] = input[center * 4 + 3];
+            }
+        }
+
+        std::mem::swap(&mut input, &mut output);
+    }
+
+    return input;
+}
+
+/// Falls off with the squared distance between the features
+fn edge_weight(a: glam::Vec3, b: glam::Vec3, sigma: f32) -> f32 {
+    return (-a.distance_squared(b) / (sigma * sigma)).exp();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: DenoiseConfig = DenoiseConfig {
+        iterations: 3,
+        color_sigma: 0.5,
+        normal_sigma: 0.3,
+        albedo_sigma: 0.1,
+    };
+
+    fn layer(width: u32, height: u32, value: impl Fn(u32, u32) -> glam::Vec3) -> Vec<f32> {
+        let mut data = Vec::new();
+
+        for y in 0..height {
+            for x in 0..width {
+                data.extend_from_slice(&value(x, y).extend(1.0).to_array());
+            }
+        }
+
+        return data;
+    }
+
+    /// Mean squared difference from a constant color
+    fn error(image: &[f32], expected: f32) -> f32 {
+        let pixels = image.len() / 4;
+
+        return image
+            .chunks_exact(4)
+            .map(|p| {
+                return p[..3]
+                    .iter()
+                    .map(|c| return (c - expected).powi(2))
+                    .sum::<f32>();
+            })
+            .sum::<f32>()
+            / pixels as f32;
+    }
+
+    #[test]
+    fn noise_is_reduced_and_edges_are_kept() {
+        let (width, height) = (32, 16);
+
+        // left half faces +z, right half +x, both grey with the same albedo
+        let normal = layer(width, height, |x, _| {
+            return if x < width / 2 {
+                glam::Vec3::Z
+            } else {
+                glam::Vec3::X
+            };
+        });
+        let albedo = layer(width, height, |_, _| return glam::Vec3::splat(0.5));
+        let aovs = vec![
+            albedo,
+            normal,
+            layer(width, height, |_, _| return glam::Vec3::ZERO),
+        ];
+
+        let noisy = layer(width, height, |x, y| {
+            let base = if x < width / 2 { 0.2 } else { 0.8 };
+            let noise = if (x * 7 + y * 13) % 3 == 0 {
+                0.1
+            } else {
+                -0.05
+            };
+
+            return glam::Vec3::splat(base + noise);
+        });
+
+        let denoised = denoise(&CONFIG, width, height, &noisy, &aovs);
+
+        let half = |image: &[f32], right: bool| {
+            return image
+                .chunks_exact(4 * width as usize / 2)
+                .skip(right as usize)
+                .step_by(2)
+                .flatten()
+                .copied()
+                .collect::<Vec<f32>>();
+        };
+
+        for (right, expected) in [(false, 0.2), (true, 0.8)] {
+            let before = error(&half(&noisy, right), expected);
+            let after = error(&half(&denoised, right), expected);
+
+            // the halves don't bleed into each other across the normal edge
+            assert!(
+                after < before * 0.25,
+                "error before {}, after {}",
+                before,
+                after
+            );
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use std::mem::size_of;
+
+use crate::core::{error::WebRayError, gpu::Gpu};
+
+use super::{
+    buffers::KernelBuffers,
+    config::SystemConfig,
+    denoise::{DenoiseConfig, DenoiseIteration},
+};
+
+/// Pixels per side of a denoise workgroup
+const WORKGROUP_SIZE: u32 = 8;
+
+/// Runs denoise.wgsl over the render texture, every iteration filters the
+/// render texture into the denoise texture which is then copied back
+pub struct Denoiser {
+    pipeline: wgpu::ComputePipeline,
+    layout: wgpu::BindGroupLayout,
+    iteration: wgpu::Buffer,
+}
+
+impl Denoiser {
+    pub fn new(gpu: &Gpu) -> Self {
+        let layout = Self::bind_group_layout(gpu);
+        let pipeline = Self::create_pipeline(gpu, &layout);
+
+        let iteration = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Denoise iteration buffer"),
+            size: size_of::<DenoiseIteration>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        return Denoiser {
+            pipeline,
+            layout,
+            iteration,
+        };
+    }
+
+    pub fn execute(
+        &self,
+        gpu: &Gpu,
+        config: &DenoiseConfig,
+        system_config: &SystemConfig,
+        buffers: &KernelBuffers,
+    ) -> Result<(), WebRayError> {
+        let width = system_config.image.width;
+        let height = system_config.image.height;
+
+        let bind_group = self.bind_group(gpu, buffers);
+
+        for i in 0..config.iterations {
+            gpu.queue.write_buffer(
+                &self.iteration,
+                0,
+                &config.iteration(i).as_wgsl_bytes()?[..],
+            );
+
+            let mut encoder = gpu
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Denoise encoder"),
+                });
+
+            {
+                let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("Denoise pass"),
+                    timestamp_writes: None,
+                });
+
+                pass.set_pipeline(&self.pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.dispatch_workgroups(
+                    width.div_ceil(WORKGROUP_SIZE),
+                    height.div_ceil(WORKGROUP_SIZE),
+                    1,
+                );
+            }
+
+            encoder.copy_texture_to_texture(
+                buffers.denoise.as_image_copy(),
+                buffers.render.as_image_copy(),
+                wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+            );
+
+            let submission_index = gpu.queue.submit([encoder.finish()]);
+
+            gpu.wait(Some(submission_index))?;
+        }
+
+        log::info!("Denoised, iterations: {}", config.iterations);
+
+        return Ok(());
+    }
+
+    fn bind_group(&self, gpu: &Gpu, buffers: &KernelBuffers) -> wgpu::BindGroup {
+        return gpu.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Denoise bind group"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(
+                        &buffers
+                            .render
+                            .create_view(&wgpu::TextureViewDescriptor::default()),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&buffers.aovs.create_view(
+                        &wgpu::TextureViewDescriptor {
+                            dimension: Some(wgpu::TextureViewDimension::D2Array),
+                            ..Default::default()
+                        },
+                    )),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(
+                        &buffers
+                            .denoise
+                            .create_view(&wgpu::TextureViewDescriptor::default()),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.iteration.as_entire_binding(),
+                },
+            ],
+        });
+    }
+
+    fn bind_group_layout(gpu: &Gpu) -> wgpu::BindGroupLayout {
+        return gpu
+            .device
+            .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("Denoise bind group layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::StorageTexture {
+                            access: wgpu::StorageTextureAccess::WriteOnly,
+                            format: wgpu::TextureFormat::Rgba32Float,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ],
+            });
+    }
+
+    fn create_pipeline(gpu: &Gpu, layout: &wgpu::BindGroupLayout) -> wgpu::ComputePipeline {
+        let shader = gpu
+            .device
+            .create_shader_module(wgpu::include_wgsl!("../shaders/denoise.wgsl"));
+
+        let pipeline_layout = gpu
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Denoise pipeline layout"),
+                bind_group_layouts: &[layout],
+                push_constant_ranges: &[],
+            });
+
+        return gpu
+            .device
+            .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                label: Some("Denoise pipeline"),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: "main",
+            });
+    }
+}
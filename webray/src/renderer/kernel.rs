@@ -1,126 +1,407 @@
-use crate::core::gpu::Gpu;
+use crate::{
+    core::{error::WebRayError, gpu::Gpu},
+    utils::time,
+};
 
 use super::{
+    aov::{Aov, AovImage},
     bindings::KernelBindings,
     buffers::KernelBuffers,
-    config::{ExecutionConfig, ExecutionContext, SystemConfig, TileSize},
+    cancel::{resolve_partial, RenderStatus, StopCondition},
+    config::{padded_row_size, ExecutionContext, KernelConfig, SystemConfig, TileSize},
+    denoise_gpu::Denoiser,
+    scheduler::{DispatchScheduler, CALIBRATION_TILE},
+    RenderEvent, Tile,
 };
 
+/// Pixels per side of a kernel workgroup, same as WORKGROUP_SIZE in webray.wgsl
+const WORKGROUP_SIZE: u32 = 8;
+
 pub struct Kernel {
     pub pipeline: wgpu::ComputePipeline,
+    denoiser: Denoiser,
 }
 
 impl Kernel {
     pub fn new(gpu: &Gpu, bindings: &KernelBindings) -> Self {
         let pipeline = Kernel::create_pipeline(gpu, bindings);
+        let denoiser = Denoiser::new(gpu);
 
-        return Kernel { pipeline };
+        return Kernel { pipeline, denoiser };
     }
 
     pub async fn execute(
         &self,
         gpu: &Gpu,
-        system_config: &SystemConfig,
-        execution_config: &ExecutionConfig,
+        config: &KernelConfig,
         bindings: &KernelBindings,
         buffers: &KernelBuffers,
-    ) -> Result<Vec<u8>, ()> {
-        let tile_count = Self::count_tiles(
-            system_config.image.width,
-            system_config.image.height,
-            execution_config.tile_size,
-        );
+        stop: &StopCondition,
+        mut on_event: Option<&mut dyn FnMut(RenderEvent)>,
+    ) -> Result<(Vec<f32>, Vec<AovImage>, RenderStatus), WebRayError> {
+        let system_config = &config.system;
+        let execution_config = &config.execution;
+
+        let width = system_config.image.width;
+        let height = system_config.image.height;
+
+        let mut scheduler = DispatchScheduler::new(execution_config.dispatch_budget);
+
+        let tile_edge = match execution_config.tile_size {
+            TileSize::Full => width.max(height),
+            TileSize::Tile(size) => size,
+            TileSize::Auto => {
+                self.auto_tile_edge(gpu, system_config, bindings, buffers, &mut scheduler)
+                    .await?
+            }
+        };
+
+        let tile_count = glam::uvec2(width.div_ceil(tile_edge), height.div_ceil(tile_edge));
 
         log::info!(
-            "Tile count: {}, width: {}, height: {}",
+            "Tile count: {}, tile size: {}, width: {}, height: {}",
             tile_count.x * tile_count.y,
-            system_config.image.width,
-            system_config.image.height
+            tile_edge,
+            width,
+            height
         );
 
-        match execution_config.tile_size {
-            TileSize::Full => {
-                log::info!(
-                    "Rendering full tile, width: {}, height: {}",
-                    system_config.image.width,
-                    system_config.image.height
-                );
+        let pass_count = execution_config.pass_count();
+
+        for pass in 0..pass_count {
+            let sample_offset = pass * execution_config.samples_per_pass;
+            let sample_count = execution_config
+                .samples_per_pass
+                .min(execution_config.samples - sample_offset);
 
-                self.render_tile(
+            log::info!(
+                "Rendering pass {}/{}, samples: {}",
+                pass + 1,
+                pass_count,
+                sample_offset + sample_count
+            );
+
+            let stopped = self
+                .render_pass(
                     gpu,
-                    glam::uvec2(0, 0),
-                    system_config.image.width,
-                    system_config.image.height,
+                    config,
+                    tile_edge,
+                    tile_count,
+                    pass,
+                    glam::uvec2(sample_offset, sample_count),
                     bindings,
                     buffers,
-                );
+                    &mut scheduler,
+                    stop,
+                    &mut on_event,
+                )
+                .await?;
+
+            if let Some((status, skipped)) = stopped {
+                return self
+                    .stopped_result(gpu, config, buffers, pass, status, &skipped)
+                    .await;
+            }
+
+            // the accumulation is left noisy, only the resolved image is filtered
+            if let Some(denoise) = &config.denoise {
+                self.denoiser
+                    .execute(gpu, denoise, system_config, buffers)?;
+            }
+
+            // final pass is read back below
+            if pass + 1 == pass_count {
+                break;
+            }
+
+            self.copy_accumulation(gpu, system_config, buffers)?;
+
+            if let Some(callback) = on_event.as_mut() {
+                let hdr = self
+                    .map_result(gpu, system_config, buffers, &buffers.render, 0)
+                    .await?;
+
+                callback(RenderEvent::PassFinished {
+                    samples: sample_offset + sample_count,
+                    total_samples: execution_config.samples,
+                    image: config.post.apply(&hdr),
+                });
+            }
+        }
+
+        log::info!("Reading result buffer");
+
+        let result = self
+            .map_result(gpu, system_config, buffers, &buffers.render, 0)
+            .await?;
+
+        let aovs = self
+            .map_aovs(gpu, system_config, &execution_config.aovs, buffers)
+            .await?;
+
+        return Ok((result, aovs, RenderStatus::Complete));
+    }
+
+    /// Result of a render stopped in the given pass, the samples taken so far are averaged
+    /// per pixel from the accumulation and returned without denoising
+    async fn stopped_result(
+        &self,
+        gpu: &Gpu,
+        config: &KernelConfig,
+        buffers: &KernelBuffers,
+        pass: u32,
+        status: RenderStatus,
+        skipped: &[Tile],
+    ) -> Result<(Vec<f32>, Vec<AovImage>, RenderStatus), WebRayError> {
+        let system_config = &config.system;
+
+        log::warn!(
+            "Render stopped ({:?}) in pass {}, {} tiles skipped",
+            status,
+            pass + 1,
+            skipped.len()
+        );
+
+        let mut accumulation = self
+            .map_result(gpu, system_config, buffers, &buffers.accumulation_out, 0)
+            .await?;
+
+        // aovs are only written by the first pass
+        let aovs = if pass == 0 {
+            // tiles the first pass didn't reach still hold the previous render
+            for tile in skipped {
+                let width = system_config.image.width;
+
+                for y in tile.y..tile.y + tile.height {
+                    let start = ((y * width + tile.x) * 4) as usize;
+
+                    accumulation[start..start + (tile.width * 4) as usize].fill(0.0);
+                }
             }
-            TileSize::Tile(size) => {
-                let mut id = 1;
-                let total_tile_count = tile_count.x * tile_count.y;
-
-                for x in 0..tile_count.x {
-                    for y in 0..tile_count.y {
-                        let tile_position = glam::uvec2(x, y);
-
-                        let width = ((x + 1) * size).min(system_config.image.width) - (x * size);
-                        let height = ((y + 1) * size).min(system_config.image.height) - (y * size);
-
-                        log::info!(
-                            "Rendering ({:07.3}%) tile {}: {}, width: {}, height: {}",
-                            ((id as f32) * 100.0) / (total_tile_count as f32),
-                            id,
-                            tile_position,
-                            width,
-                            height
-                        );
-
-                        self.render_tile(
+
+            Vec::new()
+        } else {
+            self.map_aovs(gpu, system_config, &config.execution.aovs, buffers)
+                .await?
+        };
+
+        return Ok((resolve_partial(&accumulation), aovs, status));
+    }
+
+    /// Measures a single sample over a calibration tile and picks the largest tile
+    /// that stays in the dispatch budget. The first pass renders over the calibration tile
+    async fn auto_tile_edge(
+        &self,
+        gpu: &Gpu,
+        system_config: &SystemConfig,
+        bindings: &KernelBindings,
+        buffers: &KernelBuffers,
+        scheduler: &mut DispatchScheduler,
+    ) -> Result<u32, WebRayError> {
+        let width = system_config.image.width;
+        let height = system_config.image.height;
+
+        let tile = Tile {
+            index: 0,
+            count: 1,
+            x: 0,
+            y: 0,
+            width: CALIBRATION_TILE.min(width),
+            height: CALIBRATION_TILE.min(height),
+        };
+
+        let elapsed = self
+            .render_tile(gpu, &tile, glam::uvec2(0, 1), bindings, buffers)
+            .await?;
+
+        scheduler.record(tile.width * tile.height, 1, elapsed);
+
+        let edge = scheduler.tile_size(width, height);
+
+        log::info!(
+            "Auto tile size: {}, calibration took {:.3}ms",
+            edge,
+            elapsed
+        );
+
+        return Ok(edge);
+    }
+
+    /// samples is (offset, count) of the samples taken by this pass, every tile takes
+    /// them in as many batches as the scheduler needs to stay in the dispatch budget.
+    /// Tiles are only read back when someone is listening for them.
+    /// Returns the status and the tiles that weren't rendered when stopped early
+    #[allow(clippy::too_many_arguments)]
+    async fn render_pass(
+        &self,
+        gpu: &Gpu,
+        config: &KernelConfig,
+        size: u32,
+        tile_count: glam::UVec2,
+        pass: u32,
+        samples: glam::UVec2,
+        bindings: &KernelBindings,
+        buffers: &KernelBuffers,
+        scheduler: &mut DispatchScheduler,
+        stop: &StopCondition,
+        on_event: &mut Option<&mut dyn FnMut(RenderEvent)>,
+    ) -> Result<Option<(RenderStatus, Vec<Tile>)>, WebRayError> {
+        let image = &config.system.image;
+
+        let tiles_per_pass = tile_count.x * tile_count.y;
+        let mut id = 0;
+
+        let mut stopped = None;
+        let mut skipped = Vec::new();
+
+        for x in 0..tile_count.x {
+            for y in 0..tile_count.y {
+                let tile = Tile {
+                    index: pass * tiles_per_pass + id,
+                    count: config.execution.pass_count() * tiles_per_pass,
+                    x: x * size,
+                    y: y * size,
+                    width: ((x + 1) * size).min(image.width) - (x * size),
+                    height: ((y + 1) * size).min(image.height) - (y * size),
+                };
+
+                if stopped.is_none() {
+                    // the browser only gets to run cancel between tasks
+                    #[cfg(target_arch = "wasm32")]
+                    super::cancel::yield_to_event_loop().await;
+
+                    stopped = stop.check();
+                }
+
+                if stopped.is_some() {
+                    skipped.push(tile);
+                    id += 1;
+                    continue;
+                }
+
+                log::info!(
+                    "Rendering ({:07.3}%) tile {}: {}, width: {}, height: {}",
+                    ((id + 1) as f32 * 100.0) / (tiles_per_pass as f32),
+                    id + 1,
+                    glam::uvec2(x, y),
+                    tile.width,
+                    tile.height
+                );
+
+                if let Some(callback) = on_event.as_mut() {
+                    callback(RenderEvent::TileStarted { tile });
+                }
+
+                let pixels = tile.width * tile.height;
+                let mut taken = 0;
+
+                loop {
+                    let batch = scheduler.samples(pixels, samples.y - taken);
+
+                    let elapsed = self
+                        .render_tile(
                             gpu,
-                            tile_position * size,
-                            width,
-                            height,
+                            &tile,
+                            glam::uvec2(samples.x + taken, batch),
                             bindings,
                             buffers,
-                        );
+                        )
+                        .await?;
+
+                    log::debug!("Batch of {} samples took {:.3}ms", batch, elapsed);
 
-                        id += 1;
+                    scheduler.record(pixels, batch, elapsed);
+                    taken += batch;
+
+                    if taken == samples.y {
+                        break;
+                    }
+
+                    // the next batch adds to the samples of this one
+                    self.copy_tile_accumulation(gpu, buffers, &tile);
+
+                    #[cfg(target_arch = "wasm32")]
+                    super::cancel::yield_to_event_loop().await;
+
+                    // pixels of a partly rendered tile are resolved with the samples they have
+                    stopped = stop.check();
+
+                    if stopped.is_some() {
+                        break;
                     }
                 }
+
+                if let Some(callback) = on_event.as_mut() {
+                    let hdr = self.map_tile(gpu, buffers, &tile).await?;
+
+                    callback(RenderEvent::TileFinished {
+                        tile,
+                        image: config.post.apply(&hdr),
+                    });
+                }
+
+                id += 1;
             }
         }
 
-        // scoped threads can borrow non-'static data as scope guarantees
-        // all threads will join at the end of the scope
-        // std::thread::scope(|scope| {
-        //     let _ = scope.spawn(move || {
+        return Ok(stopped.map(|status| return (status, skipped)));
+    }
+
+    /// samples is (offset, count) of the samples taken by this batch, tiles needing more
+    /// workgroups than the device allows along a dimension are split over several dispatches.
+    /// Returns the milliseconds the batch took
+    async fn render_tile(
+        &self,
+        gpu: &Gpu,
+        tile: &Tile,
+        samples: glam::UVec2,
+        bindings: &KernelBindings,
+        buffers: &KernelBuffers,
+    ) -> Result<f64, WebRayError> {
+        let start = time::now_ms();
 
-        //     });
-        // });
+        let max_workgroups = gpu.device.limits().max_compute_workgroups_per_dimension;
 
-        log::info!("Reading result buffer");
+        for (offset, size) in
+            Self::split_dispatches(glam::uvec2(tile.width, tile.height), max_workgroups)
+        {
+            self.dispatch(
+                gpu,
+                glam::uvec2(tile.x, tile.y) + offset,
+                size,
+                samples,
+                bindings,
+                buffers,
+            )?;
+        }
 
-        let result = self.map_result(gpu, system_config, buffers).await;
+        Self::wait_for_queue(gpu).await?;
 
-        return result;
+        return Ok(time::now_ms() - start);
     }
 
-    fn render_tile(
+    /// Submits a dispatch over the area, one invocation per pixel
+    fn dispatch(
         &self,
         gpu: &Gpu,
         tile_position: glam::UVec2,
-        width: u32,
-        height: u32,
+        tile_size: glam::UVec2,
+        samples: glam::UVec2,
         bindings: &KernelBindings,
         buffers: &KernelBuffers,
-    ) {
+    ) -> Result<(), WebRayError> {
         // Write execution context
-        let execution_context = ExecutionContext { tile_position };
+        let execution_context = ExecutionContext {
+            tile_position,
+            tile_size,
+            sample_offset: samples.x,
+            sample_count: samples.y,
+        };
 
         gpu.queue.write_buffer(
             &buffers.execution_context,
             0,
-            &execution_context.as_wgsl_bytes().unwrap()[..],
+            &execution_context.as_wgsl_bytes()?[..],
         );
 
         // Setup commands
@@ -141,23 +422,188 @@ impl Kernel {
             pass.set_bind_group(0, bindings.system_binding.as_ref().unwrap(), &[]);
             pass.set_bind_group(1, bindings.user_binding.as_ref().unwrap(), &[]);
             pass.set_bind_group(2, bindings.execution_binding.as_ref().unwrap(), &[]);
-            pass.dispatch_workgroups(width, height, 1);
+            pass.dispatch_workgroups(
+                tile_size.x.div_ceil(WORKGROUP_SIZE),
+                tile_size.y.div_ceil(WORKGROUP_SIZE),
+                1,
+            );
         }
 
         // Submit commands
+        gpu.queue.submit([encoder.finish()]);
+
+        return Ok(());
+    }
+
+    /// Resolves once everything submitted so far is done, on wasm the browser is
+    /// left to run meanwhile as polling there doesn't block
+    async fn wait_for_queue(gpu: &Gpu) -> Result<(), WebRayError> {
+        let (sender, receiver) = flume::bounded(1);
+
+        gpu.queue.on_submitted_work_done(move || {
+            let _ = sender.send(());
+        });
+
+        gpu.wait(None)?;
+
+        let _ = receiver.recv_async().await;
+
+        return Ok(());
+    }
+
+    /// Copies the tile's running sum into the accumulation the next batch adds to
+    fn copy_tile_accumulation(&self, gpu: &Gpu, buffers: &KernelBuffers, tile: &Tile) {
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Tile accumulation encoder"),
+            });
+
+        let origin = wgpu::Origin3d {
+            x: tile.x,
+            y: tile.y,
+            z: 0,
+        };
+
+        encoder.copy_texture_to_texture(
+            wgpu::ImageCopyTexture {
+                origin,
+                ..buffers.accumulation_out.as_image_copy()
+            },
+            wgpu::ImageCopyTexture {
+                origin,
+                ..buffers.accumulation_in.as_image_copy()
+            },
+            wgpu::Extent3d {
+                width: tile.width,
+                height: tile.height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        // queued before the next batch's dispatch, no need to wait
+        gpu.queue.submit([encoder.finish()]);
+    }
+
+    /// Makes the running sum written by the last pass readable by the next one
+    fn copy_accumulation(
+        &self,
+        gpu: &Gpu,
+        system_config: &SystemConfig,
+        buffers: &KernelBuffers,
+    ) -> Result<(), WebRayError> {
+        let mut encoder = gpu
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Accumulation encoder"),
+            });
+
+        encoder.copy_texture_to_texture(
+            buffers.accumulation_out.as_image_copy(),
+            buffers.accumulation_in.as_image_copy(),
+            wgpu::Extent3d {
+                width: system_config.image.width,
+                height: system_config.image.height,
+                depth_or_array_layers: 1,
+            },
+        );
+
         let submission_index = gpu.queue.submit([encoder.finish()]);
 
-        gpu.device
-            .poll(wgpu::Maintain::WaitForSubmissionIndex(submission_index))
-            .panic_on_timeout();
+        return gpu.wait(Some(submission_index));
+    }
+
+    /// Reads the aovs back, layers shared by several aovs are only read once
+    async fn map_aovs(
+        &self,
+        gpu: &Gpu,
+        kernel_config: &SystemConfig,
+        aovs: &[Aov],
+        buffers: &KernelBuffers,
+    ) -> Result<Vec<AovImage>, WebRayError> {
+        let mut layers: Vec<(u32, Vec<f32>)> = Vec::new();
+        let mut images = Vec::with_capacity(aovs.len());
+
+        for aov in aovs {
+            let layer = aov.layer();
+
+            if !layers.iter().any(|(l, _)| return *l == layer) {
+                log::info!("Reading aov layer {}", layer);
+
+                let data = self
+                    .map_result(gpu, kernel_config, buffers, &buffers.aovs, layer)
+                    .await?;
+
+                layers.push((layer, data));
+            }
+
+            let (_, data) = layers.iter().find(|(l, _)| return *l == layer).unwrap();
+
+            images.push(AovImage {
+                aov: *aov,
+                data: aov.extract(data),
+            });
+        }
+
+        return Ok(images);
     }
 
+    /// Reads a layer of an image sized rgba32float texture through the result buffer
     async fn map_result(
         &self,
         gpu: &Gpu,
         kernel_config: &SystemConfig,
         buffers: &KernelBuffers,
-    ) -> Result<Vec<u8>, ()> {
+        texture: &wgpu::Texture,
+        layer: u32,
+    ) -> Result<Vec<f32>, WebRayError> {
+        return Self::map_texture(
+            gpu,
+            texture,
+            glam::uvec3(0, 0, layer),
+            glam::uvec2(kernel_config.image.width, kernel_config.image.height),
+            &buffers.result,
+        )
+        .await;
+    }
+
+    /// Reads the tile's pixels of the render texture, through a staging
+    /// buffer as the result buffer is mapped by the image readbacks
+    async fn map_tile(
+        &self,
+        gpu: &Gpu,
+        buffers: &KernelBuffers,
+        tile: &Tile,
+    ) -> Result<Vec<f32>, WebRayError> {
+        let staging = gpu.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Tile result buffer"),
+            size: (padded_row_size(tile.width) * tile.height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        return Self::map_texture(
+            gpu,
+            &buffers.render,
+            glam::uvec3(tile.x, tile.y, 0),
+            glam::uvec2(tile.width, tile.height),
+            &staging,
+        )
+        .await;
+    }
+
+    /// Copies the area of an rgba32float texture at origin (x, y, layer) to the buffer and reads
+    /// it back, the buffer holds the area's rows padded to COPY_BYTES_PER_ROW_ALIGNMENT
+    async fn map_texture(
+        gpu: &Gpu,
+        texture: &wgpu::Texture,
+        origin: glam::UVec3,
+        size: glam::UVec2,
+        buffer: &wgpu::Buffer,
+    ) -> Result<Vec<f32>, WebRayError> {
+        let row_size = size.x * 16;
+        let padded_row_size = padded_row_size(size.x);
+
         let mut encoder = gpu
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
@@ -166,58 +612,78 @@ impl Kernel {
 
         encoder.copy_texture_to_buffer(
             wgpu::ImageCopyTexture {
-                texture: &buffers.render,
+                texture,
                 mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
+                origin: wgpu::Origin3d {
+                    x: origin.x,
+                    y: origin.y,
+                    z: origin.z,
+                },
                 aspect: wgpu::TextureAspect::All,
             },
             wgpu::ImageCopyBuffer {
-                buffer: &buffers.result,
+                buffer,
                 layout: wgpu::ImageDataLayout {
                     offset: 0,
-                    bytes_per_row: Some(kernel_config.image.width * 4),
-                    rows_per_image: Some(kernel_config.image.height),
+                    bytes_per_row: Some(padded_row_size),
+                    rows_per_image: Some(size.y),
                 },
             },
             wgpu::Extent3d {
-                width: kernel_config.image.width,
-                height: kernel_config.image.height,
+                width: size.x,
+                height: size.y,
                 depth_or_array_layers: 1,
             },
         );
 
         let submission_index = gpu.queue.submit([encoder.finish()]);
 
-        let (sender, receiver) = flume::bounded(1);
+        Self::map_buffer(gpu, buffer, submission_index).await?;
+
+        // 4 floats per pixel, allocated as f32 so the bytes are correctly aligned
+        let mut output = vec![0f32; (size.x * size.y * 4) as usize];
+
+        {
+            let view = buffer.slice(..).get_mapped_range();
 
-        let result_slice = buffers.result.slice(..);
+            for (row, padded_row) in bytemuck::cast_slice_mut::<f32, u8>(&mut output)
+                .chunks_exact_mut(row_size as usize)
+                .zip(view.chunks_exact(padded_row_size as usize))
+            {
+                row.copy_from_slice(&padded_row[..row_size as usize]);
+            }
+        }
+
+        buffer.unmap();
 
-        result_slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
+        return Ok(output);
+    }
+
+    /// Maps the whole buffer for reading once the submission is done
+    async fn map_buffer(
+        gpu: &Gpu,
+        buffer: &wgpu::Buffer,
+        submission_index: wgpu::SubmissionIndex,
+    ) -> Result<(), WebRayError> {
+        let (sender, receiver) = flume::bounded(1);
+
+        buffer.slice(..).map_async(wgpu::MapMode::Read, move |v| {
+            let _ = sender.send(v);
+        });
 
         // Wait for result
         // wgpu has an internal timeout of 5 secs
         // wgpu has a bug where timeout is treated as success which triggers the map_async callback
         // causing early mapping when without the results being populated
         // https://github.com/gfx-rs/wgpu/issues/3601
-        gpu.device
-            .poll(wgpu::Maintain::WaitForSubmissionIndex(submission_index))
-            .panic_on_timeout();
-
-        let mut output = vec![0u8; (kernel_config.result_size()) as usize];
-
-        if let Ok(Ok(_)) = receiver.recv_async().await {
-            let result_view = buffers.result.slice(..).get_mapped_range();
-
-            output.copy_from_slice(&result_view[..]);
-        } else {
-            return Err(());
-        }
-
-        // Cleanup
-        // result view would be dropped by here
-        buffers.result.unmap();
+        // a failed copy may never call back, so errors are returned before waiting on it
+        gpu.wait(Some(submission_index))?;
 
-        return Ok(output);
+        return match receiver.recv_async().await {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(e)) => Err(WebRayError::BufferMap(e.to_string())),
+            Err(e) => Err(WebRayError::BufferMap(e.to_string())),
+        };
     }
 
     fn create_pipeline(gpu: &Gpu, bindings: &KernelBindings) -> wgpu::ComputePipeline {
@@ -240,14 +706,46 @@ impl Kernel {
                 label: Some("Compute pipeline"),
                 layout: Some(&pipeline_layout),
                 module: &shader,
-                entry_point: "main"
+                entry_point: "main",
             });
     }
 
-    fn count_tiles(width: u32, height: u32, tile_size: TileSize) -> glam::UVec2 {
-        return match tile_size {
-            TileSize::Full => glam::uvec2(1, 1),
-            TileSize::Tile(size) => glam::uvec2(width.div_ceil(size), height.div_ceil(size)),
-        };
+    /// Splits the tile into (offset, size) areas that each fit in max_workgroups along both axes
+    fn split_dispatches(size: glam::UVec2, max_workgroups: u32) -> Vec<(glam::UVec2, glam::UVec2)> {
+        let max_size = max_workgroups * WORKGROUP_SIZE;
+        let mut dispatches = Vec::new();
+
+        for y in (0..size.y).step_by(max_size as usize) {
+            for x in (0..size.x).step_by(max_size as usize) {
+                let offset = glam::uvec2(x, y);
+
+                dispatches.push((offset, (size - offset).min(glam::UVec2::splat(max_size))));
+            }
+        }
+
+        return dispatches;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dispatches_cover_the_tile_within_the_workgroup_limit() {
+        assert_eq!(
+            Kernel::split_dispatches(glam::uvec2(100, 30), 65535),
+            vec![(glam::UVec2::ZERO, glam::uvec2(100, 30))]
+        );
+
+        // 2 workgroups are 16 pixels
+        assert_eq!(
+            Kernel::split_dispatches(glam::uvec2(40, 10), 2),
+            vec![
+                (glam::uvec2(0, 0), glam::uvec2(16, 10)),
+                (glam::uvec2(16, 0), glam::uvec2(16, 10)),
+                (glam::uvec2(32, 0), glam::uvec2(8, 10)),
+            ]
+        );
     }
 }
@@ -0,0 +1,93 @@
+use super::shapes::KPrimitive;
+
+shader_types! {
+    mod layout;
+
+    /// Light sampled at every diffuse and rough metal bounce, every kind shares
+    /// this layout and the kind decides how it is read
+    /// - point: a.xyz position
+    /// - spot: a.xyz position, b.xyz unit direction, c.x cosine of the outer and c.y of the inner cone angle
+    /// - directional: b.xyz unit direction the light travels, c.x cosine of the angular radius
+    /// - sphere: a.xyz center, a.w radius
+    /// - quad: a.xyz corner, b.xyz and c.xyz edges
+    /// - disk: a.xyz center, a.w radius, b.xyz unit normal
+    #[derive(Debug, Clone, encase::ShaderType)]
+    pub struct KLight {
+        pub a: glam::Vec4,
+        pub b: glam::Vec4,
+        pub c: glam::Vec4,
+        /// intensity for point and spot lights, irradiance for directional and radiance for the rest
+        pub color: glam::Vec3,
+        pub kind: u32,
+    }
+}
+
+impl KLight {
+    pub const POINT: u32 = 0;
+    pub const SPOT: u32 = 1;
+    pub const DIRECTIONAL: u32 = 2;
+    pub const SPHERE: u32 = 3;
+    pub const QUAD: u32 = 4;
+    pub const DISK: u32 = 5;
+
+    pub fn point(position: glam::Vec3, color: glam::Vec3) -> Self {
+        return KLight {
+            a: position.extend(0.0),
+            b: glam::Vec4::ZERO,
+            c: glam::Vec4::ZERO,
+            color,
+            kind: Self::POINT,
+        };
+    }
+
+    /// Angles in degrees, light fades out between the inner and outer angle
+    pub fn spot(
+        position: glam::Vec3,
+        direction: glam::Vec3,
+        outer_angle: f32,
+        inner_angle: f32,
+        color: glam::Vec3,
+    ) -> Self {
+        return KLight {
+            a: position.extend(0.0),
+            b: direction.normalize().extend(0.0),
+            c: glam::vec4(
+                outer_angle.to_radians().cos(),
+                inner_angle.to_radians().cos(),
+                0.0,
+                0.0,
+            ),
+            color,
+            kind: Self::SPOT,
+        };
+    }
+
+    /// Angular radius in degrees
+    pub fn directional(direction: glam::Vec3, angular_radius: f32, color: glam::Vec3) -> Self {
+        return KLight {
+            a: glam::Vec4::ZERO,
+            b: direction.normalize().extend(0.0),
+            c: glam::vec4(angular_radius.to_radians().cos(), 0.0, 0.0, 0.0),
+            color,
+            kind: Self::DIRECTIONAL,
+        };
+    }
+
+    /// Emissive primitive as a light, only spheres, quads and disks can be sampled
+    pub fn area(primitive: &KPrimitive, radiance: glam::Vec3) -> Option<Self> {
+        let kind = match primitive.kind {
+            KPrimitive::SPHERE => Self::SPHERE,
+            KPrimitive::QUAD => Self::QUAD,
+            KPrimitive::DISK => Self::DISK,
+            _ => return None,
+        };
+
+        return Some(KLight {
+            a: primitive.a,
+            b: primitive.b,
+            c: primitive.c,
+            color: radiance,
+            kind,
+        });
+    }
+}
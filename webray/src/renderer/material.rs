@@ -1,15 +1,52 @@
-#[derive(Debug, encase::ShaderType)]
-pub struct KDiffuseMat {
-    pub albedo: glam::Vec3,
-}
+shader_types! {
+    mod layout;
 
-#[derive(Debug, encase::ShaderType)]
-pub struct KMetalMat {
-    pub albedo: glam::Vec3,
-    pub roughness: f32,
+    /// Every material type shares one buffer, the material type
+    /// in a hit's mid decides how the fields are read
+    #[derive(Debug, encase::ShaderType)]
+    pub struct KMaterial {
+        /// albedo for diffuse and metal, emitted color for emissive,
+        /// even squares of a checker and the low end of noise
+        pub color: glam::Vec3,
+        /// roughness for metal, index of refraction for dielectric, strength for emissive
+        pub param: f32,
+        /// odd squares of a checker and the high end of noise
+        pub color_b: glam::Vec3,
+        /// one of the TEXTURE_ kinds, only read for diffuse and metal
+        pub texture: u32,
+        /// x, y, width, height of the image in the texture atlas
+        pub image: glam::UVec4,
+        /// repeats per uv unit for images and checkers, frequency per world unit for noise
+        pub scale: f32,
+        pub octaves: u32,
+        /// id of the scene material, written to the material id aov
+        pub id: u32,
+    }
 }
 
-#[derive(Debug, encase::ShaderType)]
-pub struct KDielectricMat {
-    pub ior: f32,
+impl KMaterial {
+    /// material types, stored in the x of a primitive's mid
+    pub const DIFFUSE: u32 = 1;
+    pub const METAL: u32 = 2;
+    pub const DIELECTRIC: u32 = 3;
+    pub const EMISSIVE: u32 = 4;
+
+    pub const TEXTURE_NONE: u32 = 0;
+    pub const TEXTURE_IMAGE: u32 = 1;
+    pub const TEXTURE_CHECKER: u32 = 2;
+    pub const TEXTURE_NOISE: u32 = 3;
+
+    /// Material with the same color across the surface
+    pub fn constant(color: glam::Vec3, param: f32) -> Self {
+        return KMaterial {
+            color,
+            param,
+            color_b: glam::Vec3::ZERO,
+            texture: Self::TEXTURE_NONE,
+            image: glam::UVec4::ZERO,
+            scale: 1.0,
+            octaves: 0,
+            id: 0,
+        };
+    }
 }
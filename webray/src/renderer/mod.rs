@@ -1,74 +1,444 @@
 use crate::{
-    core::gpu::Gpu,
-    renderer::{bindings::KernelBindings, buffers::KernelBuffers, kernel::Kernel},
+    core::{
+        error::WebRayError,
+        gpu::{AdapterOptions, Gpu},
+    },
+    renderer::{
+        aov::AovImage,
+        bindings::KernelBindings,
+        buffers::KernelBuffers,
+        cancel::{CancelToken, RenderStatus, StopCondition},
+        kernel::Kernel,
+        scene::KernelScene,
+    },
+    scene::{
+        types::{WBackgroundType, WLight, WMaterial, WObject, WScene, WShape},
+        validation::Severity,
+    },
     utils::metrics::Metrics,
 };
 
-use self::{config::KernelConfig, scene::KernelScene};
+/// Declares structs deriving encase::ShaderType in a module that allows dead code and
+/// reexports them. The derive emits `check` fns beside the struct that are never called,
+/// an allow on the struct doesn't reach them
+macro_rules! shader_types {
+    (mod $module:ident; $($item:item)*) => {
+        #[allow(dead_code)]
+        mod $module {
+            $($item)*
+        }
+
+        pub use self::$module::*;
+    };
+}
 
 mod bindings;
 mod buffers;
+mod bvh;
+mod cpu;
+mod denoise_gpu;
 mod kernel;
+mod random;
+mod scheduler;
 
+pub mod aov;
+pub mod cancel;
 pub mod config;
+pub mod denoise;
+pub mod light;
 pub mod material;
+pub mod post;
 pub mod scene;
 pub mod shapes;
+pub mod texture;
 
-pub async fn render(
-    config: &KernelConfig,
-    scene: &KernelScene,
-    metrics: &mut Option<Metrics>,
-) -> Result<Vec<u8>, ()> {
-    // dbg!(&config);
-    // dbg!(&scene);
+/// Final image of a render
+pub struct RenderOutput {
+    /// linear radiance, rgba32 per pixel
+    pub hdr: Vec<f32>,
+    /// tone mapped and srgb encoded, rgba8 per pixel
+    pub image: Vec<u8>,
+    /// aovs selected in the render settings, missing when the render
+    /// was stopped before the first pass finished
+    pub aovs: Vec<AovImage>,
+    /// whether every sample was taken
+    pub status: RenderStatus,
+}
 
-    log::info!("Render start");
+/// Area of the image rendered by one dispatch, the whole image when rendering without tiles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    /// tiles rendered before this one, counting every pass
+    pub index: u32,
+    /// tiles in the whole render, counting every pass
+    pub count: u32,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
 
-    if let Some(m) = metrics.as_mut() {
-        m.start();
+/// Reported while a render runs, images are tone mapped and srgb encoded rgba8
+#[derive(Debug, Clone)]
+pub enum RenderEvent {
+    TileStarted {
+        tile: Tile,
+    },
+    /// pixels of the tile averaged over the samples so far, before denoising
+    TileFinished {
+        tile: Tile,
+        image: Vec<u8>,
+    },
+    /// intermediate image of a progressive render, every pass but the last reports one
+    PassFinished {
+        samples: u32,
+        total_samples: u32,
+        image: Vec<u8>,
+    },
+    /// final image is in the render output
+    Finished {
+        status: RenderStatus,
+    },
+}
+
+/// Where the path tracing runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// webray.wgsl compute kernel through wgpu
+    Gpu,
+    /// Multithreaded reference path tracer, for machines without an adapter
+    Cpu,
+}
+
+/// Owns the backend resources, for the gpu the device, bind group layouts and
+/// compiled pipeline, so repeated renders only rebuild what their inputs changed
+pub struct Renderer {
+    device: Device,
+    /// scene the current buffers were built from
+    uploaded: Option<UploadedScene>,
+    /// cancels the running render, reset when the next one starts
+    cancel: CancelToken,
+    pub(crate) metrics: Option<Metrics>,
+}
+
+/// Backend resources kept alive between renders
+enum Device {
+    Gpu(Box<GpuDevice>),
+    Cpu { scene: Option<Box<KernelScene>> },
+}
+
+struct GpuDevice {
+    gpu: Gpu,
+    bindings: KernelBindings,
+    kernel: Kernel,
+    buffers: Option<KernelBuffers>,
+}
+
+/// Parts of a scene that end up in the scene buffers, everything
+/// else lives in the config uniform and is rewritten on every render
+struct UploadedScene {
+    objects: Vec<WObject>,
+    shapes: Vec<WShape>,
+    materials: Vec<WMaterial>,
+    lights: Vec<WLight>,
+    background: WBackgroundType,
+    resolution: glam::UVec2,
+    /// enabled aovs or denoising, the aov and denoise textures are only image sized while either is
+    aovs: bool,
+}
+
+impl UploadedScene {
+    fn new(scene: &WScene) -> Self {
+        return UploadedScene {
+            objects: scene.objects.clone(),
+            shapes: scene.shapes.clone(),
+            materials: scene.materials.clone(),
+            lights: scene.lights.clone(),
+            background: scene.background.bg_type.clone(),
+            resolution: Self::resolution(scene),
+            aovs: Self::has_aovs(scene),
+        };
     }
 
-    let gpu = Gpu::new().await;
+    fn resolution(scene: &WScene) -> glam::UVec2 {
+        return glam::uvec2(scene.render_settings.width, scene.render_settings.height);
+    }
 
-    log::info!("Device acquired");
+    fn has_aovs(scene: &WScene) -> bool {
+        return !scene.render_settings.aovs.selected().is_empty()
+            || scene.render_settings.denoise.is_some();
+    }
 
-    if let Some(m) = metrics.as_mut() {
-        m.capture_device_acquisition();
+    fn same_scene(&self, scene: &WScene) -> bool {
+        return self.objects == scene.objects
+            && self.shapes == scene.shapes
+            && self.materials == scene.materials
+            && self.lights == scene.lights
+            && self.background == scene.background.bg_type;
     }
+}
 
-    let buffers = KernelBuffers::new(&gpu, &config.system, scene);
+impl Renderer {
+    pub async fn new(backend: Backend) -> Result<Self, WebRayError> {
+        return Self::with_adapter(backend, &AdapterOptions::default()).await;
+    }
 
-    let mut bindings = KernelBindings::new(&gpu);
+    /// Same as new, with control over the adapter the gpu backend runs on
+    pub async fn with_adapter(
+        backend: Backend,
+        adapter: &AdapterOptions,
+    ) -> Result<Self, WebRayError> {
+        let mut metrics: Option<Metrics>;
 
-    bindings.bind_buffers(&gpu, &buffers);
+        cfg_if::cfg_if! {
+            if #[cfg(target_arch = "wasm32")] {
+                metrics = None;
+            } else {
+                metrics = Some(Metrics::new());
+            }
+        };
 
-    log::info!("Scene buffers uploaded");
+        let device = match backend {
+            Backend::Gpu => {
+                let gpu = Gpu::new(adapter).await?;
 
-    if let Some(m) = metrics.as_mut() {
-        m.capture_scene_upload();
-    }
+                log::info!("Device acquired");
+
+                if let Some(m) = metrics.as_mut() {
+                    m.set_adapter(gpu.adapter.to_string());
+                    m.capture_device_acquisition();
+                }
+
+                let bindings = KernelBindings::new(&gpu);
+
+                let kernel = Kernel::new(&gpu, &bindings);
+
+                log::info!("Kernel initialized");
 
-    let kernel = Kernel::new(&gpu, &bindings);
+                Device::Gpu(Box::new(GpuDevice {
+                    gpu,
+                    bindings,
+                    kernel,
+                    buffers: None,
+                }))
+            }
+            Backend::Cpu => {
+                if let Some(m) = metrics.as_mut() {
+                    m.set_adapter("CPU reference backend".to_string());
+                }
 
-    log::info!("Kernel initialized");
+                Device::Cpu { scene: None }
+            }
+        };
 
-    if let Some(m) = metrics.as_mut() {
-        m.capture_kernel_initialization();
+        return Ok(Renderer {
+            device,
+            uploaded: None,
+            cancel: CancelToken::new(),
+            metrics,
+        });
     }
 
-    let result = kernel
-        .execute(&gpu, &config.system, &config.execution, &bindings, &buffers)
-        .await;
+    /// Token that stops the running render between tiles, the render then
+    /// returns the samples taken so far with the Cancelled status
+    pub fn cancel_token(&self) -> CancelToken {
+        return self.cancel.clone();
+    }
+
+    pub async fn render(
+        &mut self,
+        scene: &WScene,
+        mut on_event: Option<&mut dyn FnMut(RenderEvent)>,
+    ) -> Result<RenderOutput, WebRayError> {
+        log::info!("Render start");
+
+        self.cancel.reset();
+
+        if let Some(m) = self.metrics.as_mut() {
+            // the first render also accounts for the device acquired in new
+            if self.uploaded.is_some() {
+                m.start();
+                m.capture_device_acquisition();
+            }
+        }
+
+        let diagnostics = scene.validate();
+
+        if diagnostics
+            .iter()
+            .any(|d| return d.severity == Severity::Error)
+        {
+            return Err(WebRayError::InvalidScene(diagnostics));
+        }
+
+        for diagnostic in &diagnostics {
+            log::warn!("{}", diagnostic);
+        }
+
+        let config = scene.get_kernel_config()?;
 
-    match result {
-        Ok(_) => log::info!("Render finished"),
-        Err(_) => log::error!("Something went wrong"),
+        let stop = StopCondition::new(self.cancel.clone(), config.execution.time_limit);
+
+        // reborrowed with a shorter lifetime so Finished can still be sent below
+        let backend_on_event = on_event
+            .as_mut()
+            .map(|callback| return &mut **callback as &mut dyn FnMut(RenderEvent));
+
+        let scene_changed = self
+            .uploaded
+            .as_ref()
+            .is_none_or(|uploaded| return !uploaded.same_scene(scene));
+        let resized = self.uploaded.as_ref().is_none_or(|uploaded| {
+            return uploaded.resolution != UploadedScene::resolution(scene)
+                || uploaded.aovs != UploadedScene::has_aovs(scene);
+        });
+
+        let result = match &mut self.device {
+            Device::Gpu(device) => {
+                let GpuDevice {
+                    gpu,
+                    bindings,
+                    kernel,
+                    buffers,
+                } = device.as_mut();
+
+                match buffers.as_mut() {
+                    Some(buffers) => {
+                        if scene_changed {
+                            buffers.upload_scene(gpu, &scene.get_kernel_scene()?)?;
+
+                            log::info!("Scene buffers uploaded");
+                        }
+
+                        if resized {
+                            buffers.resize(gpu, &config.system);
+
+                            log::info!("Image buffers resized");
+                        }
+
+                        buffers.upload_config(gpu, &config.system)?;
+                    }
+                    None => {
+                        *buffers = Some(KernelBuffers::new(
+                            gpu,
+                            &config.system,
+                            &scene.get_kernel_scene()?,
+                        )?);
+
+                        log::info!("Scene buffers uploaded");
+                    }
+                }
+
+                self.uploaded = Some(UploadedScene::new(scene));
+
+                if let Some(m) = self.metrics.as_mut() {
+                    m.capture_scene_upload();
+                }
+
+                // buffers were just created above
+                let buffers = buffers.as_ref().unwrap();
+
+                bindings.bind_buffers(gpu, buffers);
+
+                if let Some(m) = self.metrics.as_mut() {
+                    m.capture_kernel_initialization();
+                }
+
+                kernel
+                    .execute(gpu, &config, bindings, buffers, &stop, backend_on_event)
+                    .await
+            }
+            Device::Cpu {
+                scene: kernel_scene,
+            } => {
+                if scene_changed || kernel_scene.is_none() {
+                    *kernel_scene = Some(Box::new(scene.get_kernel_scene()?));
+                }
+
+                self.uploaded = Some(UploadedScene::new(scene));
+
+                if let Some(m) = self.metrics.as_mut() {
+                    m.capture_scene_upload();
+                    m.capture_kernel_initialization();
+                }
+
+                // scene was just built above
+                Ok(cpu::execute(
+                    &config,
+                    kernel_scene.as_ref().unwrap(),
+                    &stop,
+                    backend_on_event,
+                ))
+            }
+        };
+
+        match &result {
+            Ok(_) => log::info!("Render finished"),
+            Err(e) => log::error!("{}", e),
+        }
+
+        if let Some(m) = self.metrics.as_mut() {
+            m.capture_rendering();
+        }
+
+        let (hdr, aovs, status) = result?;
+        let image = config.post.apply(&hdr);
+
+        if let Some(callback) = on_event {
+            callback(RenderEvent::Finished { status });
+        }
+
+        return Ok(RenderOutput {
+            hdr,
+            image,
+            aovs,
+            status,
+        });
     }
+}
 
-    if let Some(m) = metrics.as_mut() {
-        m.capture_rendering();
+#[cfg(test)]
+mod tests {
+    use crate::scene::types::WLightType;
+
+    use super::*;
+
+    fn render(renderer: &mut Renderer, scene: &WScene) -> Vec<f32> {
+        return pollster::block_on(renderer.render(scene, None))
+            .unwrap()
+            .hdr;
     }
 
-    return result;
+    #[test]
+    fn changing_only_the_lights_reuploads_the_scene() {
+        let json = include_str!("../../../src/data/demo_01.scene.json");
+        let mut scene = WScene::from_json(json).unwrap();
+
+        scene.render_settings.width = 16;
+        scene.render_settings.height = 16;
+        scene.render_settings.samples = 1;
+        scene.render_settings.samples_per_pass = None;
+
+        let mut renderer = pollster::block_on(Renderer::new(Backend::Cpu)).unwrap();
+        let unlit = render(&mut renderer, &scene);
+
+        scene.lights = vec![WLight {
+            id: 0,
+            name: String::from("light"),
+            light_type: WLightType::Point {
+                position: scene.camera.look_from,
+                color: String::from("#FFFFFF"),
+                intensity: 1000.0,
+            },
+        }];
+
+        let lit = render(&mut renderer, &scene);
+        let fresh = render(
+            &mut pollster::block_on(Renderer::new(Backend::Cpu)).unwrap(),
+            &scene,
+        );
+
+        assert_ne!(lit, unlit);
+        assert_eq!(lit, fresh);
+    }
 }
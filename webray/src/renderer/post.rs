@@ -0,0 +1,63 @@
+use super::config::RenderConfig;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Tonemap {
+    /// Clamps to the displayable range
+    None,
+    Reinhard,
+    /// Narkowicz fit of the ACES filmic curve
+    Aces,
+}
+
+/// Display transform applied to the linear image read back from the kernel
+#[derive(Debug)]
+pub struct PostConfig {
+    /// exposure compensation in stops
+    pub exposure: f32,
+    pub tonemap: Tonemap,
+}
+
+impl PostConfig {
+    pub fn new(render_config: &RenderConfig) -> Self {
+        return PostConfig {
+            exposure: render_config.exposure,
+            tonemap: render_config.tonemap,
+        };
+    }
+
+    /// Maps linear rgba32 radiance to srgb encoded rgba8
+    pub fn apply(&self, hdr: &[f32]) -> Vec<u8> {
+        let scale = 2f32.powf(self.exposure);
+
+        let mut image = Vec::with_capacity(hdr.len());
+
+        for pixel in hdr.chunks_exact(4) {
+            for channel in &pixel[..3] {
+                let mapped = self.tonemap(channel * scale);
+
+                image.push((srgb_oetf(mapped.clamp(0.0, 1.0)) * 255.0 + 0.5) as u8);
+            }
+
+            image.push(255);
+        }
+
+        return image;
+    }
+
+    fn tonemap(&self, x: f32) -> f32 {
+        return match self.tonemap {
+            Tonemap::None => x,
+            Tonemap::Reinhard => x / (1.0 + x),
+            Tonemap::Aces => (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14),
+        };
+    }
+}
+
+/// Linear to srgb transfer function
+fn srgb_oetf(x: f32) -> f32 {
+    if x <= 0.0031308 {
+        return x * 12.92;
+    }
+
+    return 1.055 * x.powf(1.0 / 2.4) - 0.055;
+}
@@ -0,0 +1,104 @@
+/// PCG hash, mirrors `hash` in webray.wgsl
+pub fn hash(x: u32) -> u32 {
+    let state = x.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+
+    return (word >> 22) ^ word;
+}
+
+/// Initial rng state of a sample, mirrors `random_init` in webray.wgsl
+pub fn sample_seed(pixel_position: glam::UVec2, width: u32, sample: u32, seed: u32) -> u32 {
+    let pixel_index = pixel_position.y * width + pixel_position.x;
+
+    return hash(pixel_index ^ hash(sample ^ hash(seed)));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use super::*;
+
+    const WIDTH: u32 = 100;
+    const HEIGHT: u32 = 60;
+    const TILE_SIZE: u32 = 16;
+
+    /// Seeds of every pixel as the kernel computes them, dispatching one tile at a time
+    /// with `global_invocation_id` local to the tile
+    fn tiled_seeds(sample: u32, seed: u32) -> HashMap<glam::UVec2, u32> {
+        let mut seeds = HashMap::new();
+
+        for tile_x in 0..WIDTH.div_ceil(TILE_SIZE) {
+            for tile_y in 0..HEIGHT.div_ceil(TILE_SIZE) {
+                let tile_position = glam::uvec2(tile_x, tile_y) * TILE_SIZE;
+
+                let width = (tile_position.x + TILE_SIZE).min(WIDTH) - tile_position.x;
+                let height = (tile_position.y + TILE_SIZE).min(HEIGHT) - tile_position.y;
+
+                for x in 0..width {
+                    for y in 0..height {
+                        let pixel_position = tile_position + glam::uvec2(x, y);
+
+                        seeds.insert(
+                            pixel_position,
+                            sample_seed(pixel_position, WIDTH, sample, seed),
+                        );
+                    }
+                }
+            }
+        }
+
+        return seeds;
+    }
+
+    fn full_seeds(sample: u32, seed: u32) -> HashMap<glam::UVec2, u32> {
+        let mut seeds = HashMap::new();
+
+        for x in 0..WIDTH {
+            for y in 0..HEIGHT {
+                let pixel_position = glam::uvec2(x, y);
+
+                seeds.insert(
+                    pixel_position,
+                    sample_seed(pixel_position, WIDTH, sample, seed),
+                );
+            }
+        }
+
+        return seeds;
+    }
+
+    #[test]
+    fn tiled_and_full_renders_use_the_same_distinct_per_pixel_streams() {
+        for sample in 0..4 {
+            let tiled = tiled_seeds(sample, 7);
+            let full = full_seeds(sample, 7);
+
+            assert_eq!(tiled.len(), (WIDTH * HEIGHT) as usize);
+            assert_eq!(tiled, full);
+
+            // no two pixels share a stream, in particular the same local id in different tiles
+            let distinct: HashSet<u32> = tiled.values().copied().collect();
+            assert_eq!(distinct.len(), tiled.len());
+        }
+    }
+
+    #[test]
+    fn samples_and_seeds_decorrelate_streams() {
+        let pixel_position = glam::uvec2(3, 5);
+
+        let samples: HashSet<u32> = (0..1024)
+            .map(|sample| return sample_seed(pixel_position, WIDTH, sample, 0))
+            .collect();
+        assert_eq!(samples.len(), 1024);
+
+        assert_eq!(
+            sample_seed(pixel_position, WIDTH, 0, 42),
+            sample_seed(pixel_position, WIDTH, 0, 42)
+        );
+        assert_ne!(
+            sample_seed(pixel_position, WIDTH, 0, 42),
+            sample_seed(pixel_position, WIDTH, 0, 43)
+        );
+    }
+}
@@ -1,67 +1,324 @@
 use super::{
-    material::{KDielectricMat, KDiffuseMat, KMetalMat},
-    shapes::KSphere,
+    bvh::{Aabb, Bvh, KBvhNode},
+    light::KLight,
+    material::KMaterial,
+    shapes::{KInstance, KPrimitive, KTriangle, KVertex},
+    texture::KTexture,
 };
 
+/// Shape every object without a transform is registered to, it is placed
+/// in the world once with an identity transform
+pub const WORLD_SHAPE: u32 = 0;
+
+/// Primitives of a shape, kept apart until the BVH is built as the
+/// buffers need every shape in a contiguous range
+#[derive(Debug, Default)]
+struct Shape {
+    primitives: Vec<KPrimitive>,
+    vertices: Vec<KVertex>,
+    triangles: Vec<KTriangle>,
+}
+
+#[derive(Debug)]
+struct InstanceDesc {
+    shape: u32,
+    object_to_world: glam::Mat4,
+    mid: glam::UVec4,
+}
+
 #[derive(Debug)]
 pub struct KernelScene {
-    spheres: Vec<KSphere>,
-    diffuse_mats: Vec<KDiffuseMat>,
-    metal_mats: Vec<KMetalMat>,
-    dielectric_mats: Vec<KDielectricMat>,
+    shapes: Vec<Shape>,
+    instance_descs: Vec<InstanceDesc>,
+    primitives: Vec<KPrimitive>,
+    vertices: Vec<KVertex>,
+    triangles: Vec<KTriangle>,
+    instances: Vec<KInstance>,
+    /// top level nodes first followed by the nodes of every shape
+    bvh_nodes: Vec<KBvhNode>,
+    materials: Vec<KMaterial>,
+    /// explicit lights and the emissive primitives of the world shape, see KPrimitive::mid
+    lights: Vec<KLight>,
+    environment_map: Option<KTexture>,
+    /// image textures of every material, see KMaterial::image
+    texture_atlas: Option<KTexture>,
 }
 
 impl KernelScene {
     pub fn new() -> Self {
         return KernelScene {
-            spheres: Vec::new(),
-            diffuse_mats: Vec::new(),
-            metal_mats: Vec::new(),
-            dielectric_mats: Vec::new(),
+            shapes: vec![Shape::default()],
+            instance_descs: Vec::new(),
+            primitives: Vec::new(),
+            vertices: Vec::new(),
+            triangles: Vec::new(),
+            instances: Vec::new(),
+            bvh_nodes: Vec::new(),
+            materials: Vec::new(),
+            lights: Vec::new(),
+            environment_map: None,
+            texture_atlas: None,
+        };
+    }
+
+    /// Returns the index of a new empty shape
+    pub fn register_shape(&mut self) -> u32 {
+        self.shapes.push(Shape::default());
+        return self.shapes.len() as u32 - 1;
+    }
+
+    /// Emissive primitives of the world shape are also registered as area lights, the light index
+    /// plus one goes in the mid's z so the kernel can weight hits against light sampling.
+    /// Materials have to be registered first
+    pub fn register_primitive(&mut self, shape: u32, mut primitive: KPrimitive) {
+        if shape == WORLD_SHAPE && primitive.mid.x == KMaterial::EMISSIVE {
+            let material = &self.materials[primitive.mid.y as usize];
+            let radiance = material.color * material.param;
+
+            if radiance != glam::Vec3::ZERO {
+                if let Some(light) = KLight::area(&primitive, radiance) {
+                    primitive.mid.z = self.register_light(light) + 1;
+                }
+            }
+        }
+
+        self.shapes[shape as usize].primitives.push(primitive);
+    }
+
+    /// Indices are local to the given vertices and get offset into the shape's vertices
+    pub fn register_mesh(
+        &mut self,
+        shape: u32,
+        vertices: Vec<KVertex>,
+        indices: &[u32],
+        mid: glam::UVec4,
+    ) {
+        let shape = &mut self.shapes[shape as usize];
+        let offset = shape.vertices.len() as u32;
+
+        shape.vertices.extend(vertices);
+
+        for triangle in indices.chunks_exact(3) {
+            shape.triangles.push(KTriangle {
+                vertices: glam::uvec4(
+                    offset + triangle[0],
+                    offset + triangle[1],
+                    offset + triangle[2],
+                    0,
+                ),
+                mid,
+            });
+        }
+    }
+
+    /// Places the shape in the world, a zero mid keeps the materials of the shape's primitives
+    pub fn register_instance(&mut self, shape: u32, object_to_world: glam::Mat4, mid: glam::UVec4) {
+        self.instance_descs.push(InstanceDesc {
+            shape,
+            object_to_world,
+            mid,
+        });
+    }
+
+    /// Builds a BVH for every shape and the top level BVH over the instances,
+    /// then flattens the shapes into the primitive buffers. Shapes can't be
+    /// registered or instanced afterwards
+    pub fn build_bvh(&mut self) {
+        let mut shape_nodes = Vec::new();
+        let mut roots = Vec::with_capacity(self.shapes.len());
+        let mut bounds = Vec::with_capacity(self.shapes.len());
+
+        for shape in std::mem::take(&mut self.shapes).into_iter() {
+            let bvh = Bvh::build(&shape.primitives, &shape.vertices, &shape.triangles);
+
+            let node_offset = shape_nodes.len() as u32;
+            let primitive_offset = self.primitives.len() as u32;
+            let vertex_offset = self.vertices.len() as u32;
+            let triangle_offset = self.triangles.len() as u32;
+
+            roots.push(node_offset);
+            bounds.push(
+                if shape.primitives.is_empty() && shape.triangles.is_empty() {
+                    Aabb::empty()
+                } else {
+                    Aabb {
+                        min: bvh.nodes[0].min,
+                        max: bvh.nodes[0].max,
+                    }
+                },
+            );
+
+            for i in bvh.primitive_order.iter() {
+                self.primitives.push(shape.primitives[*i as usize].clone());
+            }
+
+            for i in bvh.triangle_order.iter() {
+                let mut triangle = shape.triangles[*i as usize].clone();
+                triangle.vertices += glam::uvec4(vertex_offset, vertex_offset, vertex_offset, 0);
+                self.triangles.push(triangle);
+            }
+
+            self.vertices.extend(shape.vertices);
+
+            for mut node in bvh.nodes.into_iter() {
+                if node.next != 0 {
+                    node.next += node_offset;
+                }
+
+                node.primitives.x += primitive_offset;
+                node.triangles.x += triangle_offset;
+
+                shape_nodes.push(node);
+            }
+        }
+
+        let mut instance_bounds = Vec::new();
+        let mut instances = Vec::new();
+
+        let world = InstanceDesc {
+            shape: WORLD_SHAPE,
+            object_to_world: glam::Mat4::IDENTITY,
+            mid: glam::UVec4::ZERO,
         };
+
+        for desc in std::iter::once(&world).chain(self.instance_descs.iter()) {
+            let shape_bounds = &bounds[desc.shape as usize];
+
+            // nothing to hit, also keeps the world shape out of scenes that only use instances
+            if shape_bounds.is_empty() {
+                continue;
+            }
+
+            instance_bounds.push(shape_bounds.transformed(&desc.object_to_world));
+            instances.push(KInstance {
+                world_to_object: desc.object_to_world.inverse(),
+                mid: desc.mid,
+                root: roots[desc.shape as usize],
+            });
+        }
+
+        let top_level = Bvh::build_instances(&instance_bounds);
+        let top_level_size = top_level.nodes.len() as u32;
+
+        for i in top_level.instance_order.iter() {
+            let mut instance = instances[*i as usize].clone();
+            instance.root += top_level_size;
+            self.instances.push(instance);
+        }
+
+        self.bvh_nodes = top_level.nodes;
+
+        for mut node in shape_nodes.into_iter() {
+            if node.next != 0 {
+                node.next += top_level_size;
+            }
+
+            self.bvh_nodes.push(node);
+        }
+
+        log::info!(
+            "BVH built, shapes: {}, instances: {}, nodes: {}",
+            roots.len(),
+            self.instances.len(),
+            self.bvh_nodes.len()
+        );
+
+        self.instance_descs.clear();
+    }
+
+    pub fn set_environment_map(&mut self, texture: KTexture) {
+        self.environment_map = Some(texture);
+    }
+
+    pub fn environment_map(&self) -> Option<&KTexture> {
+        return self.environment_map.as_ref();
+    }
+
+    pub fn set_texture_atlas(&mut self, texture: KTexture) {
+        self.texture_atlas = Some(texture);
+    }
+
+    pub fn texture_atlas(&self) -> Option<&KTexture> {
+        return self.texture_atlas.as_ref();
+    }
+
+    pub fn primitives(&self) -> &[KPrimitive] {
+        return &self.primitives;
+    }
+
+    pub fn vertices(&self) -> &[KVertex] {
+        return &self.vertices;
     }
 
-    pub fn register_sphere(&mut self, sphere: KSphere) -> u32 {
-        self.spheres.push(sphere);
-        return self.spheres.len() as u32 - 1;
+    pub fn triangles(&self) -> &[KTriangle] {
+        return &self.triangles;
     }
 
-    pub fn register_diffuse_material(&mut self, mat: KDiffuseMat) -> u32 {
-        self.diffuse_mats.push(mat);
-        return self.diffuse_mats.len() as u32 - 1;
+    pub fn instances(&self) -> &[KInstance] {
+        return &self.instances;
     }
 
-    pub fn register_metal_material(&mut self, mat: KMetalMat) -> u32 {
-        self.metal_mats.push(mat);
-        return self.metal_mats.len() as u32 - 1;
+    pub fn bvh_nodes(&self) -> &[KBvhNode] {
+        return &self.bvh_nodes;
     }
 
-    pub fn register_dielectric_material(&mut self, mat: KDielectricMat) -> u32 {
-        self.dielectric_mats.push(mat);
-        return self.dielectric_mats.len() as u32 - 1;
+    pub fn materials(&self) -> &[KMaterial] {
+        return &self.materials;
+    }
+
+    pub fn register_material(&mut self, mat: KMaterial) -> u32 {
+        self.materials.push(mat);
+        return self.materials.len() as u32 - 1;
+    }
+
+    pub fn lights(&self) -> &[KLight] {
+        return &self.lights;
+    }
+
+    pub fn register_light(&mut self, light: KLight) -> u32 {
+        self.lights.push(light);
+        return self.lights.len() as u32 - 1;
+    }
+
+    pub fn primitives_as_wgsl_bytes(&self) -> encase::internal::Result<Vec<u8>> {
+        let mut buffer = encase::StorageBuffer::new(Vec::new());
+        buffer.write(&self.primitives)?;
+        return Ok(buffer.into_inner());
+    }
+
+    pub fn vertices_as_wgsl_bytes(&self) -> encase::internal::Result<Vec<u8>> {
+        let mut buffer = encase::StorageBuffer::new(Vec::new());
+        buffer.write(&self.vertices)?;
+        return Ok(buffer.into_inner());
+    }
+
+    pub fn triangles_as_wgsl_bytes(&self) -> encase::internal::Result<Vec<u8>> {
+        let mut buffer = encase::StorageBuffer::new(Vec::new());
+        buffer.write(&self.triangles)?;
+        return Ok(buffer.into_inner());
     }
 
-    pub fn spheres_as_wgsl_bytes(&self) -> encase::internal::Result<Vec<u8>> {
+    pub fn instances_as_wgsl_bytes(&self) -> encase::internal::Result<Vec<u8>> {
         let mut buffer = encase::StorageBuffer::new(Vec::new());
-        buffer.write(&self.spheres).unwrap();
+        buffer.write(&self.instances)?;
         return Ok(buffer.into_inner());
     }
 
-    pub fn diffuse_mats_as_wgsl_bytes(&self) -> encase::internal::Result<Vec<u8>> {
+    pub fn bvh_nodes_as_wgsl_bytes(&self) -> encase::internal::Result<Vec<u8>> {
         let mut buffer = encase::StorageBuffer::new(Vec::new());
-        buffer.write(&self.diffuse_mats).unwrap();
+        buffer.write(&self.bvh_nodes)?;
         return Ok(buffer.into_inner());
     }
 
-    pub fn metal_mats_as_wgsl_bytes(&self) -> encase::internal::Result<Vec<u8>> {
+    pub fn lights_as_wgsl_bytes(&self) -> encase::internal::Result<Vec<u8>> {
         let mut buffer = encase::StorageBuffer::new(Vec::new());
-        buffer.write(&self.metal_mats).unwrap();
+        buffer.write(&self.lights)?;
         return Ok(buffer.into_inner());
     }
 
-    pub fn dielectric_mats_as_wgsl_bytes(&self) -> encase::internal::Result<Vec<u8>> {
+    pub fn materials_as_wgsl_bytes(&self) -> encase::internal::Result<Vec<u8>> {
         let mut buffer = encase::StorageBuffer::new(Vec::new());
-        buffer.write(&self.dielectric_mats).unwrap();
+        buffer.write(&self.materials)?;
         return Ok(buffer.into_inner());
     }
 }
@@ -0,0 +1,125 @@
+/// Edge of the tile the cost is measured on before an auto tile size is picked
+pub const CALIBRATION_TILE: u32 = 64;
+/// Smallest tile an auto tile size picks, below it the dispatches are mostly overhead
+const MIN_AUTO_TILE: u32 = 16;
+/// Weight of the latest dispatch when the cost falls
+const COST_FALL_RATE: f64 = 0.25;
+
+/// Sizes the kernel's submissions from the measured cost of the previous ones so each
+/// stays under the dispatch budget, well clear of wgpu's and the drivers' timeouts
+pub struct DispatchScheduler {
+    budget_ms: f64,
+    /// milliseconds per pixel sample, unknown until the first dispatch
+    cost_ms: Option<f64>,
+}
+
+impl DispatchScheduler {
+    pub fn new(budget: std::time::Duration) -> Self {
+        return DispatchScheduler {
+            budget_ms: budget.as_secs_f64() * 1000.0,
+            cost_ms: None,
+        };
+    }
+
+    /// Samples the next dispatch over this many pixels takes out of the remaining ones,
+    /// a single one while the cost is unknown
+    pub fn samples(&self, pixels: u32, remaining: u32) -> u32 {
+        let Some(cost) = self.cost_ms else {
+            return 1;
+        };
+
+        // saturates when the dispatches are too fast to measure
+        let fit = (self.budget_ms / (cost * pixels as f64)) as u32;
+
+        return fit.clamp(1, remaining.max(1));
+    }
+
+    /// Takes the slower cost right away but only falls gradually,
+    /// so a tile of cheap background doesn't make the next one overshoot
+    pub fn record(&mut self, pixels: u32, samples: u32, elapsed_ms: f64) {
+        let cost = elapsed_ms / (pixels as f64 * samples as f64);
+
+        self.cost_ms = Some(match self.cost_ms {
+            Some(previous) if cost < previous => {
+                previous * (1.0 - COST_FALL_RATE) + cost * COST_FALL_RATE
+            }
+            _ => cost,
+        });
+    }
+
+    /// Edge of the largest power of two tile a single sample of fits in the budget,
+    /// the whole image when it fits
+    pub fn tile_size(&self, width: u32, height: u32) -> u32 {
+        let Some(cost) = self.cost_ms else {
+            return CALIBRATION_TILE;
+        };
+
+        let fits = |pixels: u32| return cost * pixels as f64 <= self.budget_ms;
+
+        if fits(width * height) {
+            return width.max(height);
+        }
+
+        let mut size = MIN_AUTO_TILE;
+
+        while size * 2 < width.max(height) && fits(size * size * 4) {
+            size *= 2;
+        }
+
+        return size;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scheduler(budget_ms: u64) -> DispatchScheduler {
+        return DispatchScheduler::new(std::time::Duration::from_millis(budget_ms));
+    }
+
+    #[test]
+    fn batches_fit_the_budget_once_measured() {
+        let mut scheduler = scheduler(100);
+
+        assert_eq!(scheduler.samples(64 * 64, 32), 1);
+
+        // 1ms per 1000 pixel samples
+        scheduler.record(1000, 1, 1.0);
+
+        assert_eq!(scheduler.samples(1000, 1000), 100);
+        assert_eq!(scheduler.samples(1000, 8), 8);
+        // a single sample is always taken, even over budget
+        assert_eq!(scheduler.samples(1_000_000, 8), 1);
+    }
+
+    #[test]
+    fn cost_rises_at_once_and_falls_gradually() {
+        let mut scheduler = scheduler(100);
+
+        scheduler.record(1000, 1, 1.0);
+        scheduler.record(1000, 1, 4.0);
+        assert_eq!(scheduler.samples(1000, 1000), 25);
+
+        scheduler.record(1000, 1, 0.0);
+        assert_eq!(scheduler.samples(1000, 1000), 33);
+    }
+
+    #[test]
+    fn auto_tile_size_is_the_largest_that_fits() {
+        let mut scheduler = scheduler(100);
+
+        assert_eq!(scheduler.tile_size(1920, 1080), CALIBRATION_TILE);
+
+        // 1ms per 1000 pixel samples, 100k pixels fit
+        scheduler.record(1000, 1, 1.0);
+
+        assert_eq!(scheduler.tile_size(1920, 1080), 256);
+        // the whole image
+        assert_eq!(scheduler.tile_size(320, 240), 320);
+
+        // slower than the budget even for the smallest tile
+        scheduler.record(1, 1, 1000.0);
+        assert_eq!(scheduler.tile_size(1920, 1080), MIN_AUTO_TILE);
+    }
+}
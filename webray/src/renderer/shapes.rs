@@ -1,8 +1,128 @@
-/// Had to make size of KSphere a multiple of 2
-/// either ensure encase does tight packing or somehow specify a offset in wgpu
-#[derive(Debug, encase::ShaderType)]
-pub struct KSphere {
-    pub center: glam::Vec3,
-    pub radius: f32,
-    pub mid: glam::UVec4,
+shader_types! {
+    mod primitive_layout;
+
+    /// Analytic primitive, every kind shares this layout and the kind decides how it is read
+    /// - sphere: a.xyz center, a.w radius
+    /// - plane: a.xyz point, b.xyz unit normal
+    /// - quad: a.xyz corner, b.xyz and c.xyz edges
+    /// - box: a.xyz center, b.xyz half size, c rotation quaternion
+    /// - disk: a.xyz center, a.w radius, b.xyz unit normal
+    /// - cylinder: a.xyz base center, a.w radius, b.xyz top center
+    #[derive(Debug, Clone, encase::ShaderType)]
+    pub struct KPrimitive {
+        pub a: glam::Vec4,
+        pub b: glam::Vec4,
+        pub c: glam::Vec4,
+        /// x material type, y material index, z light index plus one for area lights and 0 otherwise,
+        /// w object id plus one
+        pub mid: glam::UVec4,
+        pub kind: u32,
+    }
+}
+
+impl KPrimitive {
+    pub const SPHERE: u32 = 0;
+    pub const PLANE: u32 = 1;
+    pub const QUAD: u32 = 2;
+    pub const BOX: u32 = 3;
+    pub const DISK: u32 = 4;
+    pub const CYLINDER: u32 = 5;
+
+    pub fn sphere(center: glam::Vec3, radius: f32, mid: glam::UVec4) -> Self {
+        return Self::new(
+            Self::SPHERE,
+            center.extend(radius),
+            glam::Vec4::ZERO,
+            glam::Vec4::ZERO,
+            mid,
+        );
+    }
+
+    pub fn plane(point: glam::Vec3, normal: glam::Vec3, mid: glam::UVec4) -> Self {
+        return Self::new(
+            Self::PLANE,
+            point.extend(0.0),
+            normal.normalize().extend(0.0),
+            glam::Vec4::ZERO,
+            mid,
+        );
+    }
+
+    pub fn quad(corner: glam::Vec3, u: glam::Vec3, v: glam::Vec3, mid: glam::UVec4) -> Self {
+        return Self::new(
+            Self::QUAD,
+            corner.extend(0.0),
+            u.extend(0.0),
+            v.extend(0.0),
+            mid,
+        );
+    }
+
+    pub fn oriented_box(
+        center: glam::Vec3,
+        half_size: glam::Vec3,
+        rotation: glam::Quat,
+        mid: glam::UVec4,
+    ) -> Self {
+        return Self::new(
+            Self::BOX,
+            center.extend(0.0),
+            half_size.extend(0.0),
+            glam::Vec4::from(rotation.normalize()),
+            mid,
+        );
+    }
+
+    pub fn disk(center: glam::Vec3, normal: glam::Vec3, radius: f32, mid: glam::UVec4) -> Self {
+        return Self::new(
+            Self::DISK,
+            center.extend(radius),
+            normal.normalize().extend(0.0),
+            glam::Vec4::ZERO,
+            mid,
+        );
+    }
+
+    pub fn cylinder(base: glam::Vec3, top: glam::Vec3, radius: f32, mid: glam::UVec4) -> Self {
+        return Self::new(
+            Self::CYLINDER,
+            base.extend(radius),
+            top.extend(0.0),
+            glam::Vec4::ZERO,
+            mid,
+        );
+    }
+
+    fn new(kind: u32, a: glam::Vec4, b: glam::Vec4, c: glam::Vec4, mid: glam::UVec4) -> Self {
+        return KPrimitive { a, b, c, mid, kind };
+    }
+}
+
+shader_types! {
+    mod mesh_layout;
+
+    #[derive(Debug, encase::ShaderType)]
+    pub struct KVertex {
+        pub position: glam::Vec3,
+        pub normal: glam::Vec3,
+        pub uv: glam::Vec2,
+    }
+
+    /// xyz of vertices are indices into the vertex buffer, w is unused
+    #[derive(Debug, Clone, encase::ShaderType)]
+    pub struct KTriangle {
+        pub vertices: glam::UVec4,
+        pub mid: glam::UVec4,
+    }
+
+    /// Places a shape in the world, rays are moved into object
+    /// space before they traverse the BVH of the shape
+    #[derive(Debug, Clone, encase::ShaderType)]
+    pub struct KInstance {
+        pub world_to_object: glam::Mat4,
+        /// material of the instance, a zero type keeps the materials of the shape's primitives
+        pub mid: glam::UVec4,
+        /// index of the root node of the shape's BVH
+        pub root: u32,
+    }
 }
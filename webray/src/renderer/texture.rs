@@ -0,0 +1,139 @@
+/// Linear rgba32float texel data uploaded as a sampled texture
+#[derive(Debug)]
+pub struct KTexture {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<f32>,
+}
+
+impl KTexture {
+    /// 1x1 black texture bound when a scene has no texture for a slot
+    pub fn placeholder() -> Self {
+        return KTexture {
+            width: 1,
+            height: 1,
+            data: vec![0.0, 0.0, 0.0, 1.0],
+        };
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        return bytemuck::cast_slice(&self.data[..]);
+    }
+}
+
+/// Every image texture of a scene packed into one sampled texture,
+/// which keeps the binding count fixed however many images there are
+#[derive(Debug)]
+pub struct KTextureAtlas {
+    pub texture: KTexture,
+    /// x, y, width, height of every packed image
+    pub rects: Vec<glam::UVec4>,
+}
+
+impl KTextureAtlas {
+    /// Shelf packs the images tallest first into rows about as wide as the atlas is tall
+    pub fn pack(images: &[KTexture]) -> Self {
+        if images.is_empty() {
+            return KTextureAtlas {
+                texture: KTexture::placeholder(),
+                rects: Vec::new(),
+            };
+        }
+
+        let area: u64 = images
+            .iter()
+            .map(|image| return image.width as u64 * image.height as u64)
+            .sum();
+        let widest = images.iter().map(|image| return image.width).max().unwrap();
+
+        let width = widest.max((area as f64).sqrt().ceil() as u32);
+
+        let mut order: Vec<usize> = (0..images.len()).collect();
+        order.sort_by_key(|i| return std::cmp::Reverse(images[*i].height));
+
+        let mut rects = vec![glam::UVec4::ZERO; images.len()];
+        let mut cursor = glam::UVec2::ZERO;
+        let mut shelf_height = 0;
+
+        for i in order {
+            let image = &images[i];
+
+            if cursor.x + image.width > width {
+                cursor = glam::uvec2(0, cursor.y + shelf_height);
+                shelf_height = 0;
+            }
+
+            rects[i] = glam::uvec4(cursor.x, cursor.y, image.width, image.height);
+
+            cursor.x += image.width;
+            shelf_height = shelf_height.max(image.height);
+        }
+
+        let height = cursor.y + shelf_height;
+        let mut data = vec![0.0; (width * height * 4) as usize];
+
+        for (image, rect) in images.iter().zip(rects.iter()) {
+            let row_size = (image.width * 4) as usize;
+
+            for row in 0..image.height {
+                let src = (row * image.width * 4) as usize;
+                let dst = (((rect.y + row) * width + rect.x) * 4) as usize;
+
+                data[dst..dst + row_size].copy_from_slice(&image.data[src..src + row_size]);
+            }
+        }
+
+        return KTextureAtlas {
+            texture: KTexture {
+                width,
+                height,
+                data,
+            },
+            rects,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, value: f32) -> KTexture {
+        return KTexture {
+            width,
+            height,
+            data: vec![value; (width * height * 4) as usize],
+        };
+    }
+
+    #[test]
+    fn packed_images_keep_their_texels_and_dont_overlap() {
+        let images: Vec<KTexture> = [(64, 32), (16, 16), (100, 7), (1, 1), (33, 64), (64, 64)]
+            .iter()
+            .enumerate()
+            .map(|(i, (width, height))| return solid(*width, *height, i as f32 + 1.0))
+            .collect();
+
+        let atlas = KTextureAtlas::pack(&images);
+        let texture = &atlas.texture;
+
+        let mut owner = vec![0.0; (texture.width * texture.height) as usize];
+
+        for (i, rect) in atlas.rects.iter().enumerate() {
+            assert_eq!(rect.z, images[i].width);
+            assert_eq!(rect.w, images[i].height);
+            assert!(rect.x + rect.z <= texture.width && rect.y + rect.w <= texture.height);
+
+            for y in rect.y..rect.y + rect.w {
+                for x in rect.x..rect.x + rect.z {
+                    let texel = (y * texture.width + x) as usize;
+
+                    assert_eq!(owner[texel], 0.0, "images overlap at {}, {}", x, y);
+                    owner[texel] = i as f32 + 1.0;
+
+                    assert_eq!(texture.data[texel * 4], i as f32 + 1.0);
+                }
+            }
+        }
+    }
+}
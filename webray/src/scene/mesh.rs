@@ -0,0 +1,121 @@
+use crate::core::error::WebRayError;
+
+use super::types::WMeshSource;
+
+/// Indexed triangle list with one normal and uv per vertex
+pub struct MeshData {
+    pub positions: Vec<glam::Vec3>,
+    pub normals: Vec<glam::Vec3>,
+    pub uvs: Vec<glam::Vec2>,
+    pub indices: Vec<u32>,
+}
+
+impl WMeshSource {
+    pub fn load(&self) -> Result<MeshData, WebRayError> {
+        return match self {
+            WMeshSource::Inline {
+                vertices,
+                indices,
+                normals,
+                uvs,
+            } => Ok(MeshData {
+                positions: vertices.clone(),
+                normals: match normals {
+                    Some(normals) => normals.clone(),
+                    None => smooth_normals(vertices, indices),
+                },
+                uvs: match uvs {
+                    Some(uvs) => uvs.clone(),
+                    None => vec![glam::Vec2::ZERO; vertices.len()],
+                },
+                indices: indices.clone(),
+            }),
+            WMeshSource::Obj { path } => load_obj(path),
+        };
+    }
+}
+
+/// Loads every model in the obj file into a single mesh,
+/// materials referenced by the file are ignored
+fn load_obj(path: &str) -> Result<MeshData, WebRayError> {
+    let (models, _) = tobj::load_obj(
+        path,
+        &tobj::LoadOptions {
+            triangulate: true,
+            single_index: true,
+            ..Default::default()
+        },
+    )
+    .map_err(|e| return WebRayError::AssetLoad(format!("{}: {}", path, e)))?;
+
+    let mut mesh_data = MeshData {
+        positions: Vec::new(),
+        normals: Vec::new(),
+        uvs: Vec::new(),
+        indices: Vec::new(),
+    };
+
+    for model in models {
+        let mesh = model.mesh;
+        let offset = mesh_data.positions.len() as u32;
+
+        let positions: Vec<glam::Vec3> = mesh
+            .positions
+            .chunks_exact(3)
+            .map(glam::Vec3::from_slice)
+            .collect();
+
+        // single_index guarantees normals line up with positions when present
+        let normals = if mesh.normals.len() == mesh.positions.len() {
+            mesh.normals
+                .chunks_exact(3)
+                .map(glam::Vec3::from_slice)
+                .map(glam::Vec3::normalize_or_zero)
+                .collect()
+        } else {
+            smooth_normals(&positions, &mesh.indices)
+        };
+
+        let uvs = if mesh.texcoords.len() / 2 == positions.len() {
+            mesh.texcoords
+                .chunks_exact(2)
+                .map(glam::Vec2::from_slice)
+                .collect()
+        } else {
+            vec![glam::Vec2::ZERO; positions.len()]
+        };
+
+        mesh_data.positions.extend(positions);
+        mesh_data.normals.extend(normals);
+        mesh_data.uvs.extend(uvs);
+
+        for index in mesh.indices {
+            mesh_data.indices.push(index + offset);
+        }
+    }
+
+    return Ok(mesh_data);
+}
+
+/// Area weighted average of the face normals around each vertex
+fn smooth_normals(positions: &[glam::Vec3], indices: &[u32]) -> Vec<glam::Vec3> {
+    let mut normals = vec![glam::Vec3::ZERO; positions.len()];
+
+    for triangle in indices.chunks_exact(3) {
+        let a = triangle[0] as usize;
+        let b = triangle[1] as usize;
+        let c = triangle[2] as usize;
+
+        // cross product length is twice the triangle area
+        let face_normal = (positions[b] - positions[a]).cross(positions[c] - positions[a]);
+
+        normals[a] += face_normal;
+        normals[b] += face_normal;
+        normals[c] += face_normal;
+    }
+
+    return normals
+        .into_iter()
+        .map(glam::Vec3::normalize_or_zero)
+        .collect();
+}
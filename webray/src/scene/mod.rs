@@ -1,81 +1,282 @@
 use std::collections::HashMap;
 
 use crate::{
+    core::error::WebRayError,
     renderer::{
-        config::{CameraConfig, KernelConfig, RenderConfig, TileSize},
-        material::{KDielectricMat, KDiffuseMat, KMetalMat},
-        scene::KernelScene,
-        shapes::KSphere,
+        aov::Aov,
+        config::{
+            BackgroundConfig, BackgroundMode, CameraConfig, KernelConfig, ProjectionMode,
+            RenderConfig, TileSize,
+        },
+        denoise::DenoiseConfig,
+        light::KLight,
+        material::KMaterial,
+        post::Tonemap,
+        scene::{KernelScene, WORLD_SHAPE},
+        shapes::{KPrimitive, KVertex},
+        texture::{KTexture, KTextureAtlas},
     },
     utils::color::hex_to_rgb,
 };
 
-use self::types::{WMaterialType, WObjectType, WScene};
+use self::{
+    texture::MAX_TEXTURE_DIMENSION,
+    types::{
+        WAovs, WBackgroundType, WColor, WImageSource, WLightType, WMaterialType, WObjectType,
+        WScene, WTexture,
+    },
+};
 
+pub mod mesh;
+pub mod texture;
 pub mod types;
+pub mod validation;
 
 impl WScene {
-    pub fn get_kernel_scene(&self) -> KernelScene {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_json(json: &str) -> Result<Self, WebRayError> {
+        return serde_json::from_str::<WScene>(json)
+            .map_err(|e| return WebRayError::SceneParse(e.to_string()));
+    }
+
+    pub fn get_kernel_scene(&self) -> Result<KernelScene, WebRayError> {
         let mut kernel_scene = KernelScene::new();
 
+        // images are packed into one atlas before materials can point into it
+        let mut image_sources: Vec<&WImageSource> = Vec::new();
+
+        for mat in self.materials[..].iter() {
+            if let WMaterialType::Diffuse {
+                color: WColor::Texture(WTexture::Image { source, .. }),
+            }
+            | WMaterialType::Metal {
+                color: WColor::Texture(WTexture::Image { source, .. }),
+                ..
+            } = &mat.mat_type
+            {
+                if !image_sources.contains(&source) {
+                    image_sources.push(source);
+                }
+            }
+        }
+
+        let images = image_sources
+            .iter()
+            .map(|source| return source.load_linear())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let atlas = KTextureAtlas::pack(&images);
+
+        if atlas.texture.width > MAX_TEXTURE_DIMENSION
+            || atlas.texture.height > MAX_TEXTURE_DIMENSION
+        {
+            return Err(WebRayError::SceneValidation(format!(
+                "Image textures need a {}x{} atlas, more than the {}px limit",
+                atlas.texture.width, atlas.texture.height, MAX_TEXTURE_DIMENSION
+            )));
+        }
+
+        let images = Images {
+            sources: image_sources,
+            rects: atlas.rects,
+        };
+
         let mut materials: HashMap<usize, (u32, u32)> = HashMap::new();
 
         for mat in self.materials[..].iter() {
-            match &mat.mat_type {
-                // because of color have to do a borrow
+            // because of color have to do a borrow
+            let (mat_type, material) = match &mat.mat_type {
                 WMaterialType::Diffuse { color } => {
-                    let albedo = hex_to_rgb(color).unwrap();
-                    let idx = kernel_scene.register_diffuse_material(KDiffuseMat { albedo });
-                    materials.insert(mat.id.try_into().unwrap(), (1, idx));
-                }
-                WMaterialType::Metal { color, roughness } => {
-                    let albedo = hex_to_rgb(color).unwrap();
-                    let idx = kernel_scene.register_metal_material(KMetalMat {
-                        albedo,
-                        roughness: *roughness,
-                    });
-                    materials.insert(mat.id.try_into().unwrap(), (2, idx));
-                }
-                WMaterialType::Dielectric { ior } => {
-                    let idx =
-                        kernel_scene.register_dielectric_material(KDielectricMat { ior: *ior });
-                    materials.insert(mat.id.try_into().unwrap(), (3, idx));
+                    (KMaterial::DIFFUSE, textured_material(color, 0.0, &images)?)
                 }
-            }
+                WMaterialType::Metal { color, roughness } => (
+                    KMaterial::METAL,
+                    textured_material(color, *roughness, &images)?,
+                ),
+                WMaterialType::Dielectric { ior } => (
+                    KMaterial::DIELECTRIC,
+                    KMaterial::constant(glam::Vec3::ONE, *ior),
+                ),
+                WMaterialType::Emissive { color, strength } => (
+                    KMaterial::EMISSIVE,
+                    KMaterial::constant(parse_color(color)?, *strength),
+                ),
+            };
+
+            let idx = kernel_scene.register_material(KMaterial {
+                id: mat.id,
+                ..material
+            });
+            materials.insert(mat.id.try_into().unwrap(), (mat_type, idx));
         }
 
+        for light in self.lights[..].iter() {
+            let light = match &light.light_type {
+                WLightType::Point {
+                    position,
+                    color,
+                    intensity,
+                } => KLight::point(*position, parse_color(color)? * *intensity),
+                WLightType::Spot {
+                    position,
+                    direction,
+                    color,
+                    intensity,
+                    angle,
+                    blend,
+                } => KLight::spot(
+                    *position,
+                    *direction,
+                    *angle,
+                    *angle * (1.0 - *blend),
+                    parse_color(color)? * *intensity,
+                ),
+                WLightType::Directional {
+                    direction,
+                    color,
+                    intensity,
+                    angular_radius,
+                } => KLight::directional(
+                    *direction,
+                    *angular_radius,
+                    parse_color(color)? * *intensity,
+                ),
+            };
+
+            kernel_scene.register_light(light);
+        }
+
+        // shared shapes are registered once, when the first instance references them
+        let mut shapes: HashMap<u32, u32> = HashMap::new();
+
         for obj in self.objects[..].iter() {
-            match obj.obj_type {
-                WObjectType::Sphere { position, radius } => {
-                    let mat_res = materials.get(&obj.material_id);
-
-                    match mat_res {
-                        Some(mat) => {
-                            kernel_scene.register_sphere(KSphere {
-                                center: position,
-                                radius,
-                                mid: glam::uvec4(mat.0, mat.1, 0, 0),
-                            });
+            let mid = match materials.get(&obj.material_id) {
+                Some(mat) => glam::uvec4(mat.0, mat.1, 0, obj.id + 1),
+                None => {
+                    return Err(WebRayError::SceneValidation(format!(
+                        "Object {} references unknown material {}",
+                        obj.id, obj.material_id
+                    )))
+                }
+            };
+
+            let transform = obj
+                .transform
+                .as_ref()
+                .map_or(glam::Mat4::IDENTITY, |t| return t.matrix());
+
+            match (&obj.obj_type, &obj.transform) {
+                (WObjectType::Instance { shape_id }, _) => {
+                    let shape = match shapes.get(shape_id) {
+                        Some(shape) => *shape,
+                        None => {
+                            let Some(source) =
+                                self.shapes.iter().find(|s| return s.id == *shape_id)
+                            else {
+                                return Err(WebRayError::SceneValidation(format!(
+                                    "Object {} references unknown shape {}",
+                                    obj.id, shape_id
+                                )));
+                            };
+
+                            let shape = kernel_scene.register_shape();
+                            register_primitives(
+                                &mut kernel_scene,
+                                shape,
+                                &source.shape_type,
+                                glam::UVec4::ZERO,
+                            )?;
+                            shapes.insert(*shape_id, shape);
+
+                            shape
                         }
-                        None => panic!("Material not found: {}", &obj.material_id),
-                    }
+                    };
+
+                    kernel_scene.register_instance(shape, transform, mid);
+                }
+                (obj_type, None) => {
+                    register_primitives(&mut kernel_scene, WORLD_SHAPE, obj_type, mid)?;
+                }
+                (obj_type, Some(_)) => {
+                    let shape = kernel_scene.register_shape();
+                    register_primitives(&mut kernel_scene, shape, obj_type, glam::UVec4::ZERO)?;
+                    kernel_scene.register_instance(shape, transform, mid);
                 }
             }
         }
 
-        return kernel_scene;
+        kernel_scene.build_bvh();
+
+        if !images.sources.is_empty() {
+            kernel_scene.set_texture_atlas(atlas.texture);
+        }
+
+        if let WBackgroundType::Hdri { source } = &self.background.bg_type {
+            let image = source.load()?.into_rgba32f();
+
+            kernel_scene.set_environment_map(KTexture {
+                width: image.width(),
+                height: image.height(),
+                data: image.into_raw(),
+            });
+        }
+
+        return Ok(kernel_scene);
     }
 
-    pub fn get_kernel_config(&self) -> KernelConfig {
+    pub fn get_kernel_config(&self) -> Result<KernelConfig, WebRayError> {
         let render_config = RenderConfig {
             width: self.render_settings.width,
             height: self.render_settings.height,
             samples: self.render_settings.samples,
+            samples_per_pass: self
+                .render_settings
+                .samples_per_pass
+                .unwrap_or(self.render_settings.samples),
             bounces: self.render_settings.bounces,
+            diffuse_bounces: self
+                .render_settings
+                .diffuse_bounces
+                .unwrap_or(self.render_settings.bounces),
+            glossy_bounces: self
+                .render_settings
+                .glossy_bounces
+                .unwrap_or(self.render_settings.bounces),
+            transmission_bounces: self
+                .render_settings
+                .transmission_bounces
+                .unwrap_or(self.render_settings.bounces),
+            roulette_depth: self.render_settings.roulette_depth,
+            aovs: self.render_settings.aovs.selected(),
+            denoise: self.render_settings.denoise.as_ref().map(|denoise| {
+                return DenoiseConfig {
+                    iterations: denoise.iterations,
+                    color_sigma: denoise.color_sigma,
+                    normal_sigma: denoise.normal_sigma,
+                    albedo_sigma: denoise.albedo_sigma,
+                };
+            }),
+            time_limit: self
+                .render_settings
+                .time_limit
+                .map(|time_limit| return parse_duration("time_limit", time_limit))
+                .transpose()?,
             tile_size: match self.render_settings.tile_size {
                 types::WTileSize::Full => TileSize::Full,
                 types::WTileSize::Tile { size } => TileSize::Tile(size),
+                types::WTileSize::Auto => TileSize::Auto,
+            },
+            dispatch_budget: parse_duration(
+                "dispatch_budget",
+                self.render_settings.dispatch_budget / 1000.0,
+            )?,
+            exposure: self.render_settings.post.exposure,
+            tonemap: match self.render_settings.post.tonemap {
+                types::WTonemap::None => Tonemap::None,
+                types::WTonemap::Reinhard => Tonemap::Reinhard,
+                types::WTonemap::Aces => Tonemap::Aces,
             },
+            seed: self.render_settings.seed,
         };
 
         let camera_config = CameraConfig {
@@ -85,8 +286,209 @@ impl WScene {
             v_fov: self.camera.v_fov,
             dof_angle: self.camera.dof_angle,
             dof_distance: self.camera.dof_distance,
+            projection: match self.camera.projection {
+                types::WProjection::Perspective => ProjectionMode::Perspective,
+                types::WProjection::Orthographic { .. } => ProjectionMode::Orthographic,
+                types::WProjection::Fisheye => ProjectionMode::Fisheye,
+                types::WProjection::Equirectangular => ProjectionMode::Equirectangular,
+            },
+            view_height: match self.camera.projection {
+                types::WProjection::Orthographic { view_height } => view_height,
+                _ => 0.0,
+            },
         };
 
-        return KernelConfig::new(&render_config, &camera_config);
+        let background_config = match &self.background.bg_type {
+            WBackgroundType::Solid { color } => BackgroundConfig {
+                mode: BackgroundMode::Solid,
+                color_a: parse_color(color)?,
+                color_b: glam::Vec3::ZERO,
+                rotation: self.background.rotation,
+                intensity: self.background.intensity,
+            },
+            WBackgroundType::Gradient { top, bottom } => BackgroundConfig {
+                mode: BackgroundMode::Gradient,
+                color_a: parse_color(top)?,
+                color_b: parse_color(bottom)?,
+                rotation: self.background.rotation,
+                intensity: self.background.intensity,
+            },
+            WBackgroundType::Hdri { .. } => BackgroundConfig {
+                mode: BackgroundMode::EnvironmentMap,
+                color_a: glam::Vec3::ZERO,
+                color_b: glam::Vec3::ZERO,
+                rotation: self.background.rotation,
+                intensity: self.background.intensity,
+            },
+        };
+
+        return Ok(KernelConfig::new(
+            &render_config,
+            &camera_config,
+            &background_config,
+        ));
+    }
+}
+
+impl WAovs {
+    /// Enabled aovs in output order
+    pub fn selected(&self) -> Vec<Aov> {
+        let enabled = [
+            self.albedo,
+            self.normal,
+            self.depth,
+            self.object_id,
+            self.material_id,
+        ];
+
+        return Aov::ALL
+            .into_iter()
+            .zip(enabled)
+            .filter(|(_, enabled)| return *enabled)
+            .map(|(aov, _)| return aov)
+            .collect();
+    }
+}
+
+/// Adds the primitive or mesh to the shape, mid is stored on the primitives
+fn register_primitives(
+    kernel_scene: &mut KernelScene,
+    shape: u32,
+    obj_type: &WObjectType,
+    mid: glam::UVec4,
+) -> Result<(), WebRayError> {
+    match obj_type {
+        WObjectType::Sphere { position, radius } => {
+            kernel_scene.register_primitive(shape, KPrimitive::sphere(*position, *radius, mid));
+        }
+        WObjectType::Plane { point, normal } => {
+            kernel_scene.register_primitive(shape, KPrimitive::plane(*point, *normal, mid));
+        }
+        WObjectType::Quad { corner, u, v } => {
+            kernel_scene.register_primitive(shape, KPrimitive::quad(*corner, *u, *v, mid));
+        }
+        WObjectType::Box { min, max } => {
+            kernel_scene.register_primitive(
+                shape,
+                KPrimitive::oriented_box(
+                    (*min + *max) * 0.5,
+                    (*max - *min) * 0.5,
+                    glam::Quat::IDENTITY,
+                    mid,
+                ),
+            );
+        }
+        WObjectType::OrientedBox {
+            center,
+            size,
+            rotation,
+        } => {
+            kernel_scene.register_primitive(
+                shape,
+                KPrimitive::oriented_box(*center, *size * 0.5, rotation.quat(), mid),
+            );
+        }
+        WObjectType::Disk {
+            center,
+            normal,
+            radius,
+        } => {
+            kernel_scene
+                .register_primitive(shape, KPrimitive::disk(*center, *normal, *radius, mid));
+        }
+        WObjectType::Cylinder { base, top, radius } => {
+            kernel_scene.register_primitive(shape, KPrimitive::cylinder(*base, *top, *radius, mid));
+        }
+        WObjectType::Mesh { source } => {
+            let mesh = source.load()?;
+
+            let mut vertices = Vec::with_capacity(mesh.positions.len());
+
+            for ((position, normal), uv) in mesh
+                .positions
+                .iter()
+                .zip(mesh.normals.iter())
+                .zip(mesh.uvs.iter())
+            {
+                vertices.push(KVertex {
+                    position: *position,
+                    normal: *normal,
+                    uv: *uv,
+                });
+            }
+
+            kernel_scene.register_mesh(shape, vertices, &mesh.indices, mid);
+        }
+        WObjectType::Instance { .. } => {
+            return Err(WebRayError::SceneValidation(String::from(
+                "Instances can't be nested in shapes",
+            )));
+        }
     }
+
+    return Ok(());
+}
+
+/// Image sources of a scene and where each ended up in the atlas
+struct Images<'a> {
+    sources: Vec<&'a WImageSource>,
+    rects: Vec<glam::UVec4>,
+}
+
+fn textured_material(
+    color: &WColor,
+    param: f32,
+    images: &Images,
+) -> Result<KMaterial, WebRayError> {
+    let texture = match color {
+        WColor::Hex(hex) => return Ok(KMaterial::constant(parse_color(hex)?, param)),
+        WColor::Texture(texture) => texture,
+    };
+
+    let mut material = KMaterial::constant(glam::Vec3::ONE, param);
+
+    match texture {
+        WTexture::Image { source, scale } => {
+            let index = images
+                .sources
+                .iter()
+                .position(|s| return *s == source)
+                .unwrap();
+
+            material.texture = KMaterial::TEXTURE_IMAGE;
+            material.image = images.rects[index];
+            material.scale = *scale;
+        }
+        WTexture::Checker { even, odd, scale } => {
+            material.texture = KMaterial::TEXTURE_CHECKER;
+            material.color = parse_color(even)?;
+            material.color_b = parse_color(odd)?;
+            material.scale = *scale;
+        }
+        WTexture::Noise {
+            low,
+            high,
+            scale,
+            octaves,
+        } => {
+            material.texture = KMaterial::TEXTURE_NOISE;
+            material.color = parse_color(low)?;
+            material.color_b = parse_color(high)?;
+            material.scale = *scale;
+            material.octaves = *octaves;
+        }
+    }
+
+    return Ok(material);
+}
+
+fn parse_color(hex: &str) -> Result<glam::Vec3, WebRayError> {
+    return hex_to_rgb(hex)
+        .ok_or_else(|| return WebRayError::SceneValidation(format!("Invalid color: {}", hex)));
+}
+
+fn parse_duration(name: &str, seconds: f32) -> Result<std::time::Duration, WebRayError> {
+    return std::time::Duration::try_from_secs_f32(seconds).map_err(|e| {
+        return WebRayError::SceneValidation(format!("Invalid {}: {}", name, e));
+    });
 }
@@ -0,0 +1,72 @@
+use crate::{core::error::WebRayError, renderer::texture::KTexture};
+
+use super::types::WImageSource;
+
+/// Largest texture dimension guaranteed by the default wgpu limits
+pub const MAX_TEXTURE_DIMENSION: u32 = 8192;
+
+impl WImageSource {
+    pub fn load(&self) -> Result<image::DynamicImage, WebRayError> {
+        let image = match self {
+            WImageSource::Path { path } => image::open(path)
+                .map_err(|e| return WebRayError::AssetLoad(format!("{}: {}", path, e)))?,
+            WImageSource::Bytes { bytes } => image::load_from_memory(bytes)
+                .map_err(|e| return WebRayError::AssetLoad(e.to_string()))?,
+        };
+
+        if image.width() > MAX_TEXTURE_DIMENSION || image.height() > MAX_TEXTURE_DIMENSION {
+            log::warn!(
+                "Image of size {}x{} exceeds {}px, downscaling",
+                image.width(),
+                image.height(),
+                MAX_TEXTURE_DIMENSION
+            );
+
+            // resize preserves the aspect ratio
+            return Ok(image.resize(
+                MAX_TEXTURE_DIMENSION,
+                MAX_TEXTURE_DIMENSION,
+                image::imageops::FilterType::Triangle,
+            ));
+        }
+
+        return Ok(image);
+    }
+
+    /// Texels in linear rgb, 8 and 16 bit images are assumed to be srgb encoded
+    pub fn load_linear(&self) -> Result<KTexture, WebRayError> {
+        let image = self.load()?;
+
+        let is_float = matches!(
+            image,
+            image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_)
+        );
+
+        let image = image.into_rgba32f();
+        let (width, height) = image.dimensions();
+        let mut data = image.into_raw();
+
+        if !is_float {
+            for texel in data.chunks_exact_mut(4) {
+                for channel in texel[..3].iter_mut() {
+                    *channel = srgb_eotf(*channel);
+                }
+            }
+        }
+
+        return Ok(KTexture {
+            width,
+            height,
+            data,
+        });
+    }
+}
+
+/// Inverse of the srgb transfer function applied on readback
+fn srgb_eotf(x: f32) -> f32 {
+    if x <= 0.04045 {
+        return x / 12.92;
+    }
+
+    return ((x + 0.055) / 1.055).powf(2.4);
+}
@@ -1,31 +1,180 @@
 use core::fmt;
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct WScene {
     pub objects: Vec<WObject>,
+    /// Shapes placed in the world by instance objects
+    #[serde(default)]
+    pub shapes: Vec<WShape>,
     pub materials: Vec<WMaterial>,
+    /// Point, spot and directional lights, emissive spheres, quads and disks are lights as well
+    #[serde(default)]
+    pub lights: Vec<WLight>,
     pub camera: WCamera,
     pub render_settings: WRenderSettings,
+    #[serde(default)]
+    pub background: WBackground,
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct WObject {
     pub id: u32,
     pub name: String,
     pub material_id: usize,
+    #[serde(default)]
+    pub transform: Option<WTransform>,
 
     #[serde(rename = "type")]
     pub obj_type: WObjectType,
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+/// Shape that any number of objects can instance, stored once in the kernel scene
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct WShape {
+    pub id: u32,
+    pub name: String,
+
+    /// Primitive or mesh, shapes can't be instances themselves
+    #[serde(rename = "type")]
+    pub shape_type: WObjectType,
+}
+
+/// Scale, then rotation, then translation
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct WTransform {
+    #[serde(default)]
+    pub translation: glam::Vec3,
+    #[serde(default)]
+    pub rotation: WRotation,
+    #[serde(default = "default_scale")]
+    pub scale: glam::Vec3,
+}
+
+fn default_scale() -> glam::Vec3 {
+    return glam::Vec3::ONE;
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type")]
+pub enum WRotation {
+    /// Degrees around x, then y, then z
+    #[serde(rename = "d_rotation_euler")]
+    Euler { degrees: glam::Vec3 },
+
+    /// x, y, z, w, normalized on use
+    #[serde(rename = "d_rotation_quat")]
+    Quat { quat: glam::Quat },
+}
+
+impl Default for WRotation {
+    fn default() -> Self {
+        return WRotation::Euler {
+            degrees: glam::Vec3::ZERO,
+        };
+    }
+}
+
+impl WRotation {
+    pub fn quat(&self) -> glam::Quat {
+        return match self {
+            WRotation::Euler { degrees } => glam::Quat::from_euler(
+                glam::EulerRot::XYZ,
+                degrees.x.to_radians(),
+                degrees.y.to_radians(),
+                degrees.z.to_radians(),
+            ),
+            WRotation::Quat { quat } => quat.normalize(),
+        };
+    }
+}
+
+impl WTransform {
+    pub fn matrix(&self) -> glam::Mat4 {
+        return glam::Mat4::from_scale_rotation_translation(
+            self.scale,
+            self.rotation.quat(),
+            self.translation,
+        );
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 #[serde(tag = "type")]
 pub enum WObjectType {
     #[serde(rename = "d_sphere")]
     Sphere { position: glam::Vec3, radius: f32 },
+
+    /// Infinite plane through the point
+    #[serde(rename = "d_plane")]
+    Plane {
+        point: glam::Vec3,
+        normal: glam::Vec3,
+    },
+
+    /// Parallelogram spanned by the edges u and v from the corner
+    #[serde(rename = "d_quad")]
+    Quad {
+        corner: glam::Vec3,
+        u: glam::Vec3,
+        v: glam::Vec3,
+    },
+
+    /// Axis aligned box
+    #[serde(rename = "d_box")]
+    Box { min: glam::Vec3, max: glam::Vec3 },
+
+    /// Box of the given edge lengths rotated around its center
+    #[serde(rename = "d_oriented_box")]
+    OrientedBox {
+        center: glam::Vec3,
+        size: glam::Vec3,
+        #[serde(default)]
+        rotation: WRotation,
+    },
+
+    #[serde(rename = "d_disk")]
+    Disk {
+        center: glam::Vec3,
+        normal: glam::Vec3,
+        radius: f32,
+    },
+
+    /// Capped cylinder between the centers of its base and top
+    #[serde(rename = "d_cylinder")]
+    Cylinder {
+        base: glam::Vec3,
+        top: glam::Vec3,
+        radius: f32,
+    },
+
+    #[serde(rename = "d_mesh")]
+    Mesh { source: WMeshSource },
+
+    /// Places the shape with the given id, the object's transform and material apply to it
+    #[serde(rename = "d_instance")]
+    Instance { shape_id: u32 },
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type")]
+pub enum WMeshSource {
+    /// Triangle list, normals are generated when not provided
+    #[serde(rename = "d_mesh_inline")]
+    Inline {
+        vertices: Vec<glam::Vec3>,
+        indices: Vec<u32>,
+        normals: Option<Vec<glam::Vec3>>,
+        /// Texture coordinates per vertex, zero when not provided
+        #[serde(default)]
+        uvs: Option<Vec<glam::Vec2>>,
+    },
+
+    /// Wavefront OBJ file, faces are triangulated on load
+    #[serde(rename = "d_mesh_obj")]
+    Obj { path: String },
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct WMaterial {
     pub id: u32,
     pub name: String,
@@ -34,20 +183,121 @@ pub struct WMaterial {
     pub mat_type: WMaterialType,
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 #[serde(tag = "type")]
 pub enum WMaterialType {
     #[serde(rename = "d_mat_diffuse")]
-    Diffuse { color: String },
+    Diffuse { color: WColor },
 
     #[serde(rename = "d_mat_metal")]
-    Metal { color: String, roughness: f32 },
+    Metal { color: WColor, roughness: f32 },
 
     #[serde(rename = "d_mat_dielectric")]
     Dielectric { ior: f32 },
+
+    #[serde(rename = "d_mat_emissive")]
+    Emissive { color: String, strength: f32 },
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct WLight {
+    pub id: u32,
+    pub name: String,
+
+    #[serde(rename = "type")]
+    pub light_type: WLightType,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type")]
+pub enum WLightType {
+    /// Light from a single point falling off with the squared distance
+    #[serde(rename = "d_light_point")]
+    Point {
+        position: glam::Vec3,
+        color: String,
+        intensity: f32,
+    },
+
+    /// Point light limited to a cone, angle is the cone's half angle in degrees
+    /// and blend the fraction of it that fades out towards the edge
+    #[serde(rename = "d_light_spot")]
+    Spot {
+        position: glam::Vec3,
+        direction: glam::Vec3,
+        color: String,
+        intensity: f32,
+        angle: f32,
+        #[serde(default = "default_spot_blend")]
+        blend: f32,
+    },
+
+    /// Light arriving from far away, direction is where the light travels and
+    /// angular_radius the apparent size of the source in degrees, 0 for hard shadows
+    #[serde(rename = "d_light_directional")]
+    Directional {
+        direction: glam::Vec3,
+        color: String,
+        intensity: f32,
+        #[serde(default)]
+        angular_radius: f32,
+    },
+}
+
+fn default_spot_blend() -> f32 {
+    return 0.15;
+}
+
+/// Hex color or a texture, plain strings keep color fields written before textures working
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub enum WColor {
+    Hex(String),
+    Texture(WTexture),
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type")]
+pub enum WTexture {
+    /// Image wrapped over the surface's uv, repeated scale times per uv unit
+    #[serde(rename = "d_tex_image")]
+    Image {
+        source: WImageSource,
+        #[serde(default = "default_texture_scale")]
+        scale: f32,
+    },
+
+    /// Alternating squares in uv space, scale squares per uv unit
+    #[serde(rename = "d_tex_checker")]
+    Checker {
+        even: String,
+        odd: String,
+        #[serde(default = "default_texture_scale")]
+        scale: f32,
+    },
+
+    /// fBm of gradient noise over the world position, blends from low to high.
+    /// scale is the frequency of the first octave per world unit
+    #[serde(rename = "d_tex_noise")]
+    Noise {
+        low: String,
+        high: String,
+        #[serde(default = "default_texture_scale")]
+        scale: f32,
+        #[serde(default = "default_octaves")]
+        octaves: u32,
+    },
+}
+
+fn default_texture_scale() -> f32 {
+    return 1.0;
+}
+
+fn default_octaves() -> u32 {
+    return 4;
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct WCamera {
     pub look_from: glam::Vec3,
     pub look_at: glam::Vec3,
@@ -55,18 +305,212 @@ pub struct WCamera {
     pub v_fov: f32,
     pub dof_angle: f32,
     pub dof_distance: f32,
+    #[serde(default)]
+    pub projection: WProjection,
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type")]
+pub enum WProjection {
+    #[default]
+    #[serde(rename = "d_projection_perspective")]
+    Perspective,
+
+    /// Parallel rays from a view of this height in world units, v_fov is unused
+    #[serde(rename = "d_projection_orthographic")]
+    Orthographic { view_height: f32 },
+
+    /// Equidistant fisheye, v_fov is the angle across the image height and may reach 360,
+    /// without depth of field
+    #[serde(rename = "d_projection_fisheye")]
+    Fisheye,
+
+    /// Full 360 by 180 panorama around the camera, without depth of field
+    #[serde(rename = "d_projection_equirectangular")]
+    Equirectangular,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct WBackground {
+    /// rotation around the up axis in degrees
+    #[serde(default)]
+    pub rotation: f32,
+    #[serde(default = "default_intensity")]
+    pub intensity: f32,
+
+    #[serde(rename = "type")]
+    pub bg_type: WBackgroundType,
+}
+
+fn default_intensity() -> f32 {
+    return 1.0;
+}
+
+impl Default for WBackground {
+    /// Sky gradient used before backgrounds were configurable
+    fn default() -> Self {
+        return WBackground {
+            rotation: 0.0,
+            intensity: 1.0,
+            bg_type: WBackgroundType::Gradient {
+                top: String::from("#4D99FF"),
+                bottom: String::from("#FFFFFF"),
+            },
+        };
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type")]
+pub enum WBackgroundType {
+    #[serde(rename = "d_bg_solid")]
+    Solid { color: String },
+
+    #[serde(rename = "d_bg_gradient")]
+    Gradient { top: String, bottom: String },
+
+    /// Equirectangular environment map
+    #[serde(rename = "d_bg_hdri")]
+    Hdri { source: WImageSource },
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type")]
+pub enum WImageSource {
+    /// Image file on disk, native only
+    #[serde(rename = "d_image_path")]
+    Path { path: String },
+
+    /// Encoded image file contents, used on wasm where there is no file system
+    #[serde(rename = "d_image_bytes")]
+    Bytes { bytes: Vec<u8> },
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct WRenderSettings {
     pub width: u32,
     pub height: u32,
     pub samples: u32,
+    /// Most rays a path traces, the per kind limits below only lower it
     pub bounces: u32,
+    /// Most scatters off diffuse surfaces on a path, defaults to bounces
+    #[serde(default)]
+    pub diffuse_bounces: Option<u32>,
+    /// Most scatters off metal on a path, defaults to bounces
+    #[serde(default)]
+    pub glossy_bounces: Option<u32>,
+    /// Most scatters through dielectrics on a path, defaults to bounces
+    #[serde(default)]
+    pub transmission_bounces: Option<u32>,
+    /// Bounces every path takes before russian roulette can end it early
+    #[serde(default = "default_roulette_depth")]
+    pub roulette_depth: u32,
     pub tile_size: WTileSize,
+    /// Milliseconds a single gpu submission should take at most, tiles are rendered
+    /// in as many sample batches as needed so the driver watchdog doesn't reset the device
+    #[serde(default = "default_dispatch_budget")]
+    pub dispatch_budget: f32,
+    /// Progressive mode, resolves and reports the image after every pass of this many samples
+    #[serde(default)]
+    pub samples_per_pass: Option<u32>,
+    #[serde(default)]
+    pub post: WPostSettings,
+    /// Auxiliary images written next to the render
+    #[serde(default)]
+    pub aovs: WAovs,
+    /// Filters the noise out of the render, off when missing
+    #[serde(default)]
+    pub denoise: Option<WDenoiseSettings>,
+    /// Seconds the render may take, once spent the samples taken so far are returned
+    #[serde(default)]
+    pub time_limit: Option<f32>,
+    /// Renders with the same seed are bit-for-bit identical
+    #[serde(default)]
+    pub seed: u32,
+}
+
+fn default_roulette_depth() -> u32 {
+    return 3;
+}
+
+fn default_dispatch_budget() -> f32 {
+    return 100.0;
+}
+
+#[derive(Debug, Clone, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+pub struct WPostSettings {
+    /// exposure compensation in stops
+    #[serde(default)]
+    pub exposure: f32,
+    #[serde(default)]
+    pub tonemap: WTonemap,
+}
+
+/// Aovs to output, all taken from the first hit of the pixel's center ray
+#[derive(Debug, Clone, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+pub struct WAovs {
+    #[serde(default)]
+    pub albedo: bool,
+    /// world space normal
+    #[serde(default)]
+    pub normal: bool,
+    /// distance from the camera
+    #[serde(default)]
+    pub depth: bool,
+    #[serde(default)]
+    pub object_id: bool,
+    #[serde(default)]
+    pub material_id: bool,
+}
+
+/// Edge avoiding a-trous filter, guided by the albedo and normal of the first hit.
+/// Neighbours stop contributing as they get further than a sigma from the pixel
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct WDenoiseSettings {
+    /// Filter passes, every pass doubles the distance between the filter taps
+    #[serde(default = "default_denoise_iterations")]
+    pub iterations: u32,
+    /// Tone mapped color difference, halved every iteration
+    #[serde(default = "default_color_sigma")]
+    pub color_sigma: f32,
+    #[serde(default = "default_normal_sigma")]
+    pub normal_sigma: f32,
+    #[serde(default = "default_albedo_sigma")]
+    pub albedo_sigma: f32,
+}
+
+fn default_denoise_iterations() -> u32 {
+    return 5;
+}
+
+fn default_color_sigma() -> f32 {
+    return 0.5;
+}
+
+fn default_normal_sigma() -> f32 {
+    return 0.3;
+}
+
+fn default_albedo_sigma() -> f32 {
+    return 0.1;
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type")]
+pub enum WTonemap {
+    /// Clamps to the displayable range
+    #[default]
+    #[serde(rename = "d_tonemap_none")]
+    None,
+
+    #[serde(rename = "d_tonemap_reinhard")]
+    Reinhard,
+
+    #[serde(rename = "d_tonemap_aces")]
+    Aces,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 #[serde(tag = "type")]
 pub enum WTileSize {
     #[serde(rename = "d_tile_size_full")]
@@ -74,6 +518,10 @@ pub enum WTileSize {
 
     #[serde(rename = "d_tile_size")]
     Tile { size: u32 },
+
+    /// Largest tile a single sample of fits in the dispatch budget, measured before rendering
+    #[serde(rename = "d_tile_size_auto")]
+    Auto,
 }
 
 impl fmt::Display for WScene {
@@ -84,16 +532,28 @@ impl fmt::Display for WScene {
             write!(f, "\n\tObject: {}", item).unwrap();
         }
 
+        write!(f, "\nShapes:").unwrap();
+
+        for item in &self.shapes {
+            write!(f, "\n\tShape: {}", item).unwrap();
+        }
+
         write!(f, "\nMaterials:").unwrap();
 
         for item in &self.materials {
             write!(f, "\n\tMaterial: {}", item).unwrap();
         }
 
+        write!(f, "\nLights:").unwrap();
+
+        for item in &self.lights {
+            write!(f, "\n\tLight: {}", item).unwrap();
+        }
+
         return write!(
             f,
-            "\nCamera:\n\t{}\nRenderSettings:\n\t{}",
-            self.camera, self.render_settings
+            "\nCamera:\n\t{}\nRenderSettings:\n\t{}\nBackground:\n\t{}",
+            self.camera, self.render_settings, self.background
         );
     }
 }
@@ -102,18 +562,102 @@ impl fmt::Display for WObject {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         return write!(
             f,
-            "\n\t\tID: {}\n\t\tname: {}\n\t\tmaterial_id: {}\n\t\ttype: {}",
-            self.id, self.name, self.material_id, self.obj_type
+            "\n\t\tID: {}\n\t\tname: {}\n\t\tmaterial_id: {}\n\t\ttransform: {}\n\t\ttype: {}",
+            self.id,
+            self.name,
+            self.material_id,
+            match &self.transform {
+                Some(transform) => transform.to_string(),
+                None => String::from("NONE"),
+            },
+            self.obj_type
         );
     }
 }
 
+impl fmt::Display for WShape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(
+            f,
+            "\n\t\tID: {}\n\t\tname: {}\n\t\ttype: {}",
+            self.id, self.name, self.shape_type
+        );
+    }
+}
+
+impl fmt::Display for WTransform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(
+            f,
+            "TRANSFORM(translation: {}, rotation: {}, scale: {})",
+            self.translation, self.rotation, self.scale
+        );
+    }
+}
+
+impl fmt::Display for WRotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            WRotation::Euler { degrees } => write!(f, "EULER(degrees: {})", degrees),
+            WRotation::Quat { quat } => write!(f, "QUAT({})", quat),
+        };
+    }
+}
+
 impl fmt::Display for WObjectType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         return match self {
             WObjectType::Sphere { position, radius } => {
                 write!(f, "SPHERE(position: {}, radius: {})", position, radius)
             }
+            WObjectType::Plane { point, normal } => {
+                write!(f, "PLANE(point: {}, normal: {})", point, normal)
+            }
+            WObjectType::Quad { corner, u, v } => {
+                write!(f, "QUAD(corner: {}, u: {}, v: {})", corner, u, v)
+            }
+            WObjectType::Box { min, max } => write!(f, "BOX(min: {}, max: {})", min, max),
+            WObjectType::OrientedBox {
+                center,
+                size,
+                rotation,
+            } => write!(
+                f,
+                "ORIENTED_BOX(center: {}, size: {}, rotation: {})",
+                center, size, rotation
+            ),
+            WObjectType::Disk {
+                center,
+                normal,
+                radius,
+            } => write!(
+                f,
+                "DISK(center: {}, normal: {}, radius: {})",
+                center, normal, radius
+            ),
+            WObjectType::Cylinder { base, top, radius } => write!(
+                f,
+                "CYLINDER(base: {}, top: {}, radius: {})",
+                base, top, radius
+            ),
+            WObjectType::Mesh { source } => write!(f, "MESH(source: {})", source),
+            WObjectType::Instance { shape_id } => write!(f, "INSTANCE(shape_id: {})", shape_id),
+        };
+    }
+}
+
+impl fmt::Display for WMeshSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            WMeshSource::Inline {
+                vertices, indices, ..
+            } => write!(
+                f,
+                "INLINE(vertices: {}, triangles: {})",
+                vertices.len(),
+                indices.len() / 3
+            ),
+            WMeshSource::Obj { path } => write!(f, "OBJ(path: {})", path),
         };
     }
 }
@@ -136,13 +680,109 @@ impl fmt::Display for WMaterialType {
                 write!(f, "METAL(color: {}, roughness: {})", color, roughness)
             }
             WMaterialType::Dielectric { ior } => write!(f, "DIELECTRIC(ior: {})", ior),
+            WMaterialType::Emissive { color, strength } => {
+                write!(f, "EMISSIVE(color: {}, strength: {})", color, strength)
+            }
+        };
+    }
+}
+
+impl fmt::Display for WLight {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(
+            f,
+            "\n\t\tID: {}\n\t\tname: {}\n\t\ttype: {}",
+            self.id, self.name, self.light_type
+        );
+    }
+}
+
+impl fmt::Display for WLightType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            WLightType::Point {
+                position,
+                color,
+                intensity,
+            } => write!(
+                f,
+                "POINT(position: {}, color: {}, intensity: {})",
+                position, color, intensity
+            ),
+            WLightType::Spot {
+                position,
+                direction,
+                color,
+                intensity,
+                angle,
+                blend,
+            } => write!(
+                f,
+                "SPOT(position: {}, direction: {}, color: {}, intensity: {}, angle: {}, blend: {})",
+                position, direction, color, intensity, angle, blend
+            ),
+            WLightType::Directional {
+                direction,
+                color,
+                intensity,
+                angular_radius,
+            } => write!(
+                f,
+                "DIRECTIONAL(direction: {}, color: {}, intensity: {}, angular_radius: {})",
+                direction, color, intensity, angular_radius
+            ),
+        };
+    }
+}
+
+impl fmt::Display for WColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            WColor::Hex(hex) => write!(f, "{}", hex),
+            WColor::Texture(texture) => write!(f, "{}", texture),
+        };
+    }
+}
+
+impl fmt::Display for WTexture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            WTexture::Image { source, scale } => {
+                write!(f, "IMAGE(source: {}, scale: {})", source, scale)
+            }
+            WTexture::Checker { even, odd, scale } => {
+                write!(f, "CHECKER(even: {}, odd: {}, scale: {})", even, odd, scale)
+            }
+            WTexture::Noise {
+                low,
+                high,
+                scale,
+                octaves,
+            } => write!(
+                f,
+                "NOISE(low: {}, high: {}, scale: {}, octaves: {})",
+                low, high, scale, octaves
+            ),
         };
     }
 }
 
 impl fmt::Display for WCamera {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        return write!(f, "look_from: {}\n\tlook_at: {}\n\tv_up: {}\n\tv_fov: {}\n\tdof_angle: {}\n\tdof_distance: {}", self.look_from, self.look_at, self.v_up, self.v_fov, self.dof_angle, self.dof_distance);
+        return write!(f, "look_from: {}\n\tlook_at: {}\n\tv_up: {}\n\tv_fov: {}\n\tdof_angle: {}\n\tdof_distance: {}\n\tprojection: {}", self.look_from, self.look_at, self.v_up, self.v_fov, self.dof_angle, self.dof_distance, self.projection);
+    }
+}
+
+impl fmt::Display for WProjection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            WProjection::Perspective => write!(f, "PERSPECTIVE()"),
+            WProjection::Orthographic { view_height } => {
+                write!(f, "ORTHOGRAPHIC(view_height: {})", view_height)
+            }
+            WProjection::Fisheye => write!(f, "FISHEYE()"),
+            WProjection::Equirectangular => write!(f, "EQUIRECTANGULAR()"),
+        };
     }
 }
 
@@ -150,17 +790,102 @@ impl fmt::Display for WRenderSettings {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         return write!(
             f,
-            "width: {}\n\theight: {}\n\tsamples: {}\n\tbounces: {}\n\ttile size: {}",
-            self.width, self.height, self.samples, self.bounces, self.tile_size
+            "width: {}\n\theight: {}\n\tsamples: {}\n\tbounces: {}\n\tdiffuse bounces: {:?}\n\tglossy bounces: {:?}\n\ttransmission bounces: {:?}\n\troulette depth: {}\n\ttile size: {}\n\tdispatch budget: {}ms\n\tsamples per pass: {:?}\n\tpost: {}\n\taovs: {}\n\tdenoise: {}\n\ttime limit: {:?}\n\tseed: {}",
+            self.width,
+            self.height,
+            self.samples,
+            self.bounces,
+            self.diffuse_bounces,
+            self.glossy_bounces,
+            self.transmission_bounces,
+            self.roulette_depth,
+            self.tile_size,
+            self.dispatch_budget,
+            self.samples_per_pass,
+            self.post,
+            self.aovs,
+            self.denoise
+                .as_ref()
+                .map_or("OFF".to_string(), |d| return d.to_string()),
+            self.time_limit,
+            self.seed
         );
     }
 }
 
+impl fmt::Display for WPostSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "exposure: {}, tonemap: {}", self.exposure, self.tonemap);
+    }
+}
+
+impl fmt::Display for WAovs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(
+            f,
+            "albedo: {}, normal: {}, depth: {}, object id: {}, material id: {}",
+            self.albedo, self.normal, self.depth, self.object_id, self.material_id
+        );
+    }
+}
+
+impl fmt::Display for WDenoiseSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(
+            f,
+            "iterations: {}, color sigma: {}, normal sigma: {}, albedo sigma: {}",
+            self.iterations, self.color_sigma, self.normal_sigma, self.albedo_sigma
+        );
+    }
+}
+
+impl fmt::Display for WTonemap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            WTonemap::None => write!(f, "NONE"),
+            WTonemap::Reinhard => write!(f, "REINHARD"),
+            WTonemap::Aces => write!(f, "ACES"),
+        };
+    }
+}
+
+impl fmt::Display for WBackground {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(
+            f,
+            "rotation: {}\n\tintensity: {}\n\ttype: {}",
+            self.rotation, self.intensity, self.bg_type
+        );
+    }
+}
+
+impl fmt::Display for WBackgroundType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            WBackgroundType::Solid { color } => write!(f, "SOLID(color: {})", color),
+            WBackgroundType::Gradient { top, bottom } => {
+                write!(f, "GRADIENT(top: {}, bottom: {})", top, bottom)
+            }
+            WBackgroundType::Hdri { source } => write!(f, "HDRI(source: {})", source),
+        };
+    }
+}
+
+impl fmt::Display for WImageSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            WImageSource::Path { path } => write!(f, "PATH({})", path),
+            WImageSource::Bytes { bytes } => write!(f, "BYTES({} bytes)", bytes.len()),
+        };
+    }
+}
+
 impl fmt::Display for WTileSize {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         return match self {
             WTileSize::Full => write!(f, "FULL()"),
             WTileSize::Tile { size } => write!(f, "TILE(size: {})", size),
+            WTileSize::Auto => write!(f, "AUTO()"),
         };
     }
 }
@@ -0,0 +1,841 @@
+use core::fmt;
+use std::collections::HashSet;
+
+use crate::utils::color::hex_to_rgb;
+
+use super::{
+    texture::MAX_TEXTURE_DIMENSION,
+    types::{
+        WBackgroundType, WCamera, WColor, WLightType, WMaterialType, WMeshSource, WObjectType,
+        WProjection, WRenderSettings, WRotation, WScene, WTexture, WTileSize, WTransform,
+    },
+};
+
+/// Octaves past this add detail far below a pixel at any sensible scale
+const MAX_NOISE_OCTAVES: u32 = 16;
+/// Taps of the last iteration are 2^(iterations - 1) pixels apart
+const MAX_DENOISE_ITERATIONS: u32 = 10;
+/// Milliseconds, a minute is far past the point where drivers reset the device
+const MAX_DISPATCH_BUDGET: f32 = 60_000.0;
+/// A week, renders that should run longer than that can go without a limit
+const MAX_TIME_LIMIT: f32 = 7.0 * 24.0 * 60.0 * 60.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Renders, but probably not what was intended
+    Warning,
+    /// Can't be rendered
+    Error,
+}
+
+/// Problem with a single scene field
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// JSON path of the offending field, e.g. `objects[3].material_id`
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+
+        return write!(f, "{} at {}: {}", severity, self.path, self.message);
+    }
+}
+
+impl WScene {
+    /// Checks the scene for values the kernel can't render or renders as garbage,
+    /// an empty list means the scene is good to go
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Diagnostics::default();
+
+        validate_render_settings(&mut diagnostics, &self.render_settings);
+        validate_camera(&mut diagnostics, &self.camera);
+
+        let mut material_ids = HashSet::new();
+
+        for (i, material) in self.materials.iter().enumerate() {
+            let path = format!("materials[{}]", i);
+
+            if !material_ids.insert(material.id) {
+                diagnostics.error(
+                    format!("{}.id", path),
+                    format!("Duplicate material id {}", material.id),
+                );
+            }
+
+            let path = format!("{}.type", path);
+
+            match &material.mat_type {
+                WMaterialType::Diffuse { color } => {
+                    validate_color(&mut diagnostics, format!("{}.color", path), color);
+                }
+                WMaterialType::Metal { color, roughness } => {
+                    validate_color(&mut diagnostics, format!("{}.color", path), color);
+
+                    if !(0.0..=1.0).contains(roughness) {
+                        diagnostics.warning(
+                            format!("{}.roughness", path),
+                            format!("Roughness {} is outside of [0, 1]", roughness),
+                        );
+                    }
+                }
+                WMaterialType::Dielectric { ior } => {
+                    if !(*ior > 0.0 && ior.is_finite()) {
+                        diagnostics.error(
+                            format!("{}.ior", path),
+                            format!("Index of refraction must be positive, got {}", ior),
+                        );
+                    }
+                }
+                WMaterialType::Emissive { color, strength } => {
+                    diagnostics.color(format!("{}.color", path), color);
+
+                    if !(*strength >= 0.0 && strength.is_finite()) {
+                        diagnostics.error(
+                            format!("{}.strength", path),
+                            format!("Strength must not be negative, got {}", strength),
+                        );
+                    }
+                }
+            }
+        }
+
+        let mut shape_ids = HashSet::new();
+
+        for (i, shape) in self.shapes.iter().enumerate() {
+            let path = format!("shapes[{}]", i);
+
+            if !shape_ids.insert(shape.id) {
+                diagnostics.error(
+                    format!("{}.id", path),
+                    format!("Duplicate shape id {}", shape.id),
+                );
+            }
+
+            let path = format!("{}.type", path);
+
+            match &shape.shape_type {
+                WObjectType::Instance { .. } => {
+                    diagnostics.error(path, "Shapes can't be instances".into());
+                }
+                shape_type => validate_shape_type(&mut diagnostics, path, shape_type),
+            }
+        }
+
+        for (i, object) in self.objects.iter().enumerate() {
+            let path = format!("objects[{}]", i);
+
+            let references_material =
+                u32::try_from(object.material_id).is_ok_and(|id| return material_ids.contains(&id));
+
+            if !references_material {
+                diagnostics.error(
+                    format!("{}.material_id", path),
+                    format!("Unknown material {}", object.material_id),
+                );
+            }
+
+            if let Some(transform) = &object.transform {
+                validate_transform(&mut diagnostics, format!("{}.transform", path), transform);
+            }
+
+            let path = format!("{}.type", path);
+
+            match &object.obj_type {
+                WObjectType::Instance { shape_id } => {
+                    if !shape_ids.contains(shape_id) {
+                        diagnostics.error(
+                            format!("{}.shape_id", path),
+                            format!("Unknown shape {}", shape_id),
+                        );
+                    }
+                }
+                shape_type => validate_shape_type(&mut diagnostics, path, shape_type),
+            }
+        }
+
+        let mut light_ids = HashSet::new();
+
+        for (i, light) in self.lights.iter().enumerate() {
+            let path = format!("lights[{}]", i);
+
+            if !light_ids.insert(light.id) {
+                diagnostics.error(
+                    format!("{}.id", path),
+                    format!("Duplicate light id {}", light.id),
+                );
+            }
+
+            validate_light_type(
+                &mut diagnostics,
+                format!("{}.type", path),
+                &light.light_type,
+            );
+        }
+
+        let path = "background";
+
+        if !(self.background.intensity >= 0.0 && self.background.intensity.is_finite()) {
+            diagnostics.error(
+                format!("{}.intensity", path),
+                format!(
+                    "Intensity must not be negative, got {}",
+                    self.background.intensity
+                ),
+            );
+        }
+
+        match &self.background.bg_type {
+            WBackgroundType::Solid { color } => {
+                diagnostics.color(format!("{}.type.color", path), color);
+            }
+            WBackgroundType::Gradient { top, bottom } => {
+                diagnostics.color(format!("{}.type.top", path), top);
+                diagnostics.color(format!("{}.type.bottom", path), bottom);
+            }
+            WBackgroundType::Hdri { .. } => {}
+        }
+
+        return diagnostics.0;
+    }
+}
+
+fn validate_render_settings(diagnostics: &mut Diagnostics, settings: &WRenderSettings) {
+    let path = "render_settings";
+
+    // the image is rendered into a single texture
+    for (field, size) in [("width", settings.width), ("height", settings.height)] {
+        if size == 0 || size > MAX_TEXTURE_DIMENSION {
+            diagnostics.error(
+                format!("{}.{}", path, field),
+                format!(
+                    "Image {} must be in [1, {}], got {}",
+                    field, MAX_TEXTURE_DIMENSION, size
+                ),
+            );
+        }
+    }
+
+    if settings.samples == 0 {
+        diagnostics.error(
+            format!("{}.samples", path),
+            "Samples must be non zero".into(),
+        );
+    }
+
+    if settings.bounces == 0 {
+        diagnostics.warning(
+            format!("{}.bounces", path),
+            "No bounces, every ray returns black".into(),
+        );
+    }
+
+    if let WTileSize::Tile { size: 0 } = settings.tile_size {
+        diagnostics.error(
+            format!("{}.tile_size.size", path),
+            "Tile size must be non zero".into(),
+        );
+    }
+
+    diagnostics.positive_up_to(
+        format!("{}.dispatch_budget", path),
+        settings.dispatch_budget,
+        MAX_DISPATCH_BUDGET,
+    );
+
+    if settings.samples_per_pass == Some(0) {
+        diagnostics.error(
+            format!("{}.samples_per_pass", path),
+            "Samples per pass must be non zero".into(),
+        );
+    }
+
+    if !settings.post.exposure.is_finite() {
+        diagnostics.error(
+            format!("{}.post.exposure", path),
+            format!("Exposure must be finite, got {}", settings.post.exposure),
+        );
+    }
+
+    if let Some(time_limit) = settings.time_limit {
+        diagnostics.positive_up_to(format!("{}.time_limit", path), time_limit, MAX_TIME_LIMIT);
+    }
+
+    if let Some(denoise) = &settings.denoise {
+        if denoise.iterations == 0 || denoise.iterations > MAX_DENOISE_ITERATIONS {
+            diagnostics.error(
+                format!("{}.denoise.iterations", path),
+                format!(
+                    "Iterations must be between 1 and {}, got {}",
+                    MAX_DENOISE_ITERATIONS, denoise.iterations
+                ),
+            );
+        }
+
+        diagnostics.positive(format!("{}.denoise.color_sigma", path), denoise.color_sigma);
+        diagnostics.positive(
+            format!("{}.denoise.normal_sigma", path),
+            denoise.normal_sigma,
+        );
+        diagnostics.positive(
+            format!("{}.denoise.albedo_sigma", path),
+            denoise.albedo_sigma,
+        );
+    }
+}
+
+fn validate_camera(diagnostics: &mut Diagnostics, camera: &WCamera) {
+    let path = "camera";
+
+    diagnostics.finite(format!("{}.look_from", path), camera.look_from);
+    diagnostics.finite(format!("{}.look_at", path), camera.look_at);
+
+    let forward = camera.look_at - camera.look_from;
+
+    if forward.length_squared() == 0.0 {
+        diagnostics.error(
+            format!("{}.look_at", path),
+            "Camera looks at its own position".into(),
+        );
+    } else if camera.v_up.cross(forward).length_squared() == 0.0 {
+        diagnostics.error(
+            format!("{}.v_up", path),
+            "Up vector is zero or parallel to the view direction".into(),
+        );
+    }
+
+    match camera.projection {
+        WProjection::Perspective => {
+            if !(camera.v_fov > 0.0 && camera.v_fov < 180.0) {
+                diagnostics.error(
+                    format!("{}.v_fov", path),
+                    format!("Field of view must be in (0, 180), got {}", camera.v_fov),
+                );
+            }
+        }
+        WProjection::Orthographic { view_height } => {
+            diagnostics.positive(format!("{}.projection.view_height", path), view_height);
+        }
+        // past 360 the image would wrap around behind the camera more than once
+        WProjection::Fisheye => {
+            if !(camera.v_fov > 0.0 && camera.v_fov <= 360.0) {
+                diagnostics.error(
+                    format!("{}.v_fov", path),
+                    format!(
+                        "Fisheye field of view must be in (0, 360], got {}",
+                        camera.v_fov
+                    ),
+                );
+            }
+        }
+        WProjection::Equirectangular => {}
+    }
+
+    if !(camera.dof_angle >= 0.0 && camera.dof_angle < 180.0) {
+        diagnostics.error(
+            format!("{}.dof_angle", path),
+            format!(
+                "Defocus angle must be in [0, 180), got {}",
+                camera.dof_angle
+            ),
+        );
+    }
+
+    // focus distance also sizes the viewport, not only the depth of field
+    if !(camera.dof_distance > 0.0 && camera.dof_distance.is_finite()) {
+        diagnostics.error(
+            format!("{}.dof_distance", path),
+            format!(
+                "Focus distance must be positive, got {}",
+                camera.dof_distance
+            ),
+        );
+    }
+}
+
+/// Hex color or texture of a material
+fn validate_color(diagnostics: &mut Diagnostics, path: String, color: &WColor) {
+    let texture = match color {
+        WColor::Hex(hex) => return diagnostics.color(path, hex),
+        WColor::Texture(texture) => texture,
+    };
+
+    match texture {
+        WTexture::Image { scale, .. } => {
+            diagnostics.positive(format!("{}.scale", path), *scale);
+        }
+        WTexture::Checker { even, odd, scale } => {
+            diagnostics.color(format!("{}.even", path), even);
+            diagnostics.color(format!("{}.odd", path), odd);
+            diagnostics.positive(format!("{}.scale", path), *scale);
+        }
+        WTexture::Noise {
+            low,
+            high,
+            scale,
+            octaves,
+        } => {
+            diagnostics.color(format!("{}.low", path), low);
+            diagnostics.color(format!("{}.high", path), high);
+            diagnostics.positive(format!("{}.scale", path), *scale);
+
+            if !(1..=MAX_NOISE_OCTAVES).contains(octaves) {
+                diagnostics.error(
+                    format!("{}.octaves", path),
+                    format!(
+                        "Octaves must be in [1, {}], got {}",
+                        MAX_NOISE_OCTAVES, octaves
+                    ),
+                );
+            }
+        }
+    }
+}
+
+/// Primitive or mesh of an object or shape, path points at its type
+fn validate_shape_type(diagnostics: &mut Diagnostics, path: String, shape_type: &WObjectType) {
+    match shape_type {
+        WObjectType::Sphere { position, radius } => {
+            diagnostics.finite(format!("{}.position", path), *position);
+
+            if *radius == 0.0 || !radius.is_finite() {
+                diagnostics.error(
+                    format!("{}.radius", path),
+                    format!("Radius must be non zero, got {}", radius),
+                );
+            }
+        }
+        WObjectType::Plane { point, normal } => {
+            diagnostics.finite(format!("{}.point", path), *point);
+            diagnostics.direction(format!("{}.normal", path), *normal);
+        }
+        WObjectType::Quad { corner, u, v } => {
+            diagnostics.finite(format!("{}.corner", path), *corner);
+            diagnostics.finite(format!("{}.u", path), *u);
+            diagnostics.finite(format!("{}.v", path), *v);
+
+            if u.cross(*v).length_squared() == 0.0 {
+                diagnostics.error(
+                    format!("{}.v", path),
+                    "Edges are zero or parallel, the quad has no area".into(),
+                );
+            }
+        }
+        WObjectType::Box { min, max } => {
+            diagnostics.finite(format!("{}.min", path), *min);
+            diagnostics.finite(format!("{}.max", path), *max);
+
+            if min.cmpgt(*max).any() {
+                diagnostics.error(
+                    format!("{}.max", path),
+                    format!("Max {} is below min {}", max, min),
+                );
+            }
+        }
+        WObjectType::OrientedBox {
+            center,
+            size,
+            rotation,
+        } => {
+            diagnostics.finite(format!("{}.center", path), *center);
+
+            if !size.is_finite() || size.cmplt(glam::Vec3::ZERO).any() {
+                diagnostics.error(
+                    format!("{}.size", path),
+                    format!("Size components must be finite and positive, got {}", size),
+                );
+            }
+
+            validate_rotation(diagnostics, format!("{}.rotation", path), rotation);
+        }
+        WObjectType::Disk {
+            center,
+            normal,
+            radius,
+        } => {
+            diagnostics.finite(format!("{}.center", path), *center);
+            diagnostics.direction(format!("{}.normal", path), *normal);
+            diagnostics.positive(format!("{}.radius", path), *radius);
+        }
+        WObjectType::Cylinder { base, top, radius } => {
+            diagnostics.finite(format!("{}.base", path), *base);
+            diagnostics.finite(format!("{}.top", path), *top);
+
+            if base == top {
+                diagnostics.error(
+                    format!("{}.top", path),
+                    "Top and base are the same point".into(),
+                );
+            }
+
+            diagnostics.positive(format!("{}.radius", path), *radius);
+        }
+        WObjectType::Mesh { source } => {
+            validate_mesh_source(diagnostics, format!("{}.source", path), source);
+        }
+        WObjectType::Instance { .. } => {}
+    }
+}
+
+fn validate_light_type(diagnostics: &mut Diagnostics, path: String, light_type: &WLightType) {
+    match light_type {
+        WLightType::Point {
+            position,
+            color,
+            intensity,
+        } => {
+            diagnostics.finite(format!("{}.position", path), *position);
+            diagnostics.color(format!("{}.color", path), color);
+            diagnostics.non_negative(format!("{}.intensity", path), *intensity);
+        }
+        WLightType::Spot {
+            position,
+            direction,
+            color,
+            intensity,
+            angle,
+            blend,
+        } => {
+            diagnostics.finite(format!("{}.position", path), *position);
+            diagnostics.direction(format!("{}.direction", path), *direction);
+            diagnostics.color(format!("{}.color", path), color);
+            diagnostics.non_negative(format!("{}.intensity", path), *intensity);
+
+            if !(*angle > 0.0 && *angle <= 90.0) {
+                diagnostics.error(
+                    format!("{}.angle", path),
+                    format!("Angle must be in (0, 90] degrees, got {}", angle),
+                );
+            }
+
+            if !(0.0..=1.0).contains(blend) {
+                diagnostics.error(
+                    format!("{}.blend", path),
+                    format!("Blend must be in [0, 1], got {}", blend),
+                );
+            }
+        }
+        WLightType::Directional {
+            direction,
+            color,
+            intensity,
+            angular_radius,
+        } => {
+            diagnostics.direction(format!("{}.direction", path), *direction);
+            diagnostics.color(format!("{}.color", path), color);
+            diagnostics.non_negative(format!("{}.intensity", path), *intensity);
+
+            if !(*angular_radius >= 0.0 && *angular_radius < 90.0) {
+                diagnostics.error(
+                    format!("{}.angular_radius", path),
+                    format!(
+                        "Angular radius must be in [0, 90) degrees, got {}",
+                        angular_radius
+                    ),
+                );
+            }
+        }
+    }
+}
+
+fn validate_transform(diagnostics: &mut Diagnostics, path: String, transform: &WTransform) {
+    diagnostics.finite(format!("{}.translation", path), transform.translation);
+
+    // a zero scale can't be inverted to move rays into object space
+    if !transform.scale.is_finite() || transform.scale.cmpeq(glam::Vec3::ZERO).any() {
+        diagnostics.error(
+            format!("{}.scale", path),
+            format!(
+                "Scale components must be finite and non zero, got {}",
+                transform.scale
+            ),
+        );
+    }
+
+    validate_rotation(
+        diagnostics,
+        format!("{}.rotation", path),
+        &transform.rotation,
+    );
+}
+
+fn validate_rotation(diagnostics: &mut Diagnostics, path: String, rotation: &WRotation) {
+    match rotation {
+        WRotation::Euler { degrees } => {
+            diagnostics.finite(format!("{}.degrees", path), *degrees);
+        }
+        WRotation::Quat { quat } => {
+            if !quat.is_finite() || quat.length_squared() == 0.0 {
+                diagnostics.error(
+                    format!("{}.quat", path),
+                    format!("Quaternion must be finite and non zero, got {}", quat),
+                );
+            }
+        }
+    }
+}
+
+fn validate_mesh_source(diagnostics: &mut Diagnostics, path: String, source: &WMeshSource) {
+    // obj files are only checked when loaded
+    let WMeshSource::Inline {
+        vertices,
+        indices,
+        normals,
+        uvs,
+    } = source
+    else {
+        return;
+    };
+
+    if indices.is_empty() {
+        diagnostics.warning(format!("{}.indices", path), "Mesh has no triangles".into());
+    }
+
+    if indices.len() % 3 != 0 {
+        diagnostics.error(
+            format!("{}.indices", path),
+            format!("Index count {} is not a multiple of 3", indices.len()),
+        );
+    }
+
+    if let Some(index) = indices
+        .iter()
+        .find(|i| return **i as usize >= vertices.len())
+    {
+        diagnostics.error(
+            format!("{}.indices", path),
+            format!(
+                "Index {} is out of bounds for {} vertices",
+                index,
+                vertices.len()
+            ),
+        );
+    }
+
+    if let Some(normals) = normals {
+        if normals.len() != vertices.len() {
+            diagnostics.error(
+                format!("{}.normals", path),
+                format!("{} normals for {} vertices", normals.len(), vertices.len()),
+            );
+        }
+    }
+
+    if let Some(uvs) = uvs {
+        if uvs.len() != vertices.len() {
+            diagnostics.error(
+                format!("{}.uvs", path),
+                format!("{} uvs for {} vertices", uvs.len(), vertices.len()),
+            );
+        }
+    }
+}
+
+#[derive(Default)]
+struct Diagnostics(Vec<Diagnostic>);
+
+impl Diagnostics {
+    fn error(&mut self, path: String, message: String) {
+        self.0.push(Diagnostic {
+            severity: Severity::Error,
+            path,
+            message,
+        });
+    }
+
+    fn warning(&mut self, path: String, message: String) {
+        self.0.push(Diagnostic {
+            severity: Severity::Warning,
+            path,
+            message,
+        });
+    }
+
+    fn color(&mut self, path: String, hex: &str) {
+        if hex_to_rgb(hex).is_none() {
+            self.error(path, format!("Invalid color {}", hex));
+        }
+    }
+
+    fn finite(&mut self, path: String, value: glam::Vec3) {
+        if !value.is_finite() {
+            self.error(path, format!("Expected finite components, got {}", value));
+        }
+    }
+
+    fn direction(&mut self, path: String, value: glam::Vec3) {
+        if !value.is_finite() || value.length_squared() == 0.0 {
+            self.error(
+                path,
+                format!("Expected a finite non zero direction, got {}", value),
+            );
+        }
+    }
+
+    fn non_negative(&mut self, path: String, value: f32) {
+        if !(value >= 0.0 && value.is_finite()) {
+            self.error(path, format!("Must not be negative, got {}", value));
+        }
+    }
+
+    fn positive(&mut self, path: String, value: f32) {
+        if !(value > 0.0 && value.is_finite()) {
+            self.error(path, format!("Must be positive, got {}", value));
+        }
+    }
+
+    fn positive_up_to(&mut self, path: String, value: f32, max: f32) {
+        if !(value > 0.0 && value <= max) {
+            self.error(
+                path,
+                format!("Must be positive and at most {}, got {}", max, value),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scene::types::WLight;
+
+    use super::*;
+
+    fn scene() -> WScene {
+        let json = include_str!("../../../src/data/demo_01.scene.json");
+
+        return WScene::from_json(json).unwrap();
+    }
+
+    fn paths(diagnostics: &[Diagnostic]) -> Vec<&str> {
+        return diagnostics.iter().map(|d| return d.path.as_str()).collect();
+    }
+
+    #[test]
+    fn demo_scene_is_valid() {
+        assert_eq!(scene().validate(), vec![]);
+    }
+
+    #[test]
+    fn diagnostics_point_at_the_offending_field() {
+        let mut scene = scene();
+
+        scene.render_settings.width = 0;
+        scene.camera.look_at = scene.camera.look_from;
+        scene.objects[1].material_id = 999;
+        scene.materials.push(scene.materials[0].clone());
+
+        let diagnostics = scene.validate();
+
+        assert!(diagnostics
+            .iter()
+            .all(|d| return d.severity == Severity::Error));
+        assert_eq!(
+            paths(&diagnostics),
+            vec![
+                "render_settings.width",
+                "camera.look_at",
+                "materials[4].id",
+                "objects[1].material_id",
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_lights_are_reported() {
+        let mut scene = scene();
+
+        let light = |light_type| {
+            return WLight {
+                id: 0,
+                name: String::from("light"),
+                light_type,
+            };
+        };
+
+        scene.lights = vec![
+            light(WLightType::Spot {
+                position: glam::Vec3::Y,
+                direction: glam::Vec3::ZERO,
+                color: String::from("#FFFFFF"),
+                intensity: 1.0,
+                angle: 120.0,
+                blend: 0.15,
+            }),
+            light(WLightType::Directional {
+                direction: glam::Vec3::NEG_Y,
+                color: String::from("#FFFFFF"),
+                intensity: -1.0,
+                angular_radius: 0.5,
+            }),
+        ];
+
+        assert_eq!(
+            paths(&scene.validate()),
+            vec![
+                "lights[0].type.direction",
+                "lights[0].type.angle",
+                "lights[1].id",
+                "lights[1].type.intensity",
+            ]
+        );
+    }
+
+    #[test]
+    fn images_larger_than_a_texture_are_reported() {
+        let mut scene = scene();
+
+        scene.render_settings.width = 16384;
+        scene.render_settings.height = MAX_TEXTURE_DIMENSION;
+
+        assert_eq!(paths(&scene.validate()), vec!["render_settings.width"]);
+    }
+
+    #[test]
+    fn durations_out_of_range_are_reported() {
+        let mut scene = scene();
+
+        scene.render_settings.dispatch_budget = 1e30;
+        scene.render_settings.time_limit = Some(f32::INFINITY);
+
+        assert_eq!(
+            paths(&scene.validate()),
+            vec![
+                "render_settings.dispatch_budget",
+                "render_settings.time_limit"
+            ]
+        );
+    }
+
+    #[test]
+    fn parallel_up_vector_is_reported() {
+        let mut scene = scene();
+
+        scene.camera.v_up = scene.camera.look_at - scene.camera.look_from;
+
+        assert_eq!(paths(&scene.validate()), vec!["camera.v_up"]);
+    }
+
+    #[test]
+    fn field_of_view_range_depends_on_the_projection() {
+        let mut scene = scene();
+
+        scene.camera.v_fov = 270.0;
+        assert_eq!(paths(&scene.validate()), vec!["camera.v_fov"]);
+
+        scene.camera.projection = WProjection::Fisheye;
+        assert_eq!(scene.validate(), vec![]);
+
+        scene.camera.projection = WProjection::Orthographic { view_height: 0.0 };
+        assert_eq!(
+            paths(&scene.validate()),
+            vec!["camera.projection.view_height"]
+        );
+    }
+}
@@ -0,0 +1,70 @@
+// Edge avoiding a-trous wavelet filter, one iteration per dispatch
+// mirrors renderer/denoise.rs
+
+const KERNEL = array<f32, 5>(1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0);
+
+// layers of the aovs texture, see Aov::layer
+const AOV_LAYER_ALBEDO = 0u;
+const AOV_LAYER_NORMAL = 1u;
+
+struct Iteration {
+    step: u32, // pixels between the taps
+    color_sigma: f32,
+    normal_sigma: f32,
+    albedo_sigma: f32
+}
+
+@group(0) @binding(0) var input: texture_2d<f32>; // linear image filtered by the previous iteration
+@group(0) @binding(1) var aovs: texture_2d_array<f32>;
+@group(0) @binding(2) var output: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(3) var<uniform> iteration: Iteration;
+
+/// falls off with the squared distance between the features
+fn edge_weight(a: vec3f, b: vec3f, sigma: f32) -> f32 {
+    let d = a - b;
+    return exp(-dot(d, d) / (sigma * sigma));
+}
+
+@compute @workgroup_size(8, 8, 1)
+fn main(@builtin(global_invocation_id) id: vec3u) {
+    let size = vec2i(textureDimensions(input));
+    let pixel_position = vec2i(id.xy);
+
+    if pixel_position.x >= size.x || pixel_position.y >= size.y {
+        return;
+    }
+
+    let pixel = textureLoad(input, pixel_position, 0);
+    let mapped = pixel.rgb / (1.0 + pixel.rgb);
+    let normal = textureLoad(aovs, pixel_position, AOV_LAYER_NORMAL, 0).xyz;
+    let albedo = textureLoad(aovs, pixel_position, AOV_LAYER_ALBEDO, 0).rgb;
+
+    // constant arrays can only be indexed by constants
+    var kernel = KERNEL;
+
+    var sum = vec3f(0.0);
+    var weight_sum = 0.0;
+
+    for (var ky = 0; ky < 5; ky++) {
+        for (var kx = 0; kx < 5; kx++) {
+            let tap = pixel_position + vec2i(kx - 2, ky - 2) * i32(iteration.step);
+
+            if tap.x < 0 || tap.y < 0 || tap.x >= size.x || tap.y >= size.y {
+                continue;
+            }
+
+            let tap_color = textureLoad(input, tap, 0).rgb;
+
+            let weight = kernel[kx] * kernel[ky]
+                * edge_weight(mapped, tap_color / (1.0 + tap_color), iteration.color_sigma)
+                * edge_weight(normal, textureLoad(aovs, tap, AOV_LAYER_NORMAL, 0).xyz, iteration.normal_sigma)
+                * edge_weight(albedo, textureLoad(aovs, tap, AOV_LAYER_ALBEDO, 0).rgb, iteration.albedo_sigma);
+
+            sum += tap_color * weight;
+            weight_sum += weight;
+        }
+    }
+
+    // the center tap always has a weight so the sum is never zero
+    textureStore(output, pixel_position, vec4f(sum / weight_sum, pixel.a));
+}
@@ -1,5 +1,6 @@
 // CONSTANTS_START
 const INF_F32 = 0x1p+127f;
+const PI = 3.14159265358979323846264338327950288f;
 const EPSILON = 0x1p-149f;
 const ERR_COLOR = vec3f(1.0, 0.0, 1.0);
 // CONSTANTS_END
@@ -17,6 +18,25 @@ fn vec3f_reflect(v: vec3f, n: vec3f) -> vec3f {
     return v - 2.0 * dot(v, n) * n;
 }
 
+/// rotates v by the unit quaternion q
+/// tangent, bitangent and the unit normal as columns,
+/// branchless orthonormal basis from Duff et al. 2017
+fn orthonormal_basis(normal: vec3f) -> mat3x3f {
+    let side = select(-1.0, 1.0, normal.z >= 0.0);
+    let a = -1.0 / (side + normal.z);
+    let b = normal.x * normal.y * a;
+
+    let tangent = vec3f(1.0 + side * normal.x * normal.x * a, side * b, -side * normal.x);
+    let bitangent = vec3f(b, side + normal.y * normal.y * a, -normal.y);
+
+    return mat3x3f(tangent, bitangent, normal);
+}
+
+fn quat_rotate(q: vec4f, v: vec3f) -> vec3f {
+    let t = 2.0 * cross(q.xyz, v);
+    return v + q.w * t + cross(q.xyz, t);
+}
+
 fn vec3f_refract(uv: vec3f, n: vec3f, etai_over_etat: f32) -> vec3f {
     let cos_theta = min(dot(-uv, n), 1.0);
     let r_out_perp = etai_over_etat * (uv + cos_theta * n);
@@ -64,8 +84,20 @@ fn pcg32(_rng: ptr<private, Rng>) -> u32 {
     return w2;
 }
 
-fn random_init(seed: vec3u) {
-    rng = Rng((seed.x * seed.y) + 1337u);
+// pcg hash, a permutation of u32 so distinct inputs give distinct outputs
+fn hash(x: u32) -> u32 {
+    let state = x * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+
+    return (word >> 22u) ^ word;
+}
+
+// seeded from the absolute pixel, the sample index and the scene seed
+// so tiles and progressive passes never share a random stream
+fn random_init(pixel_position: vec2u, sample: u32) {
+    let pixel_index = pixel_position.y * config.image.width + pixel_position.x;
+
+    rng = Rng(hash(pixel_index ^ hash(sample ^ hash(config.image.seed))));
 }
 
 fn random_float() -> f32 {
@@ -102,8 +134,22 @@ fn random_in_unit_disk() -> vec3f {
     }
 }
 
+/// uniform on the unit sphere, exact so the pdf of scattered directions is known
 fn random_unit_vector() -> vec3f {
-    return normalize(random_in_unit_sphere());
+    let z = random_float_range(-1.0, 1.0);
+    let phi = 2.0 * PI * random_float();
+    let r = sqrt(max(0.0, 1.0 - z * z));
+
+    return vec3f(r * cos(phi), r * sin(phi), z);
+}
+
+/// uniform in the cone around the unit axis with the given cosine of its half angle
+fn random_in_cone(axis: vec3f, cos_max: f32) -> vec3f {
+    let cos_theta = 1.0 - random_float() * (1.0 - cos_max);
+    let phi = 2.0 * PI * random_float();
+    let sin_theta = sqrt(max(0.0, 1.0 - cos_theta * cos_theta));
+
+    return orthonormal_basis(axis) * vec3f(sin_theta * cos(phi), sin_theta * sin(phi), cos_theta);
 }
 
 fn random_on_hemisphere(normal: vec3f) -> vec3f {
@@ -122,16 +168,33 @@ struct Image {
     width: u32,
     height: u32,
     samples: u32,
-    bounces: u32
+    bounces: u32, // most rays a path traces
+    diffuse_bounces: u32,
+    glossy_bounces: u32,
+    transmission_bounces: u32,
+    roulette_depth: u32, // bounces before russian roulette can end a path
+    aovs: u32, // bit per aov, 0 skips them
+    seed: u32
 }
 // IMAGE_END
 
 // CAMERA_START
+const PROJECTION_PERSPECTIVE = 0u;
+const PROJECTION_ORTHOGRAPHIC = 1u;
+const PROJECTION_FISHEYE = 2u;
+const PROJECTION_EQUIRECTANGULAR = 3u;
+
 struct Camera {
     center: vec3f,
     dof_angle: f32,
+    dof_distance: f32,
     dof_disk_u: vec3f,
-    dof_disk_v: vec3f
+    dof_disk_v: vec3f,
+    projection: u32,
+    fov: f32, // fisheye angle across the image height in radians
+    u: vec3f,
+    v: vec3f,
+    w: vec3f // points backwards
 }
 // CAMERA_END
 
@@ -147,18 +210,36 @@ struct Viewport {
 }
 // VIEWPORT_END
 
+// BACKGROUND_START
+const BACKGROUND_SOLID = 0u;
+const BACKGROUND_GRADIENT = 1u;
+const BACKGROUND_ENVIRONMENT_MAP = 2u;
+
+struct Background {
+    mode: u32,
+    rotation: f32, // degrees around the up axis
+    intensity: f32,
+    color_a: vec3f,
+    color_b: vec3f
+}
+// BACKGROUND_END
+
 // CONFIG_START
 struct Config {
     image: Image,
     camera: Camera,
     viewport: Viewport,
-    pixel_zero_loc: vec3f
+    pixel_zero_loc: vec3f,
+    background: Background
 }
 // CONFIG_END
 
 // EXECUTION_CONTEXT_START
 struct ExecutionContext {
-    tile_position: vec2u
+    tile_position: vec2u,
+    tile_size: vec2u, // the last workgroups of a dispatch reach past the tile
+    sample_offset: u32, // samples accumulated by previous passes
+    sample_count: u32 // samples to take in this dispatch
 }
 // EXECUTION_CONTEXT_END
 
@@ -169,7 +250,10 @@ struct HitRecord {
     normal: vec3f,
     mat_type: u32,
     mat_index: u32,
-    front_face: bool
+    front_face: bool,
+    uv: vec2f,
+    light: u32, // light index plus one for emissive surfaces that are sampled, 0 otherwise
+    object: u32 // object id plus one
 }
 
 /// Uses dot product to figure out which side the ray is
@@ -206,18 +290,36 @@ fn scatter(ray: Ray, hit: HitRecord, attenuation: ptr<function, vec3f>, scattere
         case 3u: {
             return scatter_dielectric(ray, hit, attenuation, scattered);
         }
-        default: {
+        default: { // emissive surfaces absorb everything
             return false;
         }
     }
 }
 
-struct DiffuseMat {
-    albedo: vec3f
+fn emitted(hit: HitRecord) -> vec3f {
+    if hit.mat_type == 4u {
+        let material = materials[hit.mat_index];
+        let strength = material.param;
+        return material.color * strength;
+    }
+
+    return vec3f(0.0);
+}
+
+/// every material type shares this layout, the mat_type decides how it is read
+struct Material {
+    color: vec3f, // albedo for diffuse and metal, emitted color for emissive, even checker and low noise color
+    param: f32, // roughness for metal, ior for dielectric, strength for emissive
+    color_b: vec3f, // odd checker and high noise color
+    texture: u32, // TEXTURE_ kind, only read for diffuse and metal
+    image: vec4u, // x, y, width, height in the texture atlas
+    scale: f32, // repeats per uv unit for images and checkers, frequency per world unit for noise
+    octaves: u32,
+    id: u32 // scene material id, only read for the aovs
 }
 
 fn scatter_diffuse(ray: Ray, hit: HitRecord, attenuation: ptr<function, vec3f>, scattered: ptr<function, Ray>) -> bool {
-    let material = diffuse_mats[hit.mat_index];
+    let material = materials[hit.mat_index];
     var scatter_direction = hit.normal + random_unit_vector();
 
     if vec3f_near_zero(scatter_direction) {
@@ -225,36 +327,28 @@ fn scatter_diffuse(ray: Ray, hit: HitRecord, attenuation: ptr<function, vec3f>,
     }
 
     (*scattered) = Ray(hit.point, scatter_direction);
-    (*attenuation) = material.albedo;
+    (*attenuation) = material_color(material, hit);
 
     return true;
 }
 
-struct MetalMat {
-    albedo: vec3f,
-    roughness: f32
-}
-
 fn scatter_metal(ray: Ray, hit: HitRecord, attenuation: ptr<function, vec3f>, scattered: ptr<function, Ray>) -> bool {
-    let material = metal_mats[hit.mat_index];
+    let material = materials[hit.mat_index];
+    let roughness = material.param;
     let reflected = vec3f_reflect(normalize(ray.direction), hit.normal);
 
-    (*scattered) = Ray(hit.point, reflected + material.roughness * random_unit_vector());
-    (*attenuation) = material.albedo;
+    (*scattered) = Ray(hit.point, reflected + roughness * random_unit_vector());
+    (*attenuation) = material_color(material, hit);
 
     return true;
 }
 
-struct DielectricMat {
-    ior: f32
-}
-
 fn scatter_dielectric(ray: Ray, hit: HitRecord, attenuation: ptr<function, vec3f>, scattered: ptr<function, Ray>) -> bool {
-    let material = dielectric_mats[hit.mat_index];
+    let ior = materials[hit.mat_index].param;
 
     (*attenuation) = vec3f(1.0);
 
-    let refraction_ratio = select(material.ior, 1.0 / material.ior ,hit.front_face);
+    let refraction_ratio = select(ior, 1.0 / ior ,hit.front_face);
 
     let unit_direction = normalize(ray.direction);
 
@@ -281,25 +375,474 @@ fn reflectance(cosine: f32, refraction_ratio: f32) -> f32 {
     r0 = r0 * r0;
     return r0 + (1.0 - r0) * pow((1.0 - cosine), 5.0);
 }
+
+/// diffuse and metal rougher than NEE_MIN_ROUGHNESS are lit by sampling lights,
+/// everything else only finds light by scattering into it
+fn samples_lights(hit: HitRecord) -> bool {
+    switch hit.mat_type {
+        case 1u: {
+            return true;
+        }
+        case 2u: {
+            return materials[hit.mat_index].param >= NEE_MIN_ROUGHNESS;
+        }
+        default: {
+            return false;
+        }
+    }
+}
+
+/// solid angle pdf of scatter picking the unit direction, only defined where samples_lights is true.
+/// for both materials the bsdf times the cosine is the albedo times this pdf
+fn scatter_pdf(ray: Ray, hit: HitRecord, direction: vec3f) -> f32 {
+    switch hit.mat_type {
+        case 1u: {
+            return offset_sphere_pdf(direction, hit.normal, 1.0);
+        }
+        case 2u: {
+            let reflected = vec3f_reflect(normalize(ray.direction), hit.normal);
+            return offset_sphere_pdf(direction, reflected, materials[hit.mat_index].param);
+        }
+        default: {
+            return 0.0;
+        }
+    }
+}
+
+/// pdf of normalize(center + radius * random_unit_vector()) picking the unit direction, the
+/// uniform density on the offset sphere moved to solid angle at each point the direction crosses it
+fn offset_sphere_pdf(direction: vec3f, center: vec3f, radius: f32) -> f32 {
+    let half_b = dot(direction, center);
+    let discriminant = half_b * half_b - dot(center, center) + radius * radius;
+
+    if discriminant <= 0.0 || radius <= 0.0 {
+        return 0.0;
+    }
+
+    let sqrtd = sqrt(discriminant);
+    let t_near = half_b - sqrtd;
+    let t_far = half_b + sqrtd;
+
+    let distance_squared = select(0.0, t_near * t_near, t_near > 0.0) + select(0.0, t_far * t_far, t_far > 0.0);
+
+    return distance_squared / (4.0 * PI * radius * sqrtd);
+}
 // MATERIAL_END
 
-// hit interface
-// fn hit(shape: Shape, ray: Ray, ray_limits: Interval, hit: ptr<function, HitRecord>) -> bool {}
+// LIGHT_START
+const LIGHT_POINT = 0u;
+const LIGHT_SPOT = 1u;
+const LIGHT_DIRECTIONAL = 2u;
+const LIGHT_SPHERE = 3u;
+const LIGHT_QUAD = 4u;
+const LIGHT_DISK = 5u;
+
+/// metal smoother than this is treated as a mirror, light sampling would only add fireflies
+const NEE_MIN_ROUGHNESS = 0.1;
+/// shadow rays stop short of the sampled point so they don't hit the light itself
+const SHADOW_RAY_SCALE = 0.999;
+
+/// every light kind shares this layout, the kind decides how it is read
+/// point: a.xyz position
+/// spot: a.xyz position, b.xyz unit direction, c.x cosine of the outer and c.y of the inner angle
+/// directional: b.xyz unit direction the light travels, c.x cosine of the angular radius
+/// sphere: a.xyz center, a.w radius
+/// quad: a.xyz corner, b.xyz and c.xyz edges
+/// disk: a.xyz center, a.w radius, b.xyz unit normal
+struct Light {
+    a: vec4f,
+    b: vec4f,
+    c: vec4f,
+    color: vec3f, // intensity for point and spot, irradiance for directional, radiance for the rest
+    kind: u32
+}
 
-// SPHERE_START
-struct Sphere {
-    center: vec3f,
-    radius: f32,
-    mid: vec4u
+struct LightSample {
+    direction: vec3f, // unit direction towards the light
+    distance: f32, // how far a shadow ray has to go
+    value: vec3f, // arriving radiance divided by the pdf of the direction
+    pdf: f32 // solid angle pdf, 0 for lights scattered rays can't hit
+}
+
+/// next event estimation, picks a light uniformly and returns what it adds through the surface
+/// when nothing is in the way, area lights are weighted against scattering into them
+fn sample_lights(ray: Ray, hit: HitRecord) -> vec3f {
+    let count = arrayLength(&lights);
+
+    if count == 0u {
+        return vec3f(0.0);
+    }
+
+    let index = min(u32(random_float() * f32(count)), count - 1u);
+    let sample = sample_light(lights[index], hit.point);
+
+    if all(sample.value == vec3f(0.0)) || dot(sample.direction, hit.normal) <= 0.0 {
+        return vec3f(0.0);
+    }
+
+    let bsdf_pdf = scatter_pdf(ray, hit, sample.direction);
+
+    if bsdf_pdf <= 0.0 {
+        return vec3f(0.0);
+    }
+
+    var shadow_hit = HitRecord();
+
+    if hit_world(Ray(hit.point, sample.direction), Interval(0.001, sample.distance * SHADOW_RAY_SCALE), &shadow_hit) {
+        return vec3f(0.0);
+    }
+
+    var weight = 1.0;
+
+    if sample.pdf > 0.0 {
+        weight = power_heuristic(sample.pdf / f32(count), bsdf_pdf);
+    }
+
+    let albedo = material_color(materials[hit.mat_index], hit);
+
+    return albedo * bsdf_pdf * sample.value * f32(count) * weight;
+}
+
+fn sample_light(light: Light, point: vec3f) -> LightSample {
+    var sample = LightSample(vec3f(0.0), 0.0, vec3f(0.0), 0.0);
+
+    switch light.kind {
+        case LIGHT_POINT, LIGHT_SPOT: {
+            let to_light = light.a.xyz - point;
+            let distance = length(to_light);
+
+            sample.direction = to_light / distance;
+            sample.distance = distance;
+            sample.value = light.color / (distance * distance);
+
+            if light.kind == LIGHT_SPOT {
+                let cos_angle = dot(-sample.direction, light.b.xyz);
+                let edge = clamp((cos_angle - light.c.x) / max(light.c.y - light.c.x, 1e-6), 0.0, 1.0);
+
+                sample.value *= edge * edge * (3.0 - 2.0 * edge);
+            }
+        }
+        case LIGHT_DIRECTIONAL: {
+            sample.direction = -light.b.xyz;
+
+            if light.c.x < 1.0 {
+                sample.direction = random_in_cone(-light.b.xyz, light.c.x);
+            }
+
+            sample.distance = INF_F32;
+            sample.value = light.color;
+        }
+        case LIGHT_SPHERE: {
+            let radius = abs(light.a.w);
+            let to_center = light.a.xyz - point;
+            let distance_squared = dot(to_center, to_center);
+
+            // no cone to sample from inside
+            if distance_squared <= radius * radius {
+                return sample;
+            }
+
+            let sin_squared_max = radius * radius / distance_squared;
+            let cos_max = sqrt(max(0.0, 1.0 - sin_squared_max));
+
+            sample.direction = random_in_cone(to_center / sqrt(distance_squared), cos_max);
+
+            let half_b = dot(sample.direction, to_center);
+            sample.distance = half_b - sqrt(max(0.0, half_b * half_b - distance_squared + radius * radius));
+
+            sample.pdf = sphere_light_pdf(sin_squared_max, cos_max);
+            sample.value = light.color / sample.pdf;
+        }
+        case LIGHT_QUAD, LIGHT_DISK: {
+            var point_on_light: vec3f;
+            var area: f32;
+            var normal: vec3f;
+
+            if light.kind == LIGHT_QUAD {
+                let u = random_float();
+                let v = random_float();
+                let n = cross(light.b.xyz, light.c.xyz);
+
+                point_on_light = light.a.xyz + u * light.b.xyz + v * light.c.xyz;
+                area = length(n);
+                normal = n / area;
+            } else {
+                let r = light.a.w * sqrt(random_float());
+                let phi = 2.0 * PI * random_float();
+
+                point_on_light = light.a.xyz + orthonormal_basis(light.b.xyz) * vec3f(r * cos(phi), r * sin(phi), 0.0);
+                area = PI * light.a.w * light.a.w;
+                normal = light.b.xyz;
+            }
+
+            let to_light = point_on_light - point;
+            let distance = length(to_light);
+
+            sample.direction = to_light / distance;
+            sample.distance = distance;
+
+            let cos_light = abs(dot(normal, sample.direction));
+
+            if cos_light <= 0.0 {
+                return sample;
+            }
+
+            sample.pdf = distance * distance / (area * cos_light);
+            sample.value = light.color / sample.pdf;
+        }
+        default: {}
+    }
+
+    return sample;
+}
+
+/// solid angle pdf of sample_light picking the unit direction from the point, the hit is where the
+/// direction meets the light. only area lights can be hit, the rest are 0
+fn light_pdf(light: Light, point: vec3f, direction: vec3f, hit: HitRecord, distance: f32) -> f32 {
+    switch light.kind {
+        case LIGHT_SPHERE: {
+            let radius = abs(light.a.w);
+            let to_center = light.a.xyz - point;
+            let distance_squared = dot(to_center, to_center);
+
+            if distance_squared <= radius * radius {
+                return 0.0;
+            }
+
+            let sin_squared_max = radius * radius / distance_squared;
+
+            return sphere_light_pdf(sin_squared_max, sqrt(max(0.0, 1.0 - sin_squared_max)));
+        }
+        case LIGHT_QUAD: {
+            let area = length(cross(light.b.xyz, light.c.xyz));
+            return distance * distance / (area * abs(dot(hit.normal, direction)));
+        }
+        case LIGHT_DISK: {
+            let area = PI * light.a.w * light.a.w;
+            return distance * distance / (area * abs(dot(hit.normal, direction)));
+        }
+        default: {
+            return 0.0;
+        }
+    }
+}
+
+/// uniform over the cone the sphere covers, 1 - cos is rewritten to stay accurate for far away spheres
+fn sphere_light_pdf(sin_squared_max: f32, cos_max: f32) -> f32 {
+    return 1.0 / (2.0 * PI * sin_squared_max / (1.0 + cos_max));
+}
+
+/// how much of the emission a scattered ray finds is kept, the rest is
+/// found by sampling the light at the bounce the ray comes from
+fn emission_weight(ray: Ray, hit: HitRecord, bsdf_pdf: f32) -> f32 {
+    if bsdf_pdf <= 0.0 || hit.light == 0u {
+        return 1.0;
+    }
+
+    let ray_length = length(ray.direction);
+    let direction = ray.direction / ray_length;
+
+    let light_pdf = light_pdf(lights[hit.light - 1u], ray.origin, direction, hit, hit.t * ray_length) / f32(arrayLength(&lights));
+
+    return power_heuristic(bsdf_pdf, light_pdf);
+}
+
+fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let a = pdf * pdf;
+    let b = other_pdf * other_pdf;
+
+    return a / (a + b);
+}
+// LIGHT_END
+
+// TEXTURE_START
+const TEXTURE_NONE = 0u;
+const TEXTURE_IMAGE = 1u;
+const TEXTURE_CHECKER = 2u;
+const TEXTURE_NOISE = 3u;
+
+/// albedo of the material at the hit
+fn material_color(material: Material, hit: HitRecord) -> vec3f {
+    switch material.texture {
+        case TEXTURE_IMAGE: {
+            return sample_image(material.image, hit.uv * material.scale);
+        }
+        case TEXTURE_CHECKER: {
+            let cell = vec2i(floor(hit.uv * material.scale));
+            return select(material.color_b, material.color, ((cell.x + cell.y) & 1) == 0);
+        }
+        case TEXTURE_NOISE: {
+            let blend = clamp(0.5 + 0.5 * fbm(hit.point * material.scale, material.octaves), 0.0, 1.0);
+            return mix(material.color, material.color_b, blend);
+        }
+        default: {
+            return material.color;
+        }
+    }
+}
+
+/// bilinear lookup of the image's rect in the atlas, wraps in both directions, v is 0 at the bottom
+fn sample_image(rect: vec4u, uv: vec2f) -> vec3f {
+    let origin = vec2i(rect.xy);
+    let size = vec2i(rect.zw);
+
+    let texel = vec2f(fract(uv.x) * f32(size.x), (1.0 - fract(uv.y)) * f32(size.y)) - 0.5;
+    let base = floor(texel);
+    let blend = texel - base;
+
+    let x0 = (i32(base.x) + size.x) % size.x;
+    let x1 = (x0 + 1) % size.x;
+    let y0 = (i32(base.y) + size.y) % size.y;
+    let y1 = (y0 + 1) % size.y;
+
+    let top = mix(textureLoad(texture_atlas, origin + vec2i(x0, y0), 0), textureLoad(texture_atlas, origin + vec2i(x1, y0), 0), blend.x);
+    let bottom = mix(textureLoad(texture_atlas, origin + vec2i(x0, y1), 0), textureLoad(texture_atlas, origin + vec2i(x1, y1), 0), blend.x);
+
+    return mix(top, bottom, blend.y).rgb;
+}
+
+/// sum of octaves of gradient noise, each at twice the frequency and half the amplitude
+fn fbm(point: vec3f, octaves: u32) -> f32 {
+    var sum = 0.0;
+    var amplitude = 0.5;
+    var frequency = 1.0;
+
+    for (var i = 0u; i < octaves; i++) {
+        sum += amplitude * gradient_noise(point * frequency);
+
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+
+    return sum;
+}
+
+/// perlin style noise with hashed gradients instead of a permutation table
+fn gradient_noise(point: vec3f) -> f32 {
+    let cell = floor(point);
+    let local = point - cell;
+
+    // quintic fade keeps the second derivative continuous
+    let fade = local * local * local * (local * (local * 6.0 - 15.0) + 10.0);
+
+    var sum = 0.0;
+
+    for (var i = 0; i < 8; i++) {
+        let corner = vec3i(i & 1, (i >> 1u) & 1, (i >> 2u) & 1);
+        let weight = select(1.0 - fade, fade, corner == vec3i(1));
+
+        sum += weight.x * weight.y * weight.z * dot(noise_gradient(vec3i(cell) + corner), local - vec3f(corner));
+    }
+
+    return sum;
+}
+
+/// unit gradient of a lattice point
+fn noise_gradient(cell: vec3i) -> vec3f {
+    let h = hash(bitcast<u32>(cell.x) ^ hash(bitcast<u32>(cell.y) ^ hash(bitcast<u32>(cell.z))));
+    let gradient = vec3f(f32(h & 1023u), f32((h >> 10u) & 1023u), f32((h >> 20u) & 1023u)) / 511.5 - 1.0;
+
+    return normalize(gradient + vec3f(1e-6));
+}
+// TEXTURE_END
+
+// PRIMITIVE_START
+const PRIMITIVE_SPHERE = 0u;
+const PRIMITIVE_PLANE = 1u;
+const PRIMITIVE_QUAD = 2u;
+const PRIMITIVE_BOX = 3u;
+const PRIMITIVE_DISK = 4u;
+const PRIMITIVE_CYLINDER = 5u;
+
+/// every primitive kind shares this layout, the kind decides how it is read
+/// sphere: a.xyz center, a.w radius
+/// plane: a.xyz point, b.xyz unit normal
+/// quad: a.xyz corner, b.xyz and c.xyz edges
+/// box: a.xyz center, b.xyz half size, c rotation quaternion
+/// disk: a.xyz center, a.w radius, b.xyz unit normal
+/// cylinder: a.xyz base center, a.w radius, b.xyz top center
+struct Primitive {
+    a: vec4f,
+    b: vec4f,
+    c: vec4f,
+    mid: vec4u,
+    kind: u32
+}
+
+// hit interface, only finds t, the outward unit normal and the uv
+// fn hit(primitive: Primitive, ray: Ray, ray_limits: Interval, t: ptr<function, f32>, normal: ptr<function, vec3f>, uv: ptr<function, vec2f>) -> bool {}
+
+fn hit_primitive(primitive: Primitive, ray: Ray, ray_limits: Interval, hit: ptr<function, HitRecord>) -> bool {
+    var t = 0.0;
+    var out_normal = vec3f();
+    var uv = vec2f();
+    var is_hit = false;
+
+    switch primitive.kind {
+        case PRIMITIVE_SPHERE: {
+            is_hit = hit_sphere(primitive, ray, ray_limits, &t, &out_normal, &uv);
+        }
+        case PRIMITIVE_PLANE: {
+            is_hit = hit_plane(primitive, ray, ray_limits, &t, &out_normal, &uv);
+        }
+        case PRIMITIVE_QUAD: {
+            is_hit = hit_quad(primitive, ray, ray_limits, &t, &out_normal, &uv);
+        }
+        case PRIMITIVE_BOX: {
+            is_hit = hit_box(primitive, ray, ray_limits, &t, &out_normal, &uv);
+        }
+        case PRIMITIVE_DISK: {
+            is_hit = hit_disk(primitive, ray, ray_limits, &t, &out_normal, &uv);
+        }
+        case PRIMITIVE_CYLINDER: {
+            is_hit = hit_cylinder(primitive, ray, ray_limits, &t, &out_normal, &uv);
+        }
+        default: {}
+    }
+
+    if !is_hit {
+        return false;
+    }
+
+    (*hit).t = t;
+    (*hit).point = ray_at(ray, t);
+    (*hit).uv = uv;
+
+    (*hit).mat_type = primitive.mid.x;
+    (*hit).mat_index = primitive.mid.y;
+    (*hit).light = primitive.mid.z;
+    (*hit).object = primitive.mid.w;
+
+    hit_set_face_normal(hit, ray, out_normal);
+
+    return true;
+}
+
+/// longitude and latitude of a unit direction, v is 0 at the bottom
+fn sphere_uv(direction: vec3f) -> vec2f {
+    let u = (atan2(-direction.z, direction.x) + PI) / (2.0 * PI);
+    let v = acos(clamp(-direction.y, -1.0, 1.0)) / PI;
+
+    return vec2f(u, v);
+}
+
+/// coordinates of the offset in a tangent frame of the unit normal
+fn planar_uv(offset: vec3f, normal: vec3f) -> vec2f {
+    let basis = orthonormal_basis(normal);
+
+    return vec2f(dot(offset, basis[0]), dot(offset, basis[1]));
 }
 
 /// solves the sphere ray intersection equation, which is a quadratic equation
-fn hit_sphere(sphere: Sphere, ray: Ray, ray_limits: Interval, hit: ptr<function, HitRecord>) -> bool {
-    let origin_to_center = ray.origin - sphere.center; // A - C
+fn hit_sphere(sphere: Primitive, ray: Ray, ray_limits: Interval, t: ptr<function, f32>, normal: ptr<function, vec3f>, uv: ptr<function, vec2f>) -> bool {
+    let center = sphere.a.xyz;
+    let radius = sphere.a.w;
+
+    let origin_to_center = ray.origin - center; // A - C
 
     let a = vec3f_len_squared(ray.direction);
     let half_b = dot(origin_to_center, ray.direction);
-    let c = vec3f_len_squared(origin_to_center) - sphere.radius * sphere.radius;
+    let c = vec3f_len_squared(origin_to_center) - radius * radius;
 
     let discriminant = (half_b * half_b) - (a * c);
 
@@ -318,29 +861,326 @@ fn hit_sphere(sphere: Sphere, ray: Ray, ray_limits: Interval, hit: ptr<function,
     }
 
     let point = ray_at(ray, root);
-    let out_normal = (point - sphere.center) / sphere.radius; // this will be unit length
 
-    (*hit).t = root;
-    (*hit).point = point;
+    (*t) = root;
+    (*normal) = (point - center) / radius; // this will be unit length
+    (*uv) = sphere_uv((point - center) / abs(radius));
 
-    (*hit).mat_type = sphere.mid.x;
-    (*hit).mat_index = sphere.mid.y;
+    return true;
+}
 
-    hit_set_face_normal(hit, ray, out_normal);
+fn hit_plane(plane: Primitive, ray: Ray, ray_limits: Interval, t: ptr<function, f32>, normal: ptr<function, vec3f>, uv: ptr<function, vec2f>) -> bool {
+    let point = plane.a.xyz;
+    let plane_normal = plane.b.xyz;
+
+    let denom = dot(plane_normal, ray.direction);
+
+    // ray is parallel to the plane
+    if abs(denom) < 1e-8 {
+        return false;
+    }
+
+    let root = dot(point - ray.origin, plane_normal) / denom;
+    if !interval_surrounds(ray_limits, root) {
+        return false;
+    }
+
+    (*t) = root;
+    (*normal) = plane_normal;
+    (*uv) = planar_uv(ray_at(ray, root) - point, plane_normal);
+
+    return true;
+}
+
+/// plane hit followed by the planar coordinates of the point along both edges
+fn hit_quad(quad: Primitive, ray: Ray, ray_limits: Interval, t: ptr<function, f32>, normal: ptr<function, vec3f>, uv: ptr<function, vec2f>) -> bool {
+    let corner = quad.a.xyz;
+    let u = quad.b.xyz;
+    let v = quad.c.xyz;
+
+    let n = cross(u, v);
+    let unit_normal = normalize(n);
+
+    let denom = dot(unit_normal, ray.direction);
+
+    if abs(denom) < 1e-8 {
+        return false;
+    }
+
+    let root = dot(corner - ray.origin, unit_normal) / denom;
+    if !interval_surrounds(ray_limits, root) {
+        return false;
+    }
+
+    let planar = ray_at(ray, root) - corner;
+    let w = n / dot(n, n);
+
+    let alpha = dot(w, cross(planar, v));
+    let beta = dot(w, cross(u, planar));
+
+    if alpha < 0.0 || alpha > 1.0 || beta < 0.0 || beta > 1.0 {
+        return false;
+    }
+
+    (*t) = root;
+    (*normal) = unit_normal;
+    (*uv) = vec2f(alpha, beta);
+
+    return true;
+}
+
+/// slab test in the box's frame, the exit is used when the ray starts inside
+fn hit_box(box: Primitive, ray: Ray, ray_limits: Interval, t: ptr<function, f32>, normal: ptr<function, vec3f>, uv: ptr<function, vec2f>) -> bool {
+    let center = box.a.xyz;
+    let half_size = box.b.xyz;
+    let rotation = box.c;
+    let inverse_rotation = vec4f(-rotation.xyz, rotation.w);
+
+    let origin = quat_rotate(inverse_rotation, ray.origin - center);
+    let direction = quat_rotate(inverse_rotation, ray.direction);
+    let inv_direction = 1.0 / direction;
+
+    let t0 = (-half_size - origin) * inv_direction;
+    let t1 = (half_size - origin) * inv_direction;
+
+    let t_near = min(t0, t1);
+    let t_far = max(t0, t1);
+
+    let t_enter = max(max(t_near.x, t_near.y), t_near.z);
+    let t_exit = min(min(t_far.x, t_far.y), t_far.z);
+
+    if t_enter > t_exit {
+        return false;
+    }
+
+    var root = t_enter;
+    if !interval_surrounds(ray_limits, root) {
+        root = t_exit;
+        if !interval_surrounds(ray_limits, root) {
+            return false;
+        }
+    }
+
+    // the face hit is the one the point is closest to
+    let point = origin + root * direction;
+    let distance = half_size - abs(point);
+
+    // face coordinates, guarded against flat boxes
+    let face = point / max(half_size, vec3f(1e-6)) * 0.5 + 0.5;
+
+    var local_normal = vec3f(0.0, 0.0, sign(point.z));
+    var face_uv = face.xy;
+
+    if distance.x <= distance.y && distance.x <= distance.z {
+        local_normal = vec3f(sign(point.x), 0.0, 0.0);
+        face_uv = face.zy;
+    } else if distance.y <= distance.z {
+        local_normal = vec3f(0.0, sign(point.y), 0.0);
+        face_uv = face.xz;
+    }
+
+    (*t) = root;
+    (*normal) = quat_rotate(rotation, local_normal);
+    (*uv) = face_uv;
+
+    return true;
+}
+
+fn hit_disk(disk: Primitive, ray: Ray, ray_limits: Interval, t: ptr<function, f32>, normal: ptr<function, vec3f>, uv: ptr<function, vec2f>) -> bool {
+    let center = disk.a.xyz;
+    let radius = disk.a.w;
+    let disk_normal = disk.b.xyz;
+
+    let denom = dot(disk_normal, ray.direction);
+
+    if abs(denom) < 1e-8 {
+        return false;
+    }
+
+    let root = dot(center - ray.origin, disk_normal) / denom;
+    if !interval_surrounds(ray_limits, root) {
+        return false;
+    }
+
+    let offset = ray_at(ray, root) - center;
+
+    if vec3f_len_squared(offset) > radius * radius {
+        return false;
+    }
+
+    (*t) = root;
+    (*normal) = disk_normal;
+    (*uv) = planar_uv(offset, disk_normal) / (2.0 * radius) + 0.5;
+
+    return true;
+}
+
+/// closest of the two side roots and the two caps, heights along the axis are scaled by its squared length
+fn hit_cylinder(cylinder: Primitive, ray: Ray, ray_limits: Interval, t: ptr<function, f32>, normal: ptr<function, vec3f>, uv: ptr<function, vec2f>) -> bool {
+    let base = cylinder.a.xyz;
+    let radius = cylinder.a.w;
+    let axis = cylinder.b.xyz - base;
+    let axis_direction = normalize(axis);
+
+    let origin_to_base = ray.origin - base;
+
+    let axis_len_squared = vec3f_len_squared(axis);
+    let axis_dot_direction = dot(axis, ray.direction);
+    let axis_dot_origin = dot(axis, origin_to_base);
+
+    var hit_anything = false;
+    var closest_so_far = ray_limits.max;
+
+    // side, distance to the axis equals the radius
+    let a = axis_len_squared * vec3f_len_squared(ray.direction) - axis_dot_direction * axis_dot_direction;
+    let half_b = axis_len_squared * dot(origin_to_base, ray.direction) - axis_dot_origin * axis_dot_direction;
+    let c = axis_len_squared * vec3f_len_squared(origin_to_base) - axis_dot_origin * axis_dot_origin - radius * radius * axis_len_squared;
+
+    let discriminant = (half_b * half_b) - (a * c);
+
+    // a is zero when the ray runs along the axis, only the caps can be hit
+    if a > 0.0 && discriminant >= 0.0 {
+        let sqrtd = sqrt(discriminant);
+
+        for (var i = 0u; i < 2u; i++) {
+            let root = (-half_b + select(-sqrtd, sqrtd, i == 1u)) / a;
+            let height = axis_dot_origin + root * axis_dot_direction;
+
+            if interval_surrounds(Interval(ray_limits.min, closest_so_far), root) && height >= 0.0 && height <= axis_len_squared {
+                hit_anything = true;
+                closest_so_far = root;
+
+                let radial = origin_to_base + root * ray.direction - axis * (height / axis_len_squared);
+                let around = planar_uv(radial, axis_direction);
+
+                (*normal) = radial / radius;
+                (*uv) = vec2f((atan2(around.y, around.x) + PI) / (2.0 * PI), height / axis_len_squared);
+            }
+        }
+    }
+
+    // caps, base at a height of 0 and top at 1
+    if axis_dot_direction != 0.0 {
+        for (var i = 0u; i < 2u; i++) {
+            let cap = f32(i);
+            let root = (cap * axis_len_squared - axis_dot_origin) / axis_dot_direction;
+            let offset = origin_to_base + root * ray.direction - cap * axis;
+
+            if interval_surrounds(Interval(ray_limits.min, closest_so_far), root) && vec3f_len_squared(offset) <= radius * radius {
+                hit_anything = true;
+                closest_so_far = root;
+
+                (*normal) = axis_direction * select(-1.0, 1.0, i == 1u);
+                (*uv) = planar_uv(offset, axis_direction) / (2.0 * radius) + 0.5;
+            }
+        }
+    }
+
+    (*t) = closest_so_far;
+
+    return hit_anything;
+}
+
+/// range is (offset, count) into the primitives buffer
+fn hit_primitives(ray: Ray, ray_limits: Interval, range: vec2u, hit: ptr<function, HitRecord>) -> bool {
+    var temp_hit = HitRecord();
+    var hit_anything = false;
+    var closest_so_far = ray_limits.max;
+
+    for (var i = range.x; i < range.x + range.y; i++) {
+        let primitive = primitives[i];
+
+        if hit_primitive(primitive, ray, Interval(ray_limits.min, closest_so_far), &temp_hit) {
+            hit_anything = true;
+
+            closest_so_far = temp_hit.t;
+
+            *hit = temp_hit;
+        }
+    }
+
+    return hit_anything;
+}
+// PRIMITIVE_END
+
+// TRIANGLE_START
+struct Vertex {
+    position: vec3f,
+    normal: vec3f,
+    uv: vec2f
+}
+
+struct Triangle {
+    vertices: vec4u,
+    mid: vec4u
+}
+
+/// Moller-Trumbore intersection, normal is interpolated from the vertex normals
+fn hit_triangle(triangle: Triangle, ray: Ray, ray_limits: Interval, hit: ptr<function, HitRecord>) -> bool {
+    let v0 = vertices[triangle.vertices.x];
+    let v1 = vertices[triangle.vertices.y];
+    let v2 = vertices[triangle.vertices.z];
+
+    let edge1 = v1.position - v0.position;
+    let edge2 = v2.position - v0.position;
+
+    let p = cross(ray.direction, edge2);
+    let det = dot(edge1, p);
+
+    // ray is parallel to the triangle plane or the triangle is degenerate
+    if abs(det) < 1e-8 {
+        return false;
+    }
+
+    let inv_det = 1.0 / det;
+    let s = ray.origin - v0.position;
+
+    let u = dot(s, p) * inv_det;
+    if u < 0.0 || u > 1.0 {
+        return false;
+    }
+
+    let q = cross(s, edge1);
+
+    let v = dot(ray.direction, q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return false;
+    }
+
+    let t = dot(edge2, q) * inv_det;
+    if !interval_surrounds(ray_limits, t) {
+        return false;
+    }
+
+    (*hit).t = t;
+    (*hit).point = ray_at(ray, t);
+    (*hit).uv = (1.0 - u - v) * v0.uv + u * v1.uv + v * v2.uv;
+
+    (*hit).mat_type = triangle.mid.x;
+    (*hit).mat_index = triangle.mid.y;
+    (*hit).light = triangle.mid.z;
+    (*hit).object = triangle.mid.w;
+
+    // face side comes from the winding, shading uses the interpolated normal
+    let geometric_normal = normalize(cross(edge1, edge2));
+    let shading_normal = normalize((1.0 - u - v) * v0.normal + u * v1.normal + v * v2.normal);
+
+    hit_set_face_normal(hit, ray, geometric_normal);
+    (*hit).normal = select(-shading_normal, shading_normal, (*hit).front_face);
 
     return true;
 }
 
-fn hit_spheres(ray: Ray, ray_limits: Interval, hit: ptr<function, HitRecord>) -> bool {
+/// range is (offset, count) into the triangles buffer
+fn hit_triangles(ray: Ray, ray_limits: Interval, range: vec2u, hit: ptr<function, HitRecord>) -> bool {
     var temp_hit = HitRecord();
     var hit_anything = false;
     var closest_so_far = ray_limits.max;
 
-    // arrayLength returns a u32, so we make i also u32 to make logical operation happy
-    for (var i = 0u; i < arrayLength(&spheres); i++) {
-        let sphere = spheres[i];
+    for (var i = range.x; i < range.x + range.y; i++) {
+        let triangle = triangles[i];
 
-        if hit_sphere(sphere, ray, Interval(ray_limits.min, closest_so_far), &temp_hit) {
+        if hit_triangle(triangle, ray, Interval(ray_limits.min, closest_so_far), &temp_hit) {
             hit_anything = true;
 
             closest_so_far = temp_hit.t;
@@ -351,113 +1191,449 @@ fn hit_spheres(ray: Ray, ray_limits: Interval, hit: ptr<function, HitRecord>) ->
 
     return hit_anything;
 }
-// SPHERE_END
+// TRIANGLE_END
+
+// BVH_START
+const BVH_STACK_SIZE = 64u;
+
+/// places a shape in the world, mid.x of 0 keeps the materials of the shape
+struct Instance {
+    world_to_object: mat4x4f,
+    mid: vec4u,
+    root: u32 // root node of the shape's BVH
+}
+
+struct BvhNode {
+    min: vec3f,
+    next: u32,
+    max: vec3f,
+    primitives: vec2u,
+    triangles: vec2u,
+    instances: vec2u
+}
+
+/// slab test, inv_direction can contain infinities for axis aligned rays
+fn hit_aabb(node: BvhNode, ray: Ray, inv_direction: vec3f, ray_limits: Interval) -> bool {
+    let t0 = (node.min - ray.origin) * inv_direction;
+    let t1 = (node.max - ray.origin) * inv_direction;
+
+    let t_near = min(t0, t1);
+    let t_far = max(t0, t1);
+
+    let t_enter = max(max(t_near.x, t_near.y), max(t_near.z, ray_limits.min));
+    let t_exit = min(min(t_far.x, t_far.y), min(t_far.z, ray_limits.max));
+
+    return t_enter <= t_exit;
+}
+// BVH_END
+
+// WORLD_START
+/// closest hit across all instances, walks the top level BVH and
+/// descends into the BVH of every instance the ray reaches
+fn hit_world(ray: Ray, ray_limits: Interval, hit: ptr<function, HitRecord>) -> bool {
+    var hit_anything = false;
+    var closest_so_far = ray_limits.max;
+
+    let inv_direction = 1.0 / ray.direction;
+
+    var stack: array<u32, BVH_STACK_SIZE>;
+    var stack_size = 1u;
+    stack[0] = 0u;
+
+    while stack_size > 0u {
+        stack_size--;
+
+        let node_index = stack[stack_size];
+        let node = bvh_nodes[node_index];
+
+        if !hit_aabb(node, ray, inv_direction, Interval(ray_limits.min, closest_so_far)) {
+            continue;
+        }
+
+        // interior node, first child is always the next node
+        if node.next != 0u {
+            if stack_size + 2u <= BVH_STACK_SIZE {
+                stack[stack_size] = node.next;
+                stack[stack_size + 1u] = node_index + 1u;
+                stack_size += 2u;
+            }
+
+            continue;
+        }
+
+        for (var i = node.instances.x; i < node.instances.x + node.instances.y; i++) {
+            if hit_instance(instances[i], ray, Interval(ray_limits.min, closest_so_far), hit) {
+                hit_anything = true;
+                closest_so_far = (*hit).t;
+            }
+        }
+    }
+
+    return hit_anything;
+}
+
+/// intersects the instance's shape in object space, the hit is moved back to world space
+fn hit_instance(instance: Instance, ray: Ray, ray_limits: Interval, hit: ptr<function, HitRecord>) -> bool {
+    // direction is transformed without normalizing so t is the same in both spaces
+    let object_ray = Ray(
+        (instance.world_to_object * vec4f(ray.origin, 1.0)).xyz,
+        (instance.world_to_object * vec4f(ray.direction, 0.0)).xyz
+    );
+
+    if !hit_shape(object_ray, ray_limits, instance.root, hit) {
+        return false;
+    }
+
+    (*hit).point = ray_at(ray, (*hit).t);
+    // transpose of the inverse keeps normals perpendicular under non uniform scale
+    (*hit).normal = normalize((transpose(instance.world_to_object) * vec4f((*hit).normal, 0.0)).xyz);
+
+    if instance.mid.x != 0u {
+        (*hit).mat_type = instance.mid.x;
+        (*hit).mat_index = instance.mid.y;
+        (*hit).object = instance.mid.w;
+    }
+
+    return true;
+}
+
+/// closest hit in a shape, walks its BVH from the root with a fixed size stack
+fn hit_shape(ray: Ray, ray_limits: Interval, root: u32, hit: ptr<function, HitRecord>) -> bool {
+    var hit_anything = false;
+    var closest_so_far = ray_limits.max;
+
+    let inv_direction = 1.0 / ray.direction;
+
+    var stack: array<u32, BVH_STACK_SIZE>;
+    var stack_size = 1u;
+    stack[0] = root;
+
+    while stack_size > 0u {
+        stack_size--;
+
+        let node_index = stack[stack_size];
+        let node = bvh_nodes[node_index];
+
+        if !hit_aabb(node, ray, inv_direction, Interval(ray_limits.min, closest_so_far)) {
+            continue;
+        }
+
+        if node.next != 0u {
+            if stack_size + 2u <= BVH_STACK_SIZE {
+                stack[stack_size] = node.next;
+                stack[stack_size + 1u] = node_index + 1u;
+                stack_size += 2u;
+            }
+
+            continue;
+        }
+
+        if hit_primitives(ray, Interval(ray_limits.min, closest_so_far), node.primitives, hit) {
+            hit_anything = true;
+            closest_so_far = (*hit).t;
+        }
+
+        if hit_triangles(ray, Interval(ray_limits.min, closest_so_far), node.triangles, hit) {
+            hit_anything = true;
+            closest_so_far = (*hit).t;
+        }
+    }
+
+    return hit_anything;
+}
+// WORLD_END
 
 // RENDERER_START
-fn render_ray(ray: Ray) -> vec3f {
-    var current_ray_origin = ray.origin;
-    var current_ray_direction = ray.direction;
+// layers of the aovs texture, see Aov::layer
+const AOV_LAYER_ALBEDO = 0u;
+const AOV_LAYER_NORMAL = 1u;
+const AOV_LAYER_DATA = 2u;
 
-    // start with background color
+fn background(ray: Ray) -> vec3f {
     let unit_dir = normalize(ray.direction);
-    let alpha = 0.5 * (unit_dir.y + 1.0);
+    var color: vec3f;
+
+    switch config.background.mode {
+        case BACKGROUND_SOLID: {
+            color = config.background.color_a;
+        }
+        case BACKGROUND_GRADIENT: {
+            let alpha = 0.5 * (unit_dir.y + 1.0);
+            color = (1.0 - alpha) * config.background.color_b + alpha * config.background.color_a;
+        }
+        case BACKGROUND_ENVIRONMENT_MAP: {
+            color = sample_environment_map(unit_dir);
+        }
+        default: {
+            color = ERR_COLOR;
+        }
+    }
+
+    return color * config.background.intensity;
+}
+
+/// equirectangular lookup with bilinear filtering, wraps horizontally
+fn sample_environment_map(direction: vec3f) -> vec3f {
+    let size = vec2i(textureDimensions(environment_map));
+
+    let u = fract(atan2(direction.z, direction.x) / (2.0 * PI) + 0.5 + config.background.rotation / 360.0);
+    let v = acos(clamp(direction.y, -1.0, 1.0)) / PI;
+
+    let texel = vec2f(u * f32(size.x), v * f32(size.y)) - 0.5;
+    let base = floor(texel);
+    let blend = texel - base;
+
+    let x0 = (i32(base.x) + size.x) % size.x;
+    let x1 = (x0 + 1) % size.x;
+    let y0 = clamp(i32(base.y), 0, size.y - 1);
+    let y1 = clamp(i32(base.y) + 1, 0, size.y - 1);
+
+    let top = mix(textureLoad(environment_map, vec2i(x0, y0), 0), textureLoad(environment_map, vec2i(x1, y0), 0), blend.x);
+    let bottom = mix(textureLoad(environment_map, vec2i(x0, y1), 0), textureLoad(environment_map, vec2i(x1, y1), 0), blend.x);
+
+    return mix(top, bottom, blend.y).rgb;
+}
+
+fn render_ray(ray: Ray) -> vec3f {
+    var current_ray = ray;
 
-    var accumulated_color = (1.0 - alpha) * vec3f(1.0) + alpha * vec3f(0.3, 0.6, 1.0);
+    // light gathered along the path and how much of it survives till the camera
+    var radiance = vec3f(0.0);
+    var throughput = vec3f(1.0);
 
-    var bounce = 0u;
+    // pdf of the bounce that picked current_ray, 0 when that bounce didn't sample lights
+    var bsdf_pdf = 0.0;
 
-    // try world hits
-    for (bounce = 0u; bounce < config.image.bounces; bounce++) {
+    // diffuse, glossy and transmission scatters so far and how many each is allowed
+    var bounce_counts = vec3u(0u);
+    let bounce_limits = vec3u(config.image.diffuse_bounces, config.image.glossy_bounces, config.image.transmission_bounces);
+
+    // paths that run out of bounces keep what they gathered so far
+    for (var bounce = 0u; bounce < config.image.bounces; bounce++) {
         var hit = HitRecord();
-        let ray = Ray(current_ray_origin, current_ray_direction);
 
-        if hit_spheres(ray, Interval(0.001, INF_F32), &hit) {
-            var scatter_ray = Ray();
-            var attenuation = vec3f();
+        if !hit_world(current_ray, Interval(0.001, INF_F32), &hit) {
+            radiance += throughput * background(current_ray);
+            break;
+        }
 
-            if scatter(ray, hit, &attenuation, &scatter_ray) {
-                current_ray_origin = scatter_ray.origin;
-                current_ray_direction = scatter_ray.direction;
+        radiance += throughput * emitted(hit) * emission_weight(current_ray, hit, bsdf_pdf);
 
-                accumulated_color *= attenuation;
-            } else { // else should never happen
-                break;
-            }
-        } else {
+        let lit = samples_lights(hit);
+
+        if lit {
+            radiance += throughput * sample_lights(current_ray, hit);
+        }
+
+        var scatter_ray = Ray();
+        var attenuation = vec3f();
+
+        if !scatter(current_ray, hit, &attenuation, &scatter_ray) {
             break;
         }
-    }
 
-    // number of bounce visualization
-    // accumulated_color = vec3f(1.0, 1.0, 1.0) / f32(bounce + 1);
+        // diffuse, metal and dielectric are types 1 to 3, emissive surfaces never scatter
+        let kind = hit.mat_type - 1u;
+        bounce_counts[kind] += 1u;
 
-    // max bounce condition
-    if bounce >= config.image.bounces {
-        accumulated_color = vec3f(0.0, 0.0, 0.0);
+        if bounce_counts[kind] > bounce_limits[kind] {
+            break;
+        }
+
+        bsdf_pdf = 0.0;
+
+        if lit {
+            bsdf_pdf = scatter_pdf(current_ray, hit, normalize(scatter_ray.direction));
+        }
+
+        throughput *= attenuation;
+
+        // dim paths are ended at random, the survivors are brightened to keep the estimate unbiased
+        if bounce + 1u >= config.image.roulette_depth {
+            let survival = min(max(throughput.x, max(throughput.y, throughput.z)), 1.0);
+
+            if random_float() >= survival {
+                break;
+            }
+
+            throughput /= survival;
+        }
+
+        current_ray = scatter_ray;
     }
 
-    return accumulated_color;
+    return radiance;
 }
 
 fn render(pixel_position: vec2i) -> vec4f {
-    let pixel_center = config.pixel_zero_loc 
-        + (f32(pixel_position.x) * config.viewport.delta_u) 
-        + (f32(pixel_position.y) * config.viewport.delta_v);
+    let jitter = vec2f(-0.5 + random_float(), -0.5 + random_float());
+    // drawn even without depth of field so every projection takes the same random numbers
+    let lens = random_in_unit_disk();
 
-    let pixel_sample = pixel_center + sample_square();
-    
-    let ray_origin = select(dof_disk_sample(), config.camera.center, config.camera.dof_angle <= 0.0);
-    let ray_direction = pixel_sample - ray_origin;
+    let ray = camera_ray(pixel_position, jitter, select(lens.xy, vec2f(0.0), config.camera.dof_angle <= 0.0));
 
-    let ray = Ray(ray_origin, ray_direction);
+    // outside the fisheye's circle
+    if all(ray.direction == vec3f(0.0)) {
+        return vec4f(0.0, 0.0, 0.0, 1.0);
+    }
 
     let pixel_color = render_ray(ray);
 
     return vec4f(pixel_color, 1.0);
 }
 
-fn sample_square() -> vec3f {
-    return ((-0.5 + random_float()) * config.viewport.delta_u) + ((-0.5 + random_float()) * config.viewport.delta_v);
+/// first hit of the pixel's center ray, traced without depth of field and without drawing random numbers
+fn render_aovs(pixel_position: vec2i) {
+    let ray = camera_ray(pixel_position, vec2f(0.0), vec2f(0.0));
+
+    // outside the fisheye's circle
+    if all(ray.direction == vec3f(0.0)) {
+        textureStore(aovs, pixel_position, AOV_LAYER_ALBEDO, vec4f(0.0, 0.0, 0.0, 1.0));
+        textureStore(aovs, pixel_position, AOV_LAYER_NORMAL, vec4f(0.0, 0.0, 0.0, 1.0));
+        textureStore(aovs, pixel_position, AOV_LAYER_DATA, vec4f(0.0, -1.0, -1.0, 1.0));
+        return;
+    }
+
+    var hit = HitRecord();
+
+    if !hit_world(ray, Interval(0.001, INF_F32), &hit) {
+        textureStore(aovs, pixel_position, AOV_LAYER_ALBEDO, vec4f(clamp(background(ray), vec3f(0.0), vec3f(1.0)), 1.0));
+        textureStore(aovs, pixel_position, AOV_LAYER_NORMAL, vec4f(0.0, 0.0, 0.0, 1.0));
+        textureStore(aovs, pixel_position, AOV_LAYER_DATA, vec4f(0.0, -1.0, -1.0, 1.0));
+        return;
+    }
+
+    // dielectrics are white and emissive surfaces their emitted color
+    let material = materials[hit.mat_index];
+    let albedo = material_color(material, hit);
+    let depth = hit.t * length(ray.direction);
+
+    textureStore(aovs, pixel_position, AOV_LAYER_ALBEDO, vec4f(albedo, 1.0));
+    textureStore(aovs, pixel_position, AOV_LAYER_NORMAL, vec4f(hit.normal, 1.0));
+    textureStore(aovs, pixel_position, AOV_LAYER_DATA, vec4f(depth, f32(hit.object) - 1.0, f32(material.id), 1.0));
 }
 
-fn dof_disk_sample() -> vec3f {
-    let p = random_in_unit_disk();
-    return config.camera.center + (p.x * config.camera.dof_disk_u) + (p.y * config.camera.dof_disk_v);
+/// ray through the pixel offset by jitter pixels, from the point lens of the unit disk on the lens,
+/// a zero direction where the projection doesn't cover the pixel
+fn camera_ray(pixel_position: vec2i, jitter: vec2f, lens: vec2f) -> Ray {
+    let camera = config.camera;
+
+    // pixels from the top left corner of the image
+    let pixel = vec2f(pixel_position) + 0.5 + jitter;
+    let size = vec2f(f32(config.image.width), f32(config.image.height));
+
+    switch camera.projection {
+        case PROJECTION_ORTHOGRAPHIC: {
+            let pixel_sample = config.pixel_zero_loc
+                + (f32(pixel_position.x) * config.viewport.delta_u)
+                + (f32(pixel_position.y) * config.viewport.delta_v)
+                + ((jitter.x * config.viewport.delta_u) + (jitter.y * config.viewport.delta_v));
+
+            // parallel rays focused on the plane at the focus distance
+            let origin = pixel_sample + (lens.x * camera.dof_disk_u) + (lens.y * camera.dof_disk_v);
+
+            return Ray(origin, pixel_sample - (camera.dof_distance * camera.w) - origin);
+        }
+        case PROJECTION_FISHEYE: {
+            // equidistant, the angle from the view direction grows linearly with the distance from the center
+            let offset = (pixel - (0.5 * size)) / (0.5 * size.y);
+            let theta = length(offset) * camera.fov / 2.0;
+
+            if theta > PI {
+                return Ray(camera.center, vec3f(0.0));
+            }
+
+            let phi = atan2(offset.y, offset.x);
+            let direction = (cos(theta) * -camera.w) + (sin(theta) * ((cos(phi) * camera.u) - (sin(phi) * camera.v)));
+
+            return Ray(camera.center, direction);
+        }
+        case PROJECTION_EQUIRECTANGULAR: {
+            let uv = pixel / size;
+            let longitude = (uv.x - 0.5) * 2.0 * PI;
+            let latitude = (0.5 - uv.y) * PI;
+
+            let direction = (cos(latitude) * ((sin(longitude) * camera.u) - (cos(longitude) * camera.w))) + (sin(latitude) * camera.v);
+
+            return Ray(camera.center, direction);
+        }
+        default: {
+            let pixel_sample = config.pixel_zero_loc
+                + (f32(pixel_position.x) * config.viewport.delta_u)
+                + (f32(pixel_position.y) * config.viewport.delta_v)
+                + ((jitter.x * config.viewport.delta_u) + (jitter.y * config.viewport.delta_v));
+
+            let origin = camera.center + (lens.x * camera.dof_disk_u) + (lens.y * camera.dof_disk_v);
+
+            return Ray(origin, pixel_sample - origin);
+        }
+    }
 }
 // RENDERER_END
 
 // BINDINGS_START
 // System Bindings
-@group(0) @binding(0) var result: texture_storage_2d<rgba8unorm, write>; // output image
+@group(0) @binding(0) var result: texture_storage_2d<rgba32float, write>; // linear output image, tone mapped on readback
+@group(0) @binding(1) var accumulation_in: texture_2d<f32>; // sum of samples from previous passes
+@group(0) @binding(2) var accumulation_out: texture_storage_2d<rgba32float, write>; // sum including this pass
+@group(0) @binding(3) var aovs: texture_storage_2d_array<rgba32float, write>; // albedo, normal then depth, object and material id
 
 // User Bindings
 // - Config bindings
 @group(1) @binding(0) var<uniform> config: Config; // render config
 // - Scene bindings
-@group(1) @binding(1) var<storage, read> spheres: array<Sphere>; // move to different group
+@group(1) @binding(1) var<storage, read> primitives: array<Primitive>; // move to different group
 // - Material Bindings
-@group(1) @binding(2) var<storage, read> diffuse_mats: array<DiffuseMat>;
-@group(1) @binding(3) var<storage, read> metal_mats: array<MetalMat>;
-@group(1) @binding(4) var<storage, read> dielectric_mats: array<DielectricMat>;
+@group(1) @binding(2) var<storage, read> materials: array<Material>;
+// - Mesh Bindings
+@group(1) @binding(3) var<storage, read> vertices: array<Vertex>;
+@group(1) @binding(4) var<storage, read> triangles: array<Triangle>;
+// - Acceleration Bindings
+@group(1) @binding(5) var<storage, read> bvh_nodes: array<BvhNode>; // top level nodes then the nodes of every shape
+@group(1) @binding(6) var<storage, read> instances: array<Instance>;
+// - Background Bindings
+@group(1) @binding(7) var environment_map: texture_2d<f32>;
+// - Texture Bindings
+@group(1) @binding(8) var texture_atlas: texture_2d<f32>; // image textures of every material
+// - Light Bindings
+@group(1) @binding(9) var<storage, read> lights: array<Light>; // explicit lights then the emissive primitives
 
 // Execution Context Bindings
 @group(2) @binding(0) var<uniform> execution_context: ExecutionContext; // current execution context
 // BINDINGS_END
 
-@compute @workgroup_size(1, 1, 1)
+// pixels per side of a workgroup, see kernel.rs
+const WORKGROUP_SIZE = 8u;
+
+@compute @workgroup_size(WORKGROUP_SIZE, WORKGROUP_SIZE, 1)
 fn main(@builtin(global_invocation_id) id: vec3u) {
-    random_init(id);
+    if id.x >= execution_context.tile_size.x || id.y >= execution_context.tile_size.y {
+        return;
+    }
 
     let pixel_position = vec2i(i32(execution_context.tile_position.x + id.x), i32(execution_context.tile_position.y + id.y));
 
     var pixel_color = vec4f();
 
-    for (var i = 0u; i < config.image.samples; i++) {
+    for (var i = 0u; i < execution_context.sample_count; i++) {
+        // reseeding per sample keeps the image independent of the pass layout
+        random_init(vec2u(pixel_position), execution_context.sample_offset + i);
+
         pixel_color += render(pixel_position);
     }
 
-    pixel_color /= f32(config.image.samples);
+    if execution_context.sample_offset > 0u {
+        pixel_color += textureLoad(accumulation_in, pixel_position, 0);
+    }
+
+    textureStore(accumulation_out, pixel_position, pixel_color);
+
+    pixel_color /= f32(execution_context.sample_offset + execution_context.sample_count);
 
-    // sqrt applies gamma 2 transformation
-    textureStore(result, pixel_position, sqrt(pixel_color)); // final output
-}
\ No newline at end of file
+    textureStore(result, pixel_position, pixel_color); // final output
+
+    // the center ray is the same every pass
+    if execution_context.sample_offset == 0u && config.image.aovs != 0u {
+        render_aovs(pixel_position);
+    }
+}
@@ -1,5 +1,6 @@
 use rand::{rngs::ThreadRng, Rng};
 
+#[allow(dead_code)]
 pub fn random_color(rng: &mut ThreadRng) -> glam::Vec3 {
     let r: f32 = rng.gen();
     let g: f32 = rng.gen();
@@ -8,6 +9,7 @@ pub fn random_color(rng: &mut ThreadRng) -> glam::Vec3 {
     return glam::vec3(r, g, b);
 }
 
+#[allow(dead_code)]
 pub fn random_color_range(rng: &mut ThreadRng, min: f32, max: f32) -> glam::Vec3 {
     let r: f32 = rng.gen_range(min..max);
     let g: f32 = rng.gen_range(min..max);
@@ -18,7 +20,7 @@ pub fn random_color_range(rng: &mut ThreadRng, min: f32, max: f32) -> glam::Vec3
 
 pub fn hex_to_rgb(hex: &str) -> Option<glam::Vec3> {
     // Check if the hex string starts with '#' and remove it
-    let hex = if hex.starts_with('#') { &hex[1..] } else { hex };
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
 
     // Ensure that the hex string is of valid length (either 3 or 6)
     if hex.len() != 3 && hex.len() != 6 {
@@ -1,4 +1,5 @@
 pub struct Metrics {
+    adapter: Option<String>,
     start: std::time::Instant,
     device_acquisition: std::time::Duration,
     scene_upload: std::time::Duration,
@@ -11,6 +12,7 @@ pub struct Metrics {
 impl Metrics {
     pub fn new() -> Self {
         return Metrics {
+            adapter: None,
             start: std::time::Instant::now(),
             device_acquisition: std::time::Duration::ZERO,
             scene_upload: std::time::Duration::ZERO,
@@ -21,6 +23,10 @@ impl Metrics {
         };
     }
 
+    pub fn set_adapter(&mut self, adapter: String) {
+        self.adapter = Some(adapter);
+    }
+
     pub fn start(&mut self) {
         self.start = std::time::Instant::now();
     }
@@ -51,6 +57,9 @@ impl Metrics {
 
     pub fn log(&self) {
         log::info!("===== WebRay Metrics =====");
+        if let Some(adapter) = &self.adapter {
+            log::info!("Adapter: {}", adapter);
+        }
         log::info!(
             "Device Acquisition: {} secs",
             self.device_acquisition.as_secs_f64()
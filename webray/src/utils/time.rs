@@ -0,0 +1,13 @@
+/// Milliseconds since an arbitrary point fixed for the process,
+/// std::time::Instant panics on wasm so the browser clock is used there instead
+pub fn now_ms() -> f64 {
+    cfg_if::cfg_if! {
+        if #[cfg(target_arch = "wasm32")] {
+            return js_sys::Date::now();
+        } else {
+            static EPOCH: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+
+            return EPOCH.get_or_init(std::time::Instant::now).elapsed().as_secs_f64() * 1000.0;
+        }
+    }
+}